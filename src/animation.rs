@@ -0,0 +1,182 @@
+use crate::{camera::Camera, graphics::canvas::Canvas, math::Iso, sprite::AnimatedSprite};
+
+/// Playback of the clips of an animated sprite.
+///
+/// One-shot clips can't be interrupted by [`Animation::play`], when finished they continue with the last clip
+/// requested during them, or with their next clip when nothing was requested.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    /// Name of the playing clip.
+    clip: String,
+    /// Seconds the clip is playing.
+    time: f64,
+    /// Frame shown during the last update, used to trigger the events of new frames.
+    frame: Option<usize>,
+    /// Clip requested while a one-shot clip was playing, played when it's finished.
+    queued: Option<String>,
+}
+
+impl Animation {
+    /// Start playing a clip.
+    pub fn new(clip: &str) -> Self {
+        Self {
+            clip: clip.to_string(),
+            time: 0.0,
+            frame: None,
+            queued: None,
+        }
+    }
+
+    /// Name of the playing clip.
+    pub fn clip(&self) -> &str {
+        &self.clip
+    }
+
+    /// Switch to a clip unless it's already playing or an unfinished one-shot clip is playing.
+    pub fn play(&mut self, clip: &str, sprite: &AnimatedSprite) {
+        if self.clip == clip {
+            self.queued = None;
+        } else if sprite.is_one_shot(&self.clip) && !sprite.is_finished(&self.clip, self.time) {
+            self.queued = Some(clip.to_string());
+        } else {
+            self.trigger(clip);
+        }
+    }
+
+    /// Play a clip from the start, interrupting everything.
+    pub fn trigger(&mut self, clip: &str) {
+        self.clip = clip.to_string();
+        self.time = 0.0;
+        self.frame = None;
+        self.queued = None;
+    }
+
+    /// Advance the time.
+    ///
+    /// Returns the event of the frame when a new frame is shown.
+    pub fn update<'a>(&mut self, dt: f64, sprite: &'a AnimatedSprite) -> Option<&'a str> {
+        self.time += dt;
+
+        if sprite.is_finished(&self.clip, self.time) {
+            // The clip the game asked for wins over the next clip
+            let next = self
+                .queued
+                .take()
+                .or_else(|| sprite.next_clip(&self.clip).map(str::to_string));
+            if let Some(next) = next {
+                self.trigger(&next);
+            }
+        }
+
+        let frame = sprite.frame_index(&self.clip, self.time);
+        if self.frame == Some(frame) {
+            return None;
+        }
+        self.frame = Some(frame);
+
+        sprite.event(frame)
+    }
+
+    /// Draw the current frame.
+    pub fn render(&self, iso: Iso, canvas: &mut Canvas, camera: &Camera, sprite: &AnimatedSprite) {
+        sprite.render(iso, canvas, camera, &self.clip, self.time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Animation;
+    use crate::{
+        aseprite::Aseprite,
+        sprite::{AnimatedSprite, AnimatedSpriteMetadata},
+    };
+
+    /// Sprite of the player with its real clips.
+    fn sprite() -> AnimatedSprite {
+        let aseprite = Aseprite::from_bytes(include_bytes!("../assets/dino.aseprite")).unwrap();
+        let metadata =
+            toml::from_str::<AnimatedSpriteMetadata>(include_str!("../assets/dino.toml")).unwrap();
+
+        AnimatedSprite::from_aseprite(&aseprite, &metadata)
+    }
+
+    /// Seconds until a one-shot clip is finished.
+    fn duration(clip: &str, sprite: &AnimatedSprite) -> f64 {
+        let mut time = 0.0;
+        while !sprite.is_finished(clip, time) {
+            time += 0.01;
+        }
+
+        time
+    }
+
+    /// Playing must not interrupt an unfinished one-shot clip.
+    #[test]
+    fn test_play_one_shot() {
+        let sprite = sprite();
+        let mut animation = Animation::new("fly");
+
+        animation.play("land", &sprite);
+        assert_eq!(animation.clip(), "land");
+
+        animation.play("tumble", &sprite);
+        assert_eq!(animation.clip(), "land");
+
+        animation.update(duration("land", &sprite) / 2.0, &sprite);
+        animation.play("tumble", &sprite);
+        assert_eq!(animation.clip(), "land");
+
+        // Triggering always interrupts
+        animation.trigger("dead");
+        assert_eq!(animation.clip(), "dead");
+    }
+
+    /// A finished one-shot clip must continue with its next clip, unless another clip was requested.
+    #[test]
+    fn test_next_clip() {
+        let sprite = sprite();
+
+        let mut animation = Animation::new("boost");
+        animation.update(duration("boost", &sprite), &sprite);
+        assert_eq!(animation.clip(), "fly");
+
+        // Landing on the ground shouldn't show a single frame of flying before running
+        let mut animation = Animation::new("land");
+        animation.play("run", &sprite);
+        animation.update(duration("land", &sprite), &sprite);
+        assert_eq!(animation.clip(), "run");
+
+        // Without a next clip the last frame is held
+        let mut animation = Animation::new("dead");
+        animation.update(duration("dead", &sprite) * 2.0, &sprite);
+        assert_eq!(animation.clip(), "dead");
+    }
+
+    /// Events must be returned once when their frame is first shown.
+    #[test]
+    fn test_events() {
+        let sprite = sprite();
+        let mut animation = Animation::new("run");
+
+        let mut time = 0.0;
+        let mut events = 0;
+        let mut frames = 0;
+        let mut last_frame = None;
+        while time < 1.0 {
+            time += 0.01;
+            if let Some(event) = animation.update(0.01, &sprite) {
+                assert_eq!(event, "step");
+                events += 1;
+            }
+
+            let frame = sprite.frame_index("run", time);
+            if last_frame != Some(frame) && sprite.event(frame).is_some() {
+                frames += 1;
+            }
+            last_frame = Some(frame);
+        }
+
+        assert!(events > 0);
+        assert_eq!(events, frames);
+    }
+}
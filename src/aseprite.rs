@@ -0,0 +1,611 @@
+use std::borrow::Cow;
+
+use assets_manager::{loader::Loader, Asset, BoxedError};
+use miette::{miette, Result};
+use vek::Extent2;
+
+/// Bytes identifying an Aseprite file.
+const FILE_MAGIC: u16 = 0xA5E0;
+/// Bytes identifying a frame in an Aseprite file.
+const FRAME_MAGIC: u16 = 0xF1FA;
+/// Size of the file header in bytes.
+const HEADER_SIZE: usize = 128;
+
+/// Chunk types that are read, all others are ignored.
+const CHUNK_OLD_PALETTE: u16 = 0x0004;
+const CHUNK_LAYER: u16 = 0x2004;
+const CHUNK_CEL: u16 = 0x2005;
+const CHUNK_TAGS: u16 = 0x2018;
+const CHUNK_PALETTE: u16 = 0x2019;
+
+/// Image with frames read from an `.aseprite` file, all visible layers are flattened into every frame.
+///
+/// Blend modes of layers are ignored, everything is drawn on top of each other with alpha blending.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aseprite {
+    /// Width and height of every frame.
+    pub size: Extent2<usize>,
+    /// All frames in order.
+    pub frames: Vec<Frame>,
+    /// Named ranges of frames.
+    pub tags: Vec<Tag>,
+}
+
+impl Aseprite {
+    /// Decode from the binary format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut header = Reader::new(bytes);
+        header.skip(4)?;
+        if header.u16()? != FILE_MAGIC {
+            return Err(miette!("Not an Aseprite file"));
+        }
+        let frame_count = header.u16()? as usize;
+        if frame_count == 0 {
+            return Err(miette!("Aseprite file has no frames"));
+        }
+        let size = Extent2::new(header.u16()? as usize, header.u16()? as usize);
+        let color_depth = ColorDepth::from_bits(header.u16()?)?;
+        let flags = header.u32()?;
+        header.skip(2 + 4 + 4)?;
+        let transparent_index = header.u8()?;
+
+        // Layer opacity is only stored in newer files
+        let layer_opacity_valid = flags & 1 != 0;
+
+        let mut layers = Vec::new();
+        let mut palette = vec![0; 256];
+        let mut tags = Vec::new();
+        let mut frames = Vec::new();
+        // Cels of every frame, needed for cels linking to an earlier frame
+        let mut frame_cels: Vec<Vec<Cel>> = Vec::new();
+
+        let mut reader = Reader::new(bytes.get(HEADER_SIZE..).unwrap_or_default());
+        for _ in 0..frame_count {
+            let frame_size = reader.u32()? as usize;
+            let mut frame = Reader::new(reader.bytes(frame_size.saturating_sub(4))?);
+            if frame.u16()? != FRAME_MAGIC {
+                return Err(miette!("Aseprite frame is corrupt"));
+            }
+            let old_chunk_count = frame.u16()? as usize;
+            let duration = frame.u16()? as f64 / 1000.0;
+            frame.skip(2)?;
+            let chunk_count = match frame.u32()? as usize {
+                0 => old_chunk_count,
+                count => count,
+            };
+
+            let mut cels = Vec::new();
+            for _ in 0..chunk_count {
+                let chunk_size = frame.u32()? as usize;
+                let mut chunk = Reader::new(frame.bytes(chunk_size.saturating_sub(4))?);
+                match chunk.u16()? {
+                    CHUNK_OLD_PALETTE => read_old_palette(&mut chunk, &mut palette)?,
+                    CHUNK_PALETTE => read_palette(&mut chunk, &mut palette)?,
+                    CHUNK_LAYER => layers.push(Layer::read(&mut chunk, layer_opacity_valid)?),
+                    CHUNK_TAGS => tags = Tag::read_all(&mut chunk)?,
+                    CHUNK_CEL => {
+                        let cel = Cel::read(&mut chunk, color_depth, &frame_cels)?;
+                        cels.push(cel);
+                    }
+                    _ => (),
+                }
+            }
+
+            frame_cels.push(cels);
+            frames.push(duration);
+        }
+
+        let visible = Layer::visibility(&layers);
+        let frames = frames
+            .into_iter()
+            .zip(frame_cels.iter())
+            .map(|(duration, cels)| Frame {
+                pixels: flatten(
+                    size,
+                    cels,
+                    &layers,
+                    &visible,
+                    color_depth,
+                    &palette,
+                    transparent_index,
+                ),
+                duration,
+            })
+            .collect();
+
+        Ok(Self { size, frames, tags })
+    }
+
+    /// Frames of a tag, nothing when there's no tag with the name.
+    pub fn tag(&self, name: &str) -> Option<&Tag> {
+        self.tags.iter().find(|tag| tag.name == name)
+    }
+}
+
+impl Asset for Aseprite {
+    const EXTENSION: &'static str = "aseprite";
+
+    type Loader = AsepriteLoader;
+}
+
+/// Aseprite asset loader.
+pub struct AsepriteLoader;
+
+impl Loader<Aseprite> for AsepriteLoader {
+    fn load(content: Cow<[u8]>, _ext: &str) -> Result<Aseprite, BoxedError> {
+        Aseprite::from_bytes(&content).map_err(|err| err.to_string().into())
+    }
+}
+
+/// Single flattened image of an animation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// Colors row by row as `0xAARRGGBB`.
+    pub pixels: Vec<u32>,
+    /// Seconds the frame is shown.
+    pub duration: f64,
+}
+
+/// Named range of frames.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
+    /// Name set in Aseprite.
+    pub name: String,
+    /// First frame.
+    pub from: usize,
+    /// Last frame, inclusive.
+    pub to: usize,
+    /// Order the frames are played in.
+    pub direction: Direction,
+    /// How many times the frames are played, `0` is forever.
+    pub repeat: u16,
+}
+
+impl Tag {
+    /// Read all tags from a tags chunk.
+    fn read_all(chunk: &mut Reader) -> Result<Vec<Self>> {
+        let count = chunk.u16()?;
+        chunk.skip(8)?;
+
+        (0..count)
+            .map(|_| {
+                let from = chunk.u16()? as usize;
+                let to = chunk.u16()? as usize;
+                let direction = match chunk.u8()? {
+                    0 => Direction::Forward,
+                    1 => Direction::Reverse,
+                    2 => Direction::PingPong,
+                    3 => Direction::PingPongReverse,
+                    other => return Err(miette!("Unknown Aseprite tag direction {other}")),
+                };
+                let repeat = chunk.u16()?;
+                // Reserved bytes and deprecated color
+                chunk.skip(6 + 3 + 1)?;
+                let name = chunk.string()?;
+
+                Ok(Self {
+                    name,
+                    from,
+                    to,
+                    direction,
+                    repeat,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Order the frames of a tag are played in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Reverse,
+    /// Forward and then back again.
+    PingPong,
+    /// Back and then forward again.
+    PingPongReverse,
+}
+
+/// How the colors of pixels are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorDepth {
+    /// Red, green, blue and alpha bytes.
+    Rgba,
+    /// Value and alpha bytes.
+    Grayscale,
+    /// Index into the palette.
+    Indexed,
+}
+
+impl ColorDepth {
+    /// Parse from the bits per pixel in the header.
+    fn from_bits(bits: u16) -> Result<Self> {
+        match bits {
+            32 => Ok(Self::Rgba),
+            16 => Ok(Self::Grayscale),
+            8 => Ok(Self::Indexed),
+            other => Err(miette!("Unsupported Aseprite color depth {other}")),
+        }
+    }
+
+    /// Bytes of a single pixel.
+    fn bytes(&self) -> usize {
+        match self {
+            Self::Rgba => 4,
+            Self::Grayscale => 2,
+            Self::Indexed => 1,
+        }
+    }
+}
+
+/// Layer or group of layers.
+#[derive(Debug)]
+struct Layer {
+    /// Whether the eye icon is enabled.
+    visible: bool,
+    /// Whether it's a group of layers, those have no pixels.
+    group: bool,
+    /// How deep it's nested inside groups.
+    child_level: u16,
+    /// Opacity from `0` to `255`.
+    opacity: u8,
+}
+
+impl Layer {
+    /// Read from a layer chunk.
+    fn read(chunk: &mut Reader, opacity_valid: bool) -> Result<Self> {
+        let flags = chunk.u16()?;
+        let layer_type = chunk.u16()?;
+        let child_level = chunk.u16()?;
+        chunk.skip(2 + 2 + 2)?;
+        let opacity = chunk.u8()?;
+
+        // Reference layers are only used while drawing
+        let reference = flags & 64 != 0;
+
+        Ok(Self {
+            visible: flags & 1 != 0 && !reference,
+            group: layer_type == 1,
+            child_level,
+            opacity: if opacity_valid { opacity } else { 255 },
+        })
+    }
+
+    /// Whether every layer is visible, layers inside hidden groups are also hidden.
+    fn visibility(layers: &[Self]) -> Vec<bool> {
+        // Visibility of every group the current layer is nested in
+        let mut parents: Vec<bool> = Vec::new();
+
+        layers
+            .iter()
+            .map(|layer| {
+                parents.truncate(layer.child_level as usize);
+                let visible = layer.visible && parents.iter().all(|parent| *parent);
+                if layer.group {
+                    parents.push(visible);
+                }
+
+                visible && !layer.group
+            })
+            .collect()
+    }
+}
+
+/// Pixels of a layer in a single frame.
+#[derive(Debug, Clone)]
+struct Cel {
+    /// Index of the layer.
+    layer: usize,
+    /// Position of the top left corner in the frame.
+    x: i32,
+    y: i32,
+    /// Opacity from `0` to `255`.
+    opacity: u8,
+    /// Offset to the layer index used for the drawing order.
+    z_index: i16,
+    /// Width and height of the pixels.
+    size: Extent2<usize>,
+    /// Raw pixel bytes in the color depth of the file.
+    pixels: Vec<u8>,
+}
+
+impl Cel {
+    /// Read from a cel chunk.
+    fn read(chunk: &mut Reader, color_depth: ColorDepth, frames: &[Vec<Cel>]) -> Result<Self> {
+        let layer = chunk.u16()? as usize;
+        let x = chunk.i16()? as i32;
+        let y = chunk.i16()? as i32;
+        let opacity = chunk.u8()?;
+        let cel_type = chunk.u16()?;
+        let z_index = chunk.i16()?;
+        chunk.skip(5)?;
+
+        let (size, pixels) = match cel_type {
+            // Raw pixels
+            0 => {
+                let size = Extent2::new(chunk.u16()? as usize, chunk.u16()? as usize);
+                let pixels = chunk.bytes(size.product() * color_depth.bytes())?.to_vec();
+
+                (size, pixels)
+            }
+            // Same pixels as the cel of the same layer in an earlier frame
+            1 => {
+                let frame = chunk.u16()? as usize;
+                let linked = frames
+                    .get(frame)
+                    .and_then(|cels| cels.iter().find(|cel| cel.layer == layer))
+                    .ok_or_else(|| miette!("Aseprite cel links to missing frame {frame}"))?;
+
+                (linked.size, linked.pixels.clone())
+            }
+            // Zlib compressed pixels
+            2 => {
+                let size = Extent2::new(chunk.u16()? as usize, chunk.u16()? as usize);
+                let pixels = miniz_oxide::inflate::decompress_to_vec_zlib(chunk.rest())
+                    .map_err(|err| miette!("Error decompressing Aseprite cel: {err:?}"))?;
+                if pixels.len() < size.product() * color_depth.bytes() {
+                    return Err(miette!("Aseprite cel is truncated"));
+                }
+
+                (size, pixels)
+            }
+            other => return Err(miette!("Unsupported Aseprite cel type {other}")),
+        };
+
+        Ok(Self {
+            layer,
+            x,
+            y,
+            opacity,
+            z_index,
+            size,
+            pixels,
+        })
+    }
+
+    /// Color of a pixel as red, green, blue and alpha.
+    fn rgba(
+        &self,
+        index: usize,
+        color_depth: ColorDepth,
+        palette: &[u32],
+        transparent_index: u8,
+    ) -> [u8; 4] {
+        let bytes = &self.pixels[index * color_depth.bytes()..];
+        match color_depth {
+            ColorDepth::Rgba => [bytes[0], bytes[1], bytes[2], bytes[3]],
+            ColorDepth::Grayscale => [bytes[0], bytes[0], bytes[0], bytes[1]],
+            ColorDepth::Indexed if bytes[0] == transparent_index => [0; 4],
+            ColorDepth::Indexed => palette[bytes[0] as usize].to_be_bytes(),
+        }
+    }
+}
+
+/// Draw the cels of all visible layers on top of each other.
+fn flatten(
+    size: Extent2<usize>,
+    cels: &[Cel],
+    layers: &[Layer],
+    visible: &[bool],
+    color_depth: ColorDepth,
+    palette: &[u32],
+    transparent_index: u8,
+) -> Vec<u32> {
+    let mut cels = cels
+        .iter()
+        .filter(|cel| visible.get(cel.layer).copied().unwrap_or(false))
+        .collect::<Vec<_>>();
+    cels.sort_by_key(|cel| (cel.layer as i64 + cel.z_index as i64, cel.z_index));
+
+    let mut pixels = vec![[0u8; 4]; size.product()];
+    for cel in cels {
+        let opacity = cel.opacity as u32 * layers[cel.layer].opacity as u32 / 255;
+
+        for y in 0..cel.size.h {
+            let frame_y = cel.y + y as i32;
+            if frame_y < 0 || frame_y >= size.h as i32 {
+                continue;
+            }
+
+            for x in 0..cel.size.w {
+                let frame_x = cel.x + x as i32;
+                if frame_x < 0 || frame_x >= size.w as i32 {
+                    continue;
+                }
+
+                let [r, g, b, a] =
+                    cel.rgba(x + y * cel.size.w, color_depth, palette, transparent_index);
+                let src_alpha = a as u32 * opacity / 255;
+                let dst = &mut pixels[frame_x as usize + frame_y as usize * size.w];
+                *dst = blend([r, g, b], src_alpha, *dst);
+            }
+        }
+    }
+
+    pixels
+        .into_iter()
+        .map(|[r, g, b, a]| u32::from_be_bytes([a, r, g, b]))
+        .collect()
+}
+
+/// Draw a color with an alpha over another color.
+fn blend(src: [u8; 3], src_alpha: u32, dst: [u8; 4]) -> [u8; 4] {
+    let dst_alpha = dst[3] as u32 * (255 - src_alpha) / 255;
+    let alpha = src_alpha + dst_alpha;
+    if alpha == 0 {
+        return [0; 4];
+    }
+
+    let channel =
+        |i: usize| ((src[i] as u32 * src_alpha + dst[i] as u32 * dst_alpha) / alpha) as u8;
+
+    [channel(0), channel(1), channel(2), alpha as u8]
+}
+
+/// Read the palette chunk into colors as `0xRRGGBBAA`.
+fn read_palette(chunk: &mut Reader, palette: &mut Vec<u32>) -> Result<()> {
+    let size = chunk.u32()? as usize;
+    let first = chunk.u32()? as usize;
+    let last = chunk.u32()? as usize;
+    chunk.skip(8)?;
+
+    palette.resize(palette.len().max(size), 0);
+    for index in first..=last {
+        let flags = chunk.u16()?;
+        let color = u32::from_be_bytes([chunk.u8()?, chunk.u8()?, chunk.u8()?, chunk.u8()?]);
+        if flags & 1 != 0 {
+            // Name of the color
+            chunk.string()?;
+        }
+
+        if let Some(entry) = palette.get_mut(index) {
+            *entry = color;
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the palette chunk of older files into colors as `0xRRGGBBAA`.
+///
+/// Newer files also contain the new palette chunk, which is read afterwards.
+fn read_old_palette(chunk: &mut Reader, palette: &mut [u32]) -> Result<()> {
+    let packets = chunk.u16()?;
+
+    let mut index = 0;
+    for _ in 0..packets {
+        index += chunk.u8()? as usize;
+        let count = match chunk.u8()? {
+            0 => 256,
+            count => count as usize,
+        };
+
+        for _ in 0..count {
+            let color = u32::from_be_bytes([chunk.u8()?, chunk.u8()?, chunk.u8()?, 255]);
+            if let Some(entry) = palette.get_mut(index) {
+                *entry = color;
+            }
+            index += 1;
+        }
+    }
+
+    Ok(())
+}
+
+/// Read little endian values from bytes.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Start reading at the beginning.
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    /// Next amount of bytes.
+    fn bytes(&mut self, amount: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.pos..(self.pos + amount))
+            .ok_or_else(|| miette!("Aseprite file is truncated"))?;
+        self.pos += amount;
+
+        Ok(bytes)
+    }
+
+    /// All bytes that are not read yet.
+    fn rest(&mut self) -> &'a [u8] {
+        let rest = self.bytes.get(self.pos..).unwrap_or_default();
+        self.pos = self.bytes.len();
+
+        rest
+    }
+
+    /// Ignore an amount of bytes.
+    fn skip(&mut self, amount: usize) -> Result<()> {
+        self.bytes(amount).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        Ok(i16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    /// String prefixed with its length.
+    fn string(&mut self) -> Result<String> {
+        let length = self.u16()? as usize;
+
+        String::from_utf8(self.bytes(length)?.to_vec())
+            .map_err(|err| miette!("Invalid string in Aseprite file: {err}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::ImageFormat;
+
+    use super::{Aseprite, Direction};
+
+    /// Flattening the source file must result in the same pixels as the exported image.
+    #[test]
+    fn test_matches_export() {
+        let aseprite = Aseprite::from_bytes(include_bytes!("../aseprite/dino1.aseprite")).unwrap();
+        let png = image::load_from_memory_with_format(
+            include_bytes!("../assets/dino1.png"),
+            ImageFormat::Png,
+        )
+        .unwrap()
+        .into_rgba8();
+
+        assert_eq!(aseprite.size.w, png.width() as usize);
+        assert_eq!(aseprite.size.h, png.height() as usize);
+        assert_eq!(aseprite.frames.len(), 1);
+
+        for (pixel, [r, g, b, a]) in aseprite.frames[0]
+            .pixels
+            .iter()
+            .zip(png.pixels().map(|pixel| pixel.0))
+        {
+            // Fully transparent pixels can have any color
+            if a == 0 {
+                assert_eq!(pixel >> 24, 0);
+            } else {
+                assert_eq!(*pixel, u32::from_be_bytes([a, r, g, b]));
+            }
+        }
+    }
+
+    /// Animations must have their layers flattened and tags read.
+    #[test]
+    fn test_animation() {
+        let aseprite = Aseprite::from_bytes(include_bytes!("../assets/dino.aseprite")).unwrap();
+
+        assert!(aseprite.frames.len() > 1);
+        assert!(aseprite.frames.iter().all(|frame| frame.duration > 0.0));
+
+        let fly = aseprite.tag("fly").unwrap();
+        assert!(fly.from <= fly.to && fly.to < aseprite.frames.len());
+        assert_eq!(fly.direction, Direction::Forward);
+
+        // The eye is on a separate layer on top of the body
+        let eye = aseprite.frames[fly.from].pixels[23 + 4 * aseprite.size.w];
+        let body = aseprite.frames[fly.from].pixels[20 + 4 * aseprite.size.w];
+        assert_eq!(eye >> 24, 0xFF);
+        assert_ne!(eye, body);
+
+        assert!(Aseprite::from_bytes(b"not an aseprite file").is_err());
+    }
+}
@@ -1,12 +1,21 @@
-use assets_manager::{AssetCache, AssetGuard, Compound};
+use std::path::Path;
 
-use crate::game::Settings;
+use assets_manager::{source::Source, Asset, AssetCache, AssetGuard, Compound, ReloadId};
+use miette::{IntoDiagnostic, Result};
+
+use crate::{
+    card::Card,
+    font::Font,
+    game::Settings,
+    object::ObjectsSpawner,
+    sprite::{AnimatedSprite, RotatableSprite, Sprite},
+    surface::Surface,
+};
 
 /// All external data.
-#[cfg(not(feature = "embed-assets"))]
-pub struct Assets(AssetCache<assets_manager::source::FileSystem>);
-#[cfg(feature = "embed-assets")]
-pub struct Assets(AssetCache<assets_manager::source::Embedded<'static>>);
+///
+/// Passed to everything that needs to load something, so multiple games with different assets can run at the same time.
+pub struct Assets(AssetCache<Box<dyn Source + Send + Sync>>);
 
 impl Assets {
     /// Construct the asset loader.
@@ -20,24 +29,131 @@ impl Assets {
         let source =
             assets_manager::source::Embedded::from(assets_manager::source::embed!("assets"));
 
-        let asset_cache = AssetCache::with_source(source);
+        Self::with_source(source)
+    }
+
+    /// Construct the asset loader reading from a directory on disk.
+    ///
+    /// Useful for testing with fixture assets or different tunings.
+    pub fn from_dir<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let source = assets_manager::source::FileSystem::new(path).into_diagnostic()?;
+
+        Ok(Self::with_source(source))
+    }
+
+    /// Construct the asset loader from any source.
+    pub fn with_source<S>(source: S) -> Self
+    where
+        S: Source + Send + Sync + 'static,
+    {
+        let asset_cache =
+            AssetCache::with_source(Box::new(source) as Box<dyn Source + Send + Sync>);
 
         Self(asset_cache)
     }
 
     /// Load the settings.
-    pub fn settings(&self) -> AssetGuard<Settings> {
-        self.0.load_expect("settings").read()
+    pub fn settings(&self) -> AssetGuard<'_, Settings> {
+        self.asset("settings")
+    }
+
+    /// Identifiers that change every time the settings or the cards changing them are hot-reloaded.
+    ///
+    /// Cards that can't be loaded are skipped, a failed hot-reload keeps the previous version.
+    pub fn settings_reload_ids<'a>(
+        &'a self,
+        card_ids: &'a [String],
+    ) -> impl Iterator<Item = ReloadId> + 'a {
+        std::iter::once(self.0.load_expect::<Settings>("settings").last_reload_id()).chain(
+            card_ids
+                .iter()
+                .filter_map(|id| match self.0.load::<Card>(id) {
+                    Ok(card) => Some(card.last_reload_id()),
+                    Err(err) => {
+                        log::error!("Error loading card '{id}': {err}");
+
+                        None
+                    }
+                }),
+        )
+    }
+
+    /// Sorted identifiers of all cards in the `card` directory.
+    pub fn card_ids(&self) -> Vec<String> {
+        self.dir_ids::<Card>("card")
+    }
+
+    /// Load all cards from the `card` directory, sorted by name so the order is always the same.
+    pub fn cards(&self) -> Vec<Card> {
+        self.card_ids()
+            .into_iter()
+            .map(|id| self.asset::<Card>(&id).clone().with_id(&id))
+            .collect()
+    }
+
+    /// Load all ground surfaces from the `surface` directory, sorted by name so the order is always the same.
+    pub fn surfaces(&self) -> Vec<Surface> {
+        self.dir_ids::<Surface>("surface")
+            .into_iter()
+            .map(|id| self.asset::<Surface>(&id).clone().with_id(&id))
+            .collect()
+    }
+
+    /// Sorted identifiers of all assets of a type in a directory.
+    fn dir_ids<T>(&self, dir: &str) -> Vec<String>
+    where
+        T: Asset,
+    {
+        let mut ids = self
+            .0
+            .load_dir::<T>(dir, false)
+            .unwrap_or_else(|err| panic!("Error loading directory '{dir}': {err}"))
+            .ids()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>();
+        ids.sort();
+
+        ids
     }
 
     /// Load an generic asset.
-    pub fn asset<T>(&self, path: &str) -> AssetGuard<T>
+    pub fn asset<T>(&self, path: &str) -> AssetGuard<'_, T>
     where
         T: Compound,
     {
+        puffin::profile_function!();
+
         self.0.load_expect(path).read()
     }
 
+    /// Load a sprite.
+    pub fn sprite(&self, path: &str) -> AssetGuard<'_, Sprite> {
+        self.asset(path)
+    }
+
+    /// Load an object spawner.
+    pub fn objects(&self, path: &str) -> AssetGuard<'_, ObjectsSpawner> {
+        self.asset(path)
+    }
+
+    /// Load a rotatable sprite.
+    pub fn rotatable_sprite(&self, path: &str) -> AssetGuard<'_, RotatableSprite> {
+        self.asset(path)
+    }
+
+    /// Load an animated sprite from an Aseprite file.
+    pub fn animated_sprite(&self, path: &str) -> AssetGuard<'_, AnimatedSprite> {
+        self.asset(path)
+    }
+
+    /// Load a font.
+    pub fn font(&self) -> AssetGuard<'_, Font> {
+        self.asset("Beachball")
+    }
+
     /// Hot reload from disk if applicable.
     pub fn enable_hot_reloading(&'static self) {
         self.0.enhance_hot_reloading();
@@ -0,0 +1,60 @@
+use vek::{Rect, Vec2};
+
+use crate::{
+    assets::Assets,
+    graphics::{canvas::Canvas, Color},
+    input::Input,
+};
+
+/// Clickable area with a label.
+pub struct Button {
+    /// Area in screen coordinates.
+    rect: Rect<f64, f64>,
+}
+
+impl Button {
+    /// Construct a button covering an area of the screen.
+    pub const fn new(rect: Rect<f64, f64>) -> Self {
+        Self { rect }
+    }
+
+    /// Whether the mouse is on top of the button.
+    pub fn is_hovered(&self, mouse_pos: Vec2<i32>) -> bool {
+        self.rect.contains_point(mouse_pos.as_())
+    }
+
+    /// Whether the button is clicked this update.
+    pub fn is_clicked(&self, input: &Input) -> bool {
+        input.left_mouse.is_released() && self.is_hovered(input.mouse_pos)
+    }
+
+    /// Draw the button, highlighted when the mouse is on top and it can be clicked.
+    pub fn render(
+        &self,
+        label: &str,
+        enabled: bool,
+        mouse_pos: Vec2<i32>,
+        canvas: &mut Canvas,
+        assets: &Assets,
+    ) {
+        let color = if !enabled {
+            Color::DarkestGray
+        } else if self.is_hovered(mouse_pos) {
+            Color::Gray
+        } else {
+            Color::DarkGray
+        };
+
+        canvas.fill_rect(self.rect.as_(), color.as_u32());
+
+        let font = assets.font();
+        font.render_centered(
+            label,
+            Vec2::new(
+                self.rect.x + self.rect.w / 2.0,
+                self.rect.y + (self.rect.h - font.char_size.h as f64) / 2.0,
+            ),
+            canvas,
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use vek::{ops::Clamp, Aabr, Extent2, Vec2};
+
+use crate::SIZE;
+
+/// View into the world, converts world coordinates to screen coordinates.
+#[derive(Debug, Clone)]
+pub struct Camera {
+    /// World position at the center of the screen, without screen shake.
+    pos: Vec2<f64>,
+    /// How far the camera moved during the last update.
+    delta: Vec2<f64>,
+    /// Screen pixels everything is moved by to shake the screen.
+    shake: Vec2<f64>,
+    /// How many world pixels are drawn into a single screen pixel.
+    zoom: u32,
+    /// Screen position the target is shown at.
+    framing: Vec2<f64>,
+}
+
+impl Camera {
+    /// Create a camera showing a target at a screen position.
+    pub fn new(target: Vec2<f64>, framing: Vec2<f64>) -> Self {
+        let mut this = Self {
+            pos: Vec2::zero(),
+            delta: Vec2::zero(),
+            shake: Vec2::zero(),
+            zoom: 1,
+            framing,
+        };
+        this.teleport(target, framing);
+
+        this
+    }
+
+    /// Follow a target moving with a velocity.
+    pub fn update(&mut self, target: Vec2<f64>, vel: Vec2<f64>, dt: f64, settings: &Settings) {
+        puffin::profile_scope!("Update camera");
+
+        self.zoom = settings.zoom(self.zoom, vel.magnitude());
+
+        // Look further ahead the faster the target goes
+        let look_ahead =
+            (vel * settings.look_ahead).clamped(-settings.max_look_ahead, settings.max_look_ahead);
+        let goal = self.framed(target) + look_ahead;
+
+        // Only follow when the goal leaves the dead zone
+        let half_dead_zone = Vec2::from(settings.dead_zone) / 2.0;
+        let goal = goal - (goal - self.pos).clamped(-half_dead_zone, half_dead_zone);
+
+        // Exponential decay so the smoothing doesn't depend on the time step
+        let previous = self.pos;
+        self.pos += (goal - self.pos) * (1.0 - (-settings.smoothing * dt).exp());
+
+        // Never lose sight of the target
+        let max_offset = Vec2::from(settings.max_offset) * self.zoom as f64;
+        let framed = self.framed(target);
+        self.pos = framed + (self.pos - framed).clamped(-max_offset, max_offset);
+
+        self.delta = self.pos - previous;
+    }
+
+    /// Jump to a target without smoothing, showing it at a screen position.
+    pub fn teleport(&mut self, target: Vec2<f64>, framing: Vec2<f64>) {
+        self.zoom = 1;
+        self.framing = framing;
+        self.pos = self.framed(target);
+        self.delta = Vec2::zero();
+    }
+
+    /// Set the screen shake offset.
+    pub fn set_shake(&mut self, shake: Vec2<f64>) {
+        self.shake = shake;
+    }
+
+    /// Convert a world position to a screen position.
+    pub fn world_to_screen(&self, pos: Vec2<f64>) -> Vec2<f64> {
+        (pos - self.pos) / self.zoom as f64 + Vec2::from(SIZE.as_::<f64>()) / 2.0 + self.shake
+    }
+
+    /// Convert a screen position to a world position.
+    pub fn screen_to_world(&self, pos: Vec2<f64>) -> Vec2<f64> {
+        (pos - self.shake - Vec2::from(SIZE.as_::<f64>()) / 2.0) * self.zoom as f64 + self.pos
+    }
+
+    /// Part of the world that's visible, without screen shake.
+    pub fn view(&self) -> Aabr<f64> {
+        let half_size = Vec2::from(SIZE.as_::<f64>()) * self.zoom as f64 / 2.0;
+
+        Aabr {
+            min: self.pos - half_size,
+            max: self.pos + half_size,
+        }
+    }
+
+    /// World position at the center of the screen.
+    pub fn pos(&self) -> Vec2<f64> {
+        self.pos
+    }
+
+    /// How far the camera moved during the last update.
+    pub fn delta(&self) -> Vec2<f64> {
+        self.delta
+    }
+
+    /// Screen shake offset in screen pixels.
+    pub fn shake(&self) -> Vec2<f64> {
+        self.shake
+    }
+
+    /// How many world pixels are drawn into a single screen pixel.
+    pub fn zoom(&self) -> u32 {
+        self.zoom
+    }
+
+    /// Center position that shows the target at the framed screen position.
+    fn framed(&self, target: Vec2<f64>) -> Vec2<f64> {
+        target + (Vec2::from(SIZE.as_::<f64>()) / 2.0 - self.framing) * self.zoom as f64
+    }
+}
+
+/// Camera settings loaded from a file so it's easier to change them with hot-reloading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Settings {
+    /// How fast the camera catches up with the target, higher is faster.
+    pub smoothing: f64,
+    /// Seconds of the target velocity the camera looks ahead.
+    pub look_ahead: Vec2<f64>,
+    /// Maximum world distance the camera looks ahead.
+    pub max_look_ahead: Vec2<f64>,
+    /// Area in world pixels the target can move in without the camera following.
+    pub dead_zone: Extent2<f64>,
+    /// Maximum screen distance the camera can be away from the framed target.
+    pub max_offset: Extent2<f64>,
+    /// Speed of the target above which the next zoom level is used, the first level is `1`.
+    pub zoom_speeds: Vec<f64>,
+    /// Fraction of the zoom speed the target must slow down to before zooming back in.
+    pub zoom_hysteresis: f64,
+}
+
+impl Settings {
+    /// Zoom level for a speed, only zooming in again when the speed is low enough.
+    fn zoom(&self, current: u32, speed: f64) -> u32 {
+        let zoom_out = self
+            .zoom_speeds
+            .iter()
+            .take_while(|zoom_speed| speed > **zoom_speed)
+            .count() as u32
+            + 1;
+        let zoom_in = self
+            .zoom_speeds
+            .iter()
+            .take_while(|zoom_speed| speed > **zoom_speed * self.zoom_hysteresis)
+            .count() as u32
+            + 1;
+
+        current.min(zoom_in).max(zoom_out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vek::{Extent2, Vec2};
+
+    use super::{Camera, Settings};
+
+    fn settings() -> Settings {
+        Settings {
+            smoothing: 10.0,
+            look_ahead: Vec2::zero(),
+            max_look_ahead: Vec2::zero(),
+            dead_zone: Extent2::new(20.0, 20.0),
+            max_offset: Extent2::new(100.0, 100.0),
+            zoom_speeds: vec![100.0, 200.0],
+            zoom_hysteresis: 0.5,
+        }
+    }
+
+    /// Small movements inside the dead zone must not move the camera, bigger ones are followed.
+    #[test]
+    fn test_follow() {
+        let settings = settings();
+        let framing = Vec2::new(50.0, 50.0);
+        let mut camera = Camera::new(Vec2::zero(), framing);
+        assert_eq!(camera.world_to_screen(Vec2::zero()), framing);
+
+        camera.update(Vec2::new(5.0, -5.0), Vec2::zero(), 1.0, &settings);
+        assert_eq!(camera.delta(), Vec2::zero());
+
+        for _ in 0..100 {
+            camera.update(Vec2::new(100.0, 0.0), Vec2::zero(), 0.1, &settings);
+        }
+        let screen = camera.world_to_screen(Vec2::new(100.0, 0.0));
+        assert!((screen.x - (framing.x + 10.0)).abs() < 1e-6);
+    }
+
+    /// Zooming out must happen above the speed, zooming in again only below the hysteresis.
+    #[test]
+    fn test_zoom() {
+        let settings = settings();
+
+        assert_eq!(settings.zoom(1, 50.0), 1);
+        assert_eq!(settings.zoom(1, 150.0), 2);
+        assert_eq!(settings.zoom(1, 250.0), 3);
+        assert_eq!(settings.zoom(3, 150.0), 3);
+        assert_eq!(settings.zoom(3, 90.0), 2);
+        assert_eq!(settings.zoom(2, 40.0), 1);
+    }
+}
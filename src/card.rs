@@ -1,64 +1,203 @@
+use std::collections::BTreeMap;
+
 use assets_manager::{loader::TomlLoader, Asset};
-use serde::Deserialize;
-use vek::{Extent2, Vec2};
+use fastrand::Rng;
+use miette::{miette, Result};
+use serde::{Deserialize, Serialize};
+use vek::{Extent2, Rect, Vec2};
+
+use crate::{assets::Assets, game::GameState, graphics::canvas::Canvas, random::weighted_index};
+
+pub const CARD_SIZE: Extent2<f64> = Extent2::new(88.0, 110.0);
+
+/// How rare a card is, rarer cards are offered less often.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rarity {
+    #[default]
+    Common,
+    Uncommon,
+    Rare,
+}
 
-use crate::game::GameState;
+/// Base weight of every rarity tier, multiplied with the weight of the card.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RarityWeight {
+    pub common: f64,
+    pub uncommon: f64,
+    pub rare: f64,
+}
 
-pub const CARDS: usize = 4;
-pub const CARD_PATHS: [&str; CARDS] = ["cannon", "noop", "wings", "attract"];
-const CARD_SIZE: Extent2<f64> = Extent2::new(88.0, 110.0);
+impl RarityWeight {
+    /// Weight of a tier.
+    pub fn get(&self, rarity: Rarity) -> f64 {
+        match rarity {
+            Rarity::Common => self.common,
+            Rarity::Uncommon => self.uncommon,
+            Rarity::Rare => self.rare,
+        }
+    }
+}
 
-#[derive(Default, Clone, Copy, Eq, PartialEq, Deserialize)]
+/// How an effect changes a setting.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Deserialize)]
 #[serde(rename_all = "snake_case")]
-pub enum CardTarget {
+pub enum Operation {
+    /// Add the value to the setting.
     #[default]
-    Nothing,
-    InitialSpeed,
-    Gravity,
-    Attract,
+    Add,
+    /// Multiply the setting with the value.
+    Multiply,
+    /// Replace the setting with the value.
+    Set,
+}
+
+/// Change to a numeric setting when a card is bought.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Effect {
+    /// Name of the setting, nested fields are separated by dots, for example `flight.gravity`.
+    target: String,
+    /// How to change the setting.
+    #[serde(default)]
+    operation: Operation,
+    /// Value used by the operation.
+    value: f64,
+}
+
+impl Effect {
+    /// Apply the effect to the serialized settings.
+    pub fn apply(&self, settings: &mut toml::value::Table) -> Result<()> {
+        // Walk the path of nested tables
+        let mut parts = self.target.split('.');
+        let last = parts.next_back().unwrap_or_default();
+        let table = parts.try_fold(settings, |table, part| {
+            table
+                .get_mut(part)
+                .and_then(|value| value.as_table_mut())
+                .ok_or_else(|| miette!("Setting '{}' does not exist", self.target))
+        })?;
+
+        let field = table
+            .get_mut(last)
+            .ok_or_else(|| miette!("Setting '{}' does not exist", self.target))?;
+
+        let current = match field {
+            toml::Value::Integer(value) => *value as f64,
+            toml::Value::Float(value) => *value,
+            _ => return Err(miette!("Setting '{}' is not a number", self.target)),
+        };
+
+        let new = match self.operation {
+            Operation::Add => current + self.value,
+            Operation::Multiply => current * self.value,
+            Operation::Set => self.value,
+        };
+
+        // Keep the type of the field so it can still be deserialized
+        *field = match field {
+            toml::Value::Integer(_) => toml::Value::Integer(new.round() as i64),
+            _ => toml::Value::Float(new),
+        };
+
+        Ok(())
+    }
 }
 
 #[derive(Default, Clone, Deserialize)]
 pub struct Card {
     title: String,
     description: Vec<String>,
-    target: CardTarget,
+    /// Settings changed when the card is bought.
     #[serde(default)]
-    value: f64,
+    pub effects: Vec<Effect>,
     #[serde(default)]
     cost: usize,
     #[serde(default)]
     max_amount: usize,
+    /// Rarity tier.
+    #[serde(default)]
+    pub rarity: Rarity,
+    /// How likely the card is offered compared to other cards of the same tier.
+    #[serde(default = "default_weight")]
+    weight: f64,
+    /// Name of the card asset, used to keep track of how often it's bought.
     #[serde(skip)]
-    index: usize,
+    pub id: String,
 }
 
 impl Card {
-    pub fn random(money: usize, selected_cards: &[usize]) -> Self {
-        let cards = CARD_PATHS
-            .iter()
-            .enumerate()
-            .map(|(i, path)| {
-                crate::asset::<Card>(&format!("card.{path}"))
-                    .clone()
-                    .with_index(i)
-            })
-            .filter(|card| {
-                card.cost <= money
-                    && (card.max_amount == 0 || selected_cards[card.index] < card.max_amount)
-            })
+    /// Pick the cards offered in a shop.
+    ///
+    /// Only cards that are not bought the maximum amount of times are offered, every card at most once.
+    /// When `guarantee_rare` is set the first offer is a rare card if there is one available.
+    pub fn offers(
+        amount: usize,
+        selected_cards: &BTreeMap<String, usize>,
+        guarantee_rare: bool,
+        rarity_weight: &RarityWeight,
+        rng: &mut Rng,
+        assets: &Assets,
+    ) -> Vec<Self> {
+        let mut cards = assets
+            .cards()
+            .into_iter()
+            .filter(|card| !card.is_maxed(selected_cards))
             .collect::<Vec<_>>();
 
-        fastrand::choice(cards).unwrap()
+        // Only guarantee a rare card when there is one that can be offered
+        let guarantee_rare = guarantee_rare
+            && cards
+                .iter()
+                .any(|card| card.rarity == Rarity::Rare && card.weight * rarity_weight.rare > 0.0);
+
+        let mut offers = Vec::with_capacity(amount);
+        while offers.len() < amount {
+            let only_rare = guarantee_rare && offers.is_empty();
+            let weights = cards
+                .iter()
+                .map(|card| {
+                    if only_rare && card.rarity != Rarity::Rare {
+                        0.0
+                    } else {
+                        card.weight * rarity_weight.get(card.rarity)
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            match weighted_index(&weights, rng) {
+                // Remove the picked card so it can't be offered twice
+                Some(index) => offers.push(cards.remove(index)),
+                None => break,
+            }
+        }
+
+        offers
     }
 
-    pub fn render(&self, offset: Vec2<f64>, canvas: &mut [u32], selected_cards: &[usize]) {
-        let font = crate::font();
+    pub fn render(
+        &self,
+        offset: Vec2<f64>,
+        canvas: &mut Canvas,
+        selected_cards: &BTreeMap<String, usize>,
+        available: bool,
+        assets: &Assets,
+    ) {
+        let font = assets.font();
         font.render_centered(
             &self.title,
             offset + (CARD_SIZE.w / 2.0, CARD_SIZE.h / 6.0),
             canvas,
         );
+        let rarity = match self.rarity {
+            Rarity::Common => "",
+            Rarity::Uncommon => "uncommon",
+            Rarity::Rare => "rare",
+        };
+        font.render_centered(
+            rarity,
+            offset + (CARD_SIZE.w / 2.0, CARD_SIZE.h / 6.0 + 12.0),
+            canvas,
+        );
         for (i, d) in self.description.iter().enumerate() {
             font.render_centered(
                 d,
@@ -69,40 +208,65 @@ impl Card {
 
         if self.max_amount > 0 {
             font.render_centered(
-                &format!("{}/{}", selected_cards[self.index], self.max_amount),
+                &format!("{}/{}", self.amount_bought(selected_cards), self.max_amount),
                 offset + (CARD_SIZE.w / 2.0, 3.0),
                 canvas,
             );
         }
 
-        let disk = crate::sprite("disk-icon");
+        let disk = assets.sprite("disk-icon");
         let x = 32.0;
         let y = CARD_SIZE.h - disk.height() as f64 - 4.0;
-        disk.render(canvas, offset + (x, y));
+        disk.render_screen(canvas, offset + (x, y));
         font.render(
             &format!("{}", self.cost),
             offset + (disk.width() as f64 + x + 2.0, y + 1.0),
             canvas,
         );
+
+        // Darken the card when it can't be bought
+        if !available {
+            canvas.map_rect(
+                Rect::new(offset.x, offset.y, CARD_SIZE.w, CARD_SIZE.h).as_(),
+                |pixel| 0xff_00_00_00 | ((pixel >> 1) & 0x7f_7f_7f),
+            );
+        }
     }
 
-    pub fn apply(&self, game: &mut GameState) {
-        if self.cost > game.money {
+    pub fn apply(&self, game: &mut GameState, assets: &Assets) {
+        if !self.can_buy(game.money, &game.selected_cards) {
             return;
         }
 
         game.money -= self.cost;
-        match self.target {
-            CardTarget::Nothing => (),
-            CardTarget::InitialSpeed => game.extra_initial_speed += self.value,
-            CardTarget::Gravity => game.extra_gravity += self.value,
-            CardTarget::Attract => game.attraction += self.value,
-        }
-        game.selected_cards[self.index] += 1;
+        *game.selected_cards.entry(self.id.clone()).or_default() += 1;
+
+        // Apply the effects of the card
+        game.update_settings(assets);
     }
 
-    fn with_index(mut self, index: usize) -> Self {
-        self.index = index;
+    /// Whether the card is affordable and not bought the maximum amount of times.
+    pub fn can_buy(&self, money: usize, selected_cards: &BTreeMap<String, usize>) -> bool {
+        self.cost <= money && !self.is_maxed(selected_cards)
+    }
+
+    /// Whether the card is bought the maximum amount of times.
+    pub fn is_maxed(&self, selected_cards: &BTreeMap<String, usize>) -> bool {
+        self.max_amount > 0 && self.amount_bought(selected_cards) >= self.max_amount
+    }
+
+    /// How many times this card is bought.
+    pub fn amount_bought(&self, selected_cards: &BTreeMap<String, usize>) -> usize {
+        selected_cards.get(&self.id).copied().unwrap_or_default()
+    }
+
+    /// Set the name from the asset path.
+    pub fn with_id(mut self, asset_path: &str) -> Self {
+        self.id = asset_path
+            .rsplit('.')
+            .next()
+            .unwrap_or(asset_path)
+            .to_string();
 
         self
     }
@@ -113,3 +277,79 @@ impl Asset for Card {
 
     type Loader = TomlLoader;
 }
+
+/// Weight of a card when it's not set.
+fn default_weight() -> f64 {
+    1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use fastrand::Rng;
+
+    use super::{Card, Effect, Operation, Rarity, RarityWeight};
+    use crate::assets::Assets;
+
+    /// Effects must change nested settings and keep the type of the setting.
+    #[test]
+    fn test_effect() {
+        let mut settings: toml::value::Table = toml::from_str(
+            r#"
+            gravity = 98.1
+            lives = 3
+
+            [restitution]
+            x = 0.5
+            "#,
+        )
+        .unwrap();
+
+        let effect = |target: &str, operation, value| Effect {
+            target: target.to_string(),
+            operation,
+            value,
+        };
+
+        effect("gravity", Operation::Add, -10.0)
+            .apply(&mut settings)
+            .unwrap();
+        effect("lives", Operation::Multiply, 2.0)
+            .apply(&mut settings)
+            .unwrap();
+        effect("restitution.x", Operation::Set, 0.9)
+            .apply(&mut settings)
+            .unwrap();
+
+        assert_eq!(settings["gravity"].as_float(), Some(98.1 - 10.0));
+        assert_eq!(settings["lives"].as_integer(), Some(6));
+        assert_eq!(settings["restitution"]["x"].as_float(), Some(0.9));
+
+        assert!(effect("missing", Operation::Add, 1.0)
+            .apply(&mut settings)
+            .is_err());
+    }
+
+    /// Offers must never contain the same card twice and the pity must guarantee a rare card.
+    #[test]
+    fn test_offers() {
+        let assets = Assets::load();
+        let mut rng = Rng::with_seed(0);
+        let rarity_weight = RarityWeight {
+            common: 1.0,
+            uncommon: 1.0,
+            rare: 0.01,
+        };
+
+        for _ in 0..100 {
+            let offers = Card::offers(3, &BTreeMap::new(), true, &rarity_weight, &mut rng, &assets);
+
+            assert_eq!(offers.len(), 3);
+            assert_eq!(offers[0].rarity, Rarity::Rare);
+            for (i, card) in offers.iter().enumerate() {
+                assert!(offers[(i + 1)..].iter().all(|other| other.id != card.id));
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use serde::{Deserialize, Serialize};
+use vek::{Extent2, Vec2};
+
+use crate::{
+    math::Rotation,
+    physics::{collision::shape::Shape, rigidbody::RigidBodyBuilder},
+};
+
+/// Position and velocity of something flying.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Body {
+    pub pos: Vec2<f64>,
+    pub vel: Vec2<f64>,
+}
+
+/// Numerical method for moving a body forward in time.
+pub trait Integrator {
+    /// Advance the body a single step.
+    ///
+    /// The acceleration can depend on the velocity, which is needed for drag.
+    fn step(&self, body: &mut Body, acceleration: &dyn Fn(Vec2<f64>) -> Vec2<f64>, dt: f64);
+}
+
+/// Explicit Euler, moves with the velocity from the start of the step.
+///
+/// Cheapest but gains energy over time.
+#[derive(Debug, Clone, Copy)]
+pub struct Euler;
+
+impl Integrator for Euler {
+    fn step(&self, body: &mut Body, acceleration: &dyn Fn(Vec2<f64>) -> Vec2<f64>, dt: f64) {
+        let acc = acceleration(body.vel);
+        body.pos += body.vel * dt;
+        body.vel += acc * dt;
+    }
+}
+
+/// Semi-implicit Euler, moves with the velocity from the end of the step.
+///
+/// As cheap as explicit Euler but keeps the energy stable.
+#[derive(Debug, Clone, Copy)]
+pub struct SemiImplicitEuler;
+
+impl Integrator for SemiImplicitEuler {
+    fn step(&self, body: &mut Body, acceleration: &dyn Fn(Vec2<f64>) -> Vec2<f64>, dt: f64) {
+        body.vel += acceleration(body.vel) * dt;
+        body.pos += body.vel * dt;
+    }
+}
+
+/// Velocity Verlet, averages the acceleration at the start and end of the step.
+///
+/// Exact for constant acceleration.
+#[derive(Debug, Clone, Copy)]
+pub struct Verlet;
+
+impl Integrator for Verlet {
+    fn step(&self, body: &mut Body, acceleration: &dyn Fn(Vec2<f64>) -> Vec2<f64>, dt: f64) {
+        let acc = acceleration(body.vel);
+        body.pos += body.vel * dt + acc * (0.5 * dt * dt);
+
+        // Estimate the velocity at the end of the step to calculate the drag there
+        let next_acc = acceleration(body.vel + acc * dt);
+        body.vel += (acc + next_acc) * (0.5 * dt);
+    }
+}
+
+/// Integrator that can be chosen in the settings.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegratorKind {
+    Euler,
+    #[default]
+    SemiImplicitEuler,
+    Verlet,
+}
+
+impl Integrator for IntegratorKind {
+    fn step(&self, body: &mut Body, acceleration: &dyn Fn(Vec2<f64>) -> Vec2<f64>, dt: f64) {
+        match self {
+            Self::Euler => Euler.step(body, acceleration, dt),
+            Self::SemiImplicitEuler => SemiImplicitEuler.step(body, acceleration, dt),
+            Self::Verlet => Verlet.step(body, acceleration, dt),
+        }
+    }
+}
+
+/// Flight model of the player, positive `y` is down.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Flight {
+    pub gravity: f64,
+    /// Fraction of the velocity lost every second to air resistance.
+    pub air_drag: f64,
+    /// Fraction of the angular velocity lost every second to air resistance.
+    pub angular_drag: f64,
+    /// Size of the collision box, the player tumbles when landing on a corner.
+    pub collider: Extent2<f64>,
+    /// Below this velocity the player stops when touching the ground.
+    pub halting_velocity: Vec2<f64>,
+    /// Radians per second squared the player spins with while holding left or right.
+    pub spin_acceleration: f64,
+    /// Maximum radians per second the player can spin itself with.
+    pub max_spin: f64,
+    /// Fraction of the velocity kept when landing on anything but the feet.
+    pub belly_flop_speed: f64,
+    /// Fraction of the velocity kept when landing feet first.
+    pub feet_first_speed: f64,
+}
+
+impl Flight {
+    /// Rigid body of the player.
+    pub fn rigidbody_builder(&self, pos: Vec2<f64>, vel: Vec2<f64>) -> RigidBodyBuilder {
+        RigidBodyBuilder::new_dynamic(pos)
+            .with_collider(Shape::rectangle(self.collider))
+            .with_velocity(vel)
+            .with_gravity(self.gravity)
+            .with_linear_drag(self.air_drag)
+            .with_angular_drag(self.angular_drag)
+    }
+
+    /// Angular velocity after spinning in a direction for a time step, negative is counter clockwise.
+    ///
+    /// Faster spins from bounces are kept but can't be increased any further.
+    pub fn spin(&self, ang_vel: f64, dir: f64, dt: f64) -> f64 {
+        let spun = ang_vel + dir * self.spin_acceleration * dt;
+        if spun.abs() > self.max_spin && spun.abs() > ang_vel.abs() {
+            ang_vel
+        } else {
+            spun
+        }
+    }
+
+    /// Fraction of the velocity kept when landing on ground pointing in the direction of the normal.
+    ///
+    /// The feet point down when the player isn't rotated.
+    pub fn landing_speed(&self, rot: Rotation, normal: Vec2<f64>) -> f64 {
+        let feet_first = rot.rotate(Vec2::unit_y()).dot(-normal).max(0.0);
+
+        self.belly_flop_speed + (self.feet_first_speed - self.belly_flop_speed) * feet_first
+    }
+
+    /// Whether the player is slow enough to stop when touching the ground.
+    pub fn is_halting(&self, vel: Vec2<f64>) -> bool {
+        vel.x.abs() < self.halting_velocity.x && vel.y.abs() < self.halting_velocity.y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vek::{Extent2, Vec2};
+
+    use super::{Body, Euler, Flight, Integrator, SemiImplicitEuler, Verlet};
+    use crate::math::Rotation;
+
+    const GRAVITY: f64 = 100.0;
+
+    /// Potential and kinetic energy of a body with a mass of one.
+    fn energy(body: &Body) -> f64 {
+        -GRAVITY * body.pos.y + 0.5 * body.vel.magnitude_squared()
+    }
+
+    /// Simulate a thrown body for a second and return how much energy it gained.
+    fn energy_gain(integrator: &dyn Integrator, dt: f64) -> f64 {
+        let mut body = Body {
+            pos: Vec2::zero(),
+            vel: Vec2::new(200.0, -300.0),
+        };
+        let start = energy(&body);
+
+        for _ in 0..(1.0 / dt).round() as usize {
+            integrator.step(&mut body, &|_| Vec2::new(0.0, GRAVITY), dt);
+        }
+
+        energy(&body) - start
+    }
+
+    /// Without drag energy must stay the same, with Euler drifting by an amount depending on the step size.
+    #[test]
+    fn test_energy() {
+        for dt in [1.0 / 30.0, 1.0 / 60.0, 1.0 / 240.0] {
+            assert!(energy_gain(&Verlet, dt).abs() < 1e-6);
+
+            // Semi-implicit Euler loses the same amount as Euler gains
+            let euler = energy_gain(&Euler, dt);
+            let semi_implicit = energy_gain(&SemiImplicitEuler, dt);
+            assert!(euler > 0.0);
+            assert!(semi_implicit < 0.0);
+            assert!((euler + semi_implicit).abs() < 1e-6);
+        }
+
+        // Smaller steps drift less
+        assert!(energy_gain(&Euler, 1.0 / 240.0) < energy_gain(&Euler, 1.0 / 30.0));
+    }
+
+    /// Drag must slow down by the same amount regardless of the step size.
+    #[test]
+    fn test_drag() {
+        let speed_after_second = |dt: f64| {
+            let mut body = Body {
+                pos: Vec2::zero(),
+                vel: Vec2::new(100.0, 0.0),
+            };
+            let drag = -(1.0f64 - 0.5).ln();
+            for _ in 0..(1.0 / dt).round() as usize {
+                Verlet.step(&mut body, &|vel| -vel * drag, dt);
+            }
+
+            body.vel.x
+        };
+
+        for dt in [1.0 / 30.0, 1.0 / 60.0, 1.0 / 240.0] {
+            assert!((speed_after_second(dt) - 50.0).abs() < 0.1);
+        }
+    }
+
+    /// Landing feet first must keep the speed, on the belly or head it's lost.
+    #[test]
+    fn test_landing() {
+        let flight = Flight {
+            gravity: 0.0,
+            air_drag: 0.0,
+            angular_drag: 0.0,
+            collider: Extent2::new(10.0, 10.0),
+            halting_velocity: Vec2::zero(),
+            spin_acceleration: 10.0,
+            max_spin: 5.0,
+            belly_flop_speed: 0.5,
+            feet_first_speed: 1.0,
+        };
+        let up = -Vec2::unit_y();
+
+        assert!((flight.landing_speed(Rotation::zero(), up) - 1.0).abs() < 1e-6);
+        assert!((flight.landing_speed(Rotation::from_degrees(90.0), up) - 0.5).abs() < 1e-6);
+        assert!((flight.landing_speed(Rotation::from_degrees(180.0), up) - 0.5).abs() < 1e-6);
+
+        // Sloped ground the feet are pointing into
+        let slope = Vec2::new(1.0, -1.0).normalized();
+        assert!(flight.landing_speed(Rotation::from_degrees(45.0), slope) > 0.99);
+        assert!(flight.landing_speed(Rotation::zero(), slope) < 1.0);
+
+        // Spinning is capped, but faster spins are kept
+        assert!((flight.spin(0.0, 1.0, 0.1) - 1.0).abs() < 1e-6);
+        assert_eq!(flight.spin(4.5, 1.0, 0.1), 4.5);
+        assert_eq!(flight.spin(8.0, 1.0, 0.1), 8.0);
+        assert!((flight.spin(8.0, -1.0, 0.1) - 7.0).abs() < 1e-6);
+    }
+}
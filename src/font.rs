@@ -1,9 +1,9 @@
 use assets_manager::{loader::TomlLoader, AnyCache, Asset, BoxedError, Compound, SharedString};
-use blit::{prelude::SubRect, Blit, BlitBuffer, BlitOptions};
+use blit::{prelude::SubRect, BlitBuffer, BlitOptions};
 use serde::Deserialize;
 use vek::{Extent2, Vec2};
 
-use crate::{sprite::Sprite, SIZE};
+use crate::{graphics::canvas::Canvas, sprite::Sprite};
 
 /// Pixel font loaded from an image.
 pub struct Font {
@@ -16,7 +16,7 @@ pub struct Font {
 impl Font {
     /// Load a font from image bytes.
     /// Render text on a pixel buffer.
-    pub fn render(&self, text: &str, pos: Vec2<f64>, canvas: &mut [u32]) {
+    pub fn render(&self, text: &str, pos: Vec2<f64>, canvas: &mut Canvas) {
         puffin::profile_function!();
 
         // First character in the image
@@ -47,9 +47,8 @@ impl Font {
             let char_offset = (ch as u8 - char_start as u8) as u32 * self.char_size.w as u32;
 
             // Draw the character
-            self.sprite.blit(
-                canvas,
-                SIZE.into_tuple().into(),
+            canvas.blit(
+                &self.sprite,
                 &BlitOptions::new_position(x, y).with_sub_rect(SubRect::new(
                     char_offset,
                     0,
@@ -59,7 +58,7 @@ impl Font {
         });
     }
 
-    pub fn render_centered(&self, text: &str, pos: Vec2<f64>, canvas: &mut [u32]) {
+    pub fn render_centered(&self, text: &str, pos: Vec2<f64>, canvas: &mut Canvas) {
         self.render(
             text,
             pos - Vec2::new((text.len() as f64 * self.char_size.w as f64) / 2.0, 0.0),
@@ -1,21 +1,71 @@
 use std::f64::consts::PI;
 
-use assets_manager::{loader::TomlLoader, Asset};
-use serde::Deserialize;
+use std::{collections::BTreeMap, rc::Rc};
+
+use assets_manager::{loader::TomlLoader, Asset, ReloadId};
+use fastrand::Rng;
+use miette::{IntoDiagnostic, Result};
+use serde::{Deserialize, Serialize};
 use vek::{Extent2, Rect, Vec2};
 
 use crate::{
-    card::{Card, CARDS, CARD_PATHS},
-    graphics::Color,
+    animation::Animation,
+    assets::Assets,
+    button::Button,
+    camera::Camera,
+    card::{Card, Rarity, RarityWeight, CARD_SIZE},
+    flight::{Body, Flight},
+    graphics::{
+        blend::{Blend, BlendMode},
+        canvas::Canvas,
+        draw_list::{DrawLayer, DrawList},
+        Color,
+    },
     input::Input,
     math::Iso,
-    object::Object,
+    object::{Carrier, Effect, Object},
     particle::Particle,
+    physics::{
+        rigidbody::{RigidBodyBuilder, RigidBodyHandle},
+        Physics,
+    },
+    projectile::Projectile,
+    save::{SaveData, Storage},
+    terrain::Terrain,
     timer::Timer,
+    unit::{Allegiance, Unit, UnitType},
     SIZE,
 };
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+/// Horizontal position of every card in the shop.
+const CARD_X: [f64; 3] = [20.0, 116.0, 212.0];
+/// Vertical position of the cards in the shop.
+const CARD_Y: f64 = 64.0;
+/// Button in the shop to pick new cards to offer.
+const REROLL_BUTTON: Button = Button::new(Rect {
+    x: 20.0,
+    y: 182.0,
+    w: 88.0,
+    h: 14.0,
+});
+/// Button in the shop to switch to the gamble mode.
+const GAMBLE_BUTTON: Button = Button::new(Rect {
+    x: 116.0,
+    y: 182.0,
+    w: 88.0,
+    h: 14.0,
+});
+/// Button in the shop to leave it and launch.
+const LAUNCH_BUTTON: Button = Button::new(Rect {
+    x: 212.0,
+    y: 182.0,
+    w: 88.0,
+    h: 14.0,
+});
+/// Animated sprite of the player.
+const DINO_SPRITE: &str = "dino";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 enum Phase {
     Buy,
     LaunchSetAngle,
@@ -33,192 +83,273 @@ pub struct GameState {
     pub pos: Vec2<f64>,
     pub vel: Vec2<f64>,
     pub rot: f64,
+    /// Radians per second the player spins with.
+    pub ang_vel: f64,
+    /// Whether the player touched the ground during the last update.
+    pub grounded: bool,
     pub money: usize,
     pub boost: f64,
     pub boost_sign: f64,
     pub boost_delay: f64,
+    /// Boosts stored by fuel cans, used when boosting before the delay is over.
+    pub boost_charges: usize,
     pub buy_timeout: f64,
     pub buy_item: f64,
-    pub trees: Vec<Object>,
-    pub clouds: Vec<Object>,
-    pub disks: Vec<Object>,
-    pub rocks: Vec<Object>,
-    pub bombs: Vec<Object>,
+    /// Scenery and items in the world, in the order they are drawn in.
+    pub objects: Vec<Object>,
+    /// Object carrying the player, nothing when flying freely.
+    pub carrier: Option<Carrier>,
+    /// Clip the player is drawn with.
+    pub animation: Animation,
     pub particles: Vec<Particle>,
-    pub card_options: [Card; 3],
-    pub selected_cards: [usize; CARDS],
-    pub extra_initial_speed: f64,
-    pub extra_gravity: f64,
+    /// View into the world following the player.
+    pub camera: Camera,
+    /// Ground the player bounces on.
+    pub terrain: Terrain,
+    /// Simulation of the player bouncing on the ground.
+    pub physics: Physics,
+    /// Rigid body of the player, `pos`, `vel`, `rot` and `ang_vel` are copied from it after every step.
+    dino: RigidBodyHandle,
+    /// Static rigid body of the ground.
+    ground: RigidBodyHandle,
+    /// Units walking on the ground.
+    pub units: Vec<Unit>,
+    /// Projectiles thrown by units.
+    pub projectiles: Vec<Projectile>,
+    /// Horizontal position where the next unit is spawned.
+    next_unit_x: f64,
+    /// Seconds before the player can shoot again.
+    pub shot_delay: f64,
+    /// Cards offered in the shop, nothing when there are not enough cards to offer.
+    pub card_options: [Option<Card>; 3],
+    /// How many times the offers are rerolled in the current shop.
+    pub rerolls: usize,
+    /// How many shops in a row didn't offer a rare card.
+    pub shops_without_rare: usize,
+    /// Whether the shop uses the timing cursor to pick a single card before launching.
+    pub gamble: bool,
+    /// Last position of the mouse, used for highlighting what's hovered.
+    pub mouse_pos: Vec2<i32>,
+    /// How many times each card is bought, by card name.
+    pub selected_cards: BTreeMap<String, usize>,
     pub dead_timeout: f64,
     pub max_distance: f64,
     pub screen_shake_pos: Vec2<f64>,
     pub screen_shake_force: f64,
     pub screen_shake_time: f64,
-    pub attraction: f64,
+    /// Settings with the effects of all bought cards applied.
+    ///
+    /// Shared so it can be read while the rest of the state changes.
+    settings: Rc<Settings>,
+    /// Reload identifiers of the settings and cards the effects were applied with.
+    settings_reload_ids: Vec<ReloadId>,
+    /// Asset identifiers of all cards, looked up once so hot-reloading doesn't list the directory every tick.
+    card_ids: Vec<String>,
+    /// Seed the random number generator was constructed with.
+    pub seed: u64,
+    /// Random number generator for everything that influences the game, so a run can be reproduced.
+    rng: Rng,
+    /// Where the progression is saved to, nothing when it's not persisted.
+    storage: Option<Storage>,
 }
 
 impl GameState {
     /// Construct the game state with default values.
-    pub fn new() -> Self {
-        let settings = crate::settings();
-        let trees = crate::objects("palm").to_objects();
-        let mut clouds = crate::objects("cloud").to_objects();
-        clouds.extend(crate::objects("cloud2").to_objects());
-        clouds.extend(crate::objects("cloud3").to_objects());
-        clouds.extend(crate::objects("cloud4").to_objects());
-        let disks = crate::objects("disk").to_objects();
-        let rocks = crate::objects("rock").to_objects();
-        let bombs = crate::objects("bomb").to_objects();
+    ///
+    /// The same seed with the same input will always result in the same game.
+    pub fn new(seed: u64, assets: &Assets) -> Self {
+        Self::from_save(seed, &SaveData::default(), assets)
+    }
+
+    /// Construct the game state continuing from saved progression.
+    pub fn from_save(seed: u64, save: &SaveData, assets: &Assets) -> Self {
+        let settings = assets.settings();
+        let mut rng = Rng::with_seed(seed);
+
+        let camera = Camera::new(Vec2::zero(), settings.player_offset);
+
+        let objects = settings
+            .objects
+            .iter()
+            .flat_map(|path| assets.objects(path).to_objects(&camera, &mut rng))
+            .collect();
+        let terrain = Terrain::new(&mut rng, &settings.terrain, assets.surfaces());
+
+        let mut physics = Physics::new();
+        let dino = settings
+            .flight
+            .rigidbody_builder(Vec2::zero(), Vec2::zero())
+            .spawn(&mut physics);
+        let (ground_pos, ground_shape) = terrain.collider();
+        let ground = RigidBodyBuilder::new_static(ground_pos)
+            .with_collider(ground_shape)
+            .spawn(&mut physics);
 
         let mut state = Self {
             phase: Phase::Buy,
             initial_angle: settings.min_angle,
             initial_speed: settings.min_speed,
-            extra_initial_speed: 0.0,
             buy_timeout: settings.buy_time,
             particles: Vec::new(),
+            camera,
             pos: Vec2::zero(),
             vel: Vec2::zero(),
             rot: 0.0,
+            ang_vel: 0.0,
+            grounded: false,
             money: 0,
             sign: 1.0,
             boost: 0.0,
             boost_sign: 1.0,
             boost_delay: 0.0,
+            boost_charges: 0,
             buy_item: 0.0,
-            trees,
-            clouds,
-            disks,
-            rocks,
-            bombs,
-            card_options: [Card::default(), Card::default(), Card::default()],
-            selected_cards: [0; CARDS],
-            extra_gravity: 0.0,
+            objects,
+            carrier: None,
+            animation: Animation::new("fly"),
+            terrain,
+            physics,
+            dino,
+            ground,
+            units: Vec::new(),
+            projectiles: Vec::new(),
+            next_unit_x: UnitType::EnemySpear.settings(assets).min_spawn_distance,
+            shot_delay: 0.0,
+            card_options: [None, None, None],
+            rerolls: 0,
+            shops_without_rare: 0,
+            gamble: false,
+            mouse_pos: Vec2::zero(),
+            selected_cards: BTreeMap::new(),
             dead_timeout: 0.0,
             max_distance: 0.0,
             screen_shake_pos: Vec2::new(0.0, 0.0),
             screen_shake_force: 0.0,
             screen_shake_time: 0.0,
-            attraction: 0.0,
+            settings: Rc::new(settings.clone()),
+            settings_reload_ids: Vec::new(),
+            card_ids: assets.card_ids(),
+            seed,
+            rng,
+            storage: None,
         };
 
-        state.switch_to_buy();
+        save.apply(&mut state);
+        state.update_settings(assets);
+        state.switch_to_buy(assets);
 
         state
     }
 
+    /// Construct the game state with the progression loaded from storage.
+    ///
+    /// The progression is saved automatically every time the buy screen is shown.
+    pub fn with_storage(seed: u64, storage: Storage, assets: &Assets) -> Self {
+        match storage.load() {
+            Ok(save) => {
+                let mut state = Self::from_save(seed, &save.unwrap_or_default(), assets);
+                state.storage = Some(storage);
+
+                state
+            }
+            Err(err) => {
+                // Don't overwrite the broken save data so it can still be recovered
+                log::error!("Error loading save data, progression won't be saved: {err:?}");
+
+                Self::new(seed, assets)
+            }
+        }
+    }
+
     /// Update a frame and handle user input.
-    pub fn update(&mut self, input: &Input, dt: f64) {
-        let settings = crate::settings();
+    pub fn update(&mut self, input: &Input, dt: f64, assets: &Assets) {
+        // Apply the card effects again when the settings or cards are hot-reloaded
+        if !assets
+            .settings_reload_ids(&self.card_ids)
+            .eq(self.settings_reload_ids.iter().copied())
+        {
+            self.update_settings(assets);
+        }
+        let settings = Rc::clone(&self.settings);
+        self.mouse_pos = input.mouse_pos;
 
         if self.screen_shake_time > 0.0 {
-            self.screen_shake_pos.x += (fastrand::f64() - 0.5) * self.screen_shake_force * dt;
-            self.screen_shake_pos.y += (fastrand::f64() - 0.5) * self.screen_shake_force * dt;
+            self.screen_shake_pos.x += (self.rng.f64() - 0.5) * self.screen_shake_force * dt;
+            self.screen_shake_pos.y += (self.rng.f64() - 0.5) * self.screen_shake_force * dt;
             self.screen_shake_pos *= self.screen_shake_time;
             self.screen_shake_time -= dt;
         } else {
             self.screen_shake_pos = Vec2::zero();
         }
 
-        if self.phase != Phase::Dead {
-            self.clouds
-                .iter_mut()
-                .chain(self.trees.iter_mut())
-                .chain(self.disks.iter_mut())
-                .chain(self.rocks.iter_mut())
-                .chain(self.bombs.iter_mut())
-                .for_each(|obj| obj.update(self.pos, self.vel, settings.player_offset, dt));
-
-            self.particles
-                .retain_mut(|particle| particle.update(self.vel, settings.particle_gravity, dt));
-
-            self.disks.iter_mut().for_each(|disk| {
-                let delta = settings.player_offset - disk.pos;
-                if delta.x <= 0.0 && delta.magnitude() < settings.attraction_distance {
-                    let dir = delta.normalized();
-                    disk.pos += dir * self.attraction * dt;
-                }
+        self.camera.set_shake(self.screen_shake_pos);
+        self.camera.update(self.pos, self.vel, dt, &settings.camera);
 
-                if disk.collides_user(settings.player_collider) {
-                    self.money += 1;
+        if self.phase != Phase::Dead {
+            self.particles.retain_mut(|particle| {
+                particle.update(settings.particle_gravity, dt, &self.camera)
+            });
 
-                    for _ in 0..settings.particle_amount {
-                        self.particles.push(Particle::new(
-                            disk.pos,
-                            self.vel * settings.particle_vel_multiplier,
-                            settings.particle_force,
-                            Color::Brown,
-                            true,
-                            settings.particle_life,
-                        ));
-                    }
-                    for _ in 0..settings.topleft_particle_amount {
-                        self.particles.push(Particle::new(
-                            Vec2::new(33.0, 10.0),
-                            Vec2::zero(),
-                            settings.topleft_particle_force,
-                            Color::White,
-                            false,
-                            settings.topleft_particle_life,
-                        ));
-                    }
+            let player_collider = self.player_collider();
 
-                    self.screen_shake_time = settings.screen_shake_disk.duration;
-                    self.screen_shake_force = settings.screen_shake_disk.force;
+            let mut triggered = Vec::new();
+            for obj in self.objects.iter_mut() {
+                obj.update(
+                    self.pos,
+                    self.vel,
+                    settings.player_offset,
+                    &self.camera,
+                    &mut self.rng,
+                );
 
-                    disk.reset(self.pos, self.vel);
+                if obj.on_ground {
+                    obj.pos.y += self.terrain.height(obj.pos.x);
                 }
-            });
 
-            self.bombs.iter_mut().for_each(|bomb| {
-                let delta = settings.player_offset - bomb.pos;
-                if delta.x <= 0.0 && delta.magnitude() < settings.attraction_distance {
-                    let dir = delta.normalized();
-                    bomb.pos += dir * self.attraction * dt;
+                if obj.magnetic {
+                    obj.attract(
+                        self.pos,
+                        settings.attraction_distance,
+                        settings.attraction * dt,
+                    );
                 }
 
-                if bomb.collides_user(settings.player_collider) {
-                    self.vel.x += settings.bomb_force.x;
-                    if self.vel.y.is_sign_positive() {
-                        self.vel.y = -settings.bomb_force.y;
-                    } else {
-                        self.vel.y -= settings.bomb_force.y;
-                    }
-
-                    for i in 0..settings.bomb_particle_amount {
-                        self.particles.push(Particle::new(
-                            bomb.pos,
-                            self.vel * settings.bomb_particle_vel_multiplier,
-                            settings.bomb_particle_force,
-                            if i < settings.bomb_particle_amount / 2 {
-                                Color::DarkGray
-                            } else {
-                                Color::Orange
-                            },
-                            true,
-                            settings.bomb_particle_life,
-                        ));
-                    }
-
-                    self.screen_shake_time = settings.screen_shake_bomb.duration;
-                    self.screen_shake_force = settings.screen_shake_bomb.force;
+                if self.phase == Phase::Fly
+                    && obj.overlap_acceleration != Vec2::zero()
+                    && obj.collides_user(player_collider)
+                {
+                    self.vel += obj.overlap_acceleration * dt;
+                }
 
-                    bomb.reset(self.pos, self.vel);
+                if !obj.on_collision.is_empty() && obj.collides_user(player_collider) {
+                    triggered.push((
+                        obj.pos,
+                        obj.sprite_path().to_string(),
+                        obj.on_collision.clone(),
+                    ));
+                    obj.reset(self.pos, self.vel, &self.camera, &mut self.rng);
                 }
-            });
+            }
+            for (pos, sprite_path, effects) in triggered {
+                effects
+                    .iter()
+                    .for_each(|effect| self.apply_effect(effect, pos, &sprite_path));
+            }
         }
 
         match self.phase {
-            Phase::Buy => {
+            Phase::Buy if self.gamble => {
+                // Buy the card under the moving cursor and launch immediately
                 self.buy_timeout -= dt;
                 if self.buy_timeout <= 0.0 {
                     self.phase = Phase::LaunchSetAngle;
                 } else if input.left_mouse.is_released() {
                     self.phase = Phase::LaunchSetAngle;
 
-                    let index = self.buy_item.floor().clamp(0.0, 3.0) as usize;
-                    let card = self.card_options[index].clone();
-                    card.apply(self);
+                    let index = self.buy_item.floor().clamp(0.0, 2.0) as usize;
+                    if let Some(card) = self.card_options[index].clone() {
+                        card.apply(self, assets);
+                    }
                 }
 
                 self.pos = Vec2::zero();
@@ -230,6 +361,34 @@ impl GameState {
                     self.buy_item -= 3.0;
                 }
             }
+            Phase::Buy => {
+                if LAUNCH_BUTTON.is_clicked(input) || input.space.is_released() {
+                    self.phase = Phase::LaunchSetAngle;
+                } else if GAMBLE_BUTTON.is_clicked(input) || input.g.is_released() {
+                    self.gamble = true;
+                    self.buy_timeout = settings.buy_time;
+                } else if (REROLL_BUTTON.is_clicked(input) || input.r.is_released())
+                    && self.reroll_cost() <= self.money
+                {
+                    self.money -= self.reroll_cost();
+                    self.rerolls += 1;
+
+                    // Rerolling doesn't count as a shop visit for the rare guarantee
+                    self.roll_card_options(false, assets);
+                } else if input.left_mouse.is_released() {
+                    // Buy the clicked card, it stays in the shop so it can be bought multiple times
+                    if let Some(card) = self
+                        .hovered_card(input.mouse_pos)
+                        .and_then(|index| self.card_options[index].clone())
+                    {
+                        card.apply(self, assets);
+                    }
+                }
+
+                self.pos = Vec2::zero();
+                self.vel = Vec2::zero();
+                self.rot = 0.0;
+            }
             Phase::LaunchSetAngle => {
                 if input.left_mouse.is_released() {
                     self.phase = Phase::LaunchSetSpeed;
@@ -253,8 +412,23 @@ impl GameState {
                     self.sign = 1.0;
 
                     self.vel = Vec2::new(self.initial_angle.cos(), self.initial_angle.sin())
-                        * (self.initial_speed + self.extra_initial_speed);
+                        * (self.initial_speed + settings.extra_launch_speed);
+                    self.rot = self.initial_angle;
+                    self.ang_vel = 0.0;
+                    self.grounded = false;
+                    self.carrier = None;
+                    self.animation.trigger("fly");
+
+                    // Start above the ground, cards might have changed the flight model
+                    self.pos.y = -settings.flight.collider.h / 2.0;
+                    let dino = settings
+                        .flight
+                        .rigidbody_builder(self.pos, self.vel)
+                        .with_orientation(self.rot.into())
+                        .spawn(&mut self.physics);
+                    std::mem::replace(&mut self.dino, dino).delete(&mut self.physics);
                     self.boost_delay = settings.boost_delay;
+                    self.boost_charges = 0;
 
                     self.screen_shake_time = settings.screen_shake_launch.duration;
                     self.screen_shake_force = settings.screen_shake_launch.force;
@@ -268,16 +442,43 @@ impl GameState {
                 }
             }
             Phase::Fly => {
-                self.pos += self.vel * dt;
-                self.vel.y += (settings.gravity + self.extra_gravity) * dt;
-                self.vel *= settings.air_friction;
-                self.rot += (self.vel.x * settings.rot_factor.x
-                    + self
-                        .vel
-                        .y
-                        .clamp(-settings.rot_y_clamp, settings.rot_y_clamp)
-                        * settings.rot_factor.y)
-                    * dt;
+                // Birds and such move the player until they let go
+                if let Some(carrier) = &mut self.carrier {
+                    self.vel = carrier.vel;
+                    self.ang_vel = 0.0;
+
+                    carrier.time_left -= dt;
+                    if carrier.time_left <= 0.0 {
+                        self.carrier = None;
+                    }
+                } else if !self.grounded {
+                    // Spin in the air to land on the feet
+                    let spin = input.right.is_pressed() as i8 - input.left.is_pressed() as i8;
+                    self.ang_vel = settings.flight.spin(self.ang_vel, spin as f64, dt);
+                }
+
+                let impact = self.vel;
+                let landing_rot = self.rot;
+                self.terrain.update(self.pos.x, &settings.terrain);
+                let touching_ground = self.update_physics(dt);
+
+                // Landing feet first keeps the speed, a belly flop loses it
+                if touching_ground && !self.grounded {
+                    self.vel *= settings
+                        .flight
+                        .landing_speed(landing_rot.into(), self.terrain.normal(self.pos.x));
+                    self.animation.trigger("land");
+                }
+                self.grounded = touching_ground;
+
+                if self.shot_delay > 0.0 {
+                    self.shot_delay -= dt;
+                } else if input.space.is_released() {
+                    self.shoot(assets);
+                }
+
+                self.update_units(dt, assets);
+                self.update_projectiles(dt, assets);
 
                 self.boost += self.boost_sign * settings.boost_meter_speed * dt;
                 if self.boost_delay > 0.0 {
@@ -297,18 +498,26 @@ impl GameState {
                     self.boost = 0.0;
                 }
 
-                if self.boost_delay <= 0.0 && input.left_mouse.is_released() {
+                if (self.boost_delay <= 0.0 || self.boost_charges > 0)
+                    && input.left_mouse.is_released()
+                {
+                    // Skip the delay with a stored charge
+                    if self.boost_delay > 0.0 {
+                        self.boost_charges -= 1;
+                    }
+
                     let boost = if self.boost
                         > settings.boost_meter_safe_area + settings.boost_meter_penalty_area
                     {
                         for _ in 0..settings.crit_particle_amount {
                             self.particles.push(Particle::new(
-                                settings.player_offset,
-                                Vec2::zero(),
+                                self.pos,
+                                self.vel,
                                 settings.crit_particle_force,
                                 Color::DarkGreen,
-                                false,
+                                true,
                                 settings.crit_particle_life,
+                                &mut self.rng,
                             ));
                         }
 
@@ -322,6 +531,10 @@ impl GameState {
                     self.vel.x = (self.vel.x * boost.x).min(settings.max_boost_velocity.x);
                     self.vel.y = (self.vel.y * boost.y).min(settings.max_boost_velocity.y);
 
+                    if boost.x > 1.0 {
+                        self.animation.trigger("boost");
+                    }
+
                     if boost.x > 1.0
                         && self.vel.magnitude() > settings.static_velocity_boost_treshold
                     {
@@ -329,156 +542,268 @@ impl GameState {
                         self.vel.y += settings.static_velocity_boost.y * self.vel.y.signum();
                     }
 
-                    self.boost = fastrand::f64()
+                    self.boost = self.rng.f64()
                         * (settings.boost_meter_penalty_area
                             + settings.boost_meter_safe_area
                             + settings.boost_meter_crit_area);
                     self.boost_delay = settings.boost_delay;
                 }
 
-                if self.pos.y > 0.0 {
-                    if self.vel.x.abs() < settings.halting_velocity.x
-                        && self.vel.y.abs() < settings.halting_velocity.y
-                    {
-                        self.phase = Phase::Dead;
-                        self.dead_timeout = settings.dead_wait_time;
-                        self.max_distance = self.max_distance.max(self.pos.x);
-                    } else {
-                        self.screen_shake_time = settings.screen_shake_bounce.duration;
-                        self.screen_shake_force = settings.screen_shake_bounce.force * self.vel.y;
-
-                        self.pos.y = 0.0;
-                        self.vel.x *= settings.restitution.x;
-                        self.vel.y = -self.vel.y.abs() * settings.restitution.y;
-                        for _ in 0..settings.bounce_particle_amount {
-                            self.particles.push(Particle::new(
-                                settings.player_offset,
-                                self.vel * settings.bounce_particle_vel_multiplier,
-                                settings.bounce_particle_force,
-                                Color::Green,
-                                true,
-                                settings.bounce_particle_life,
-                            ));
-                        }
+                if touching_ground && settings.flight.is_halting(impact) {
+                    self.phase = Phase::Dead;
+                    self.dead_timeout = settings.dead_wait_time;
+                    self.max_distance = self.max_distance.max(self.pos.x);
+                    self.animation.trigger("dead");
+                } else if touching_ground {
+                    self.screen_shake_time = settings.screen_shake_bounce.duration;
+                    self.screen_shake_force = settings.screen_shake_bounce.force * impact.y;
+
+                    let color = self.terrain.surface(self.pos.x).particle_color;
+                    for _ in 0..settings.bounce_particle_amount {
+                        self.particles.push(Particle::new(
+                            self.pos,
+                            self.vel * settings.bounce_particle_vel_multiplier,
+                            settings.bounce_particle_force,
+                            color,
+                            true,
+                            settings.bounce_particle_life,
+                            &mut self.rng,
+                        ));
                     }
                 }
+
+                if self.phase == Phase::Fly {
+                    let clip = if self.grounded {
+                        "run"
+                    } else if self.ang_vel.abs() > settings.tumble_spin {
+                        "tumble"
+                    } else {
+                        "fly"
+                    };
+                    self.animation
+                        .play(clip, &assets.animated_sprite(DINO_SPRITE));
+                }
+                self.update_animation(dt, assets);
             }
             Phase::Dead => {
+                self.update_animation(dt, assets);
+
                 self.dead_timeout -= dt;
                 if self.dead_timeout <= 0.0 {
-                    self.switch_to_buy();
+                    self.switch_to_buy(assets);
                 }
             }
         }
     }
 
     /// Draw a frame.
-    pub fn render(&mut self, canvas: &mut [u32], _frame_time: f64) {
-        let settings = crate::settings();
+    pub fn render(&mut self, canvas: &mut Canvas, _frame_time: f64, assets: &Assets) {
+        let mut draw_list = DrawList::new();
+        self.submit(&mut draw_list, assets);
+        draw_list.render(canvas);
+    }
 
-        self.clouds
-            .iter_mut()
-            .chain(self.trees.iter_mut())
-            .chain(self.disks.iter_mut())
-            .chain(self.bombs.iter_mut())
-            .for_each(|obj| obj.render(canvas, self.screen_shake_pos));
-
-        let ground_height = (settings.player_offset.y - self.pos.y + self.screen_shake_pos.y)
-            .clamp(0.0, SIZE.h as f64) as usize;
-        canvas[(ground_height * SIZE.w)..].fill(Color::LightGreen.as_u32());
-        let edge_ground_height = (settings.player_offset.y - self.pos.y - 3.0
-            + self.screen_shake_pos.y)
-            .clamp(0.0, SIZE.h as f64) as usize;
-        canvas[(edge_ground_height * SIZE.w)..(ground_height * SIZE.w)].fill(Color::Green.as_u32());
-
-        self.rocks
-            .iter_mut()
-            .for_each(|obj| obj.render(canvas, self.screen_shake_pos));
+    /// Queue everything visible on the layer it's drawn on.
+    fn submit<'a>(&'a self, draw_list: &mut DrawList<'a>, assets: &'a Assets) {
+        let settings = &self.settings;
+        let camera = &self.camera;
 
-        if self.phase != Phase::Buy {
-            crate::sprite("flag").render(
-                canvas,
-                -self.pos
-                    + settings.player_offset
-                    + self.screen_shake_pos
-                    + (self.max_distance, -40.0),
-            );
+        // Objects on the world layer are submitted first so they are behind the ground
+        for obj in &self.objects {
+            draw_list.submit(obj.layer, move |canvas| obj.render(canvas, camera, assets));
         }
 
-        self.particles
-            .iter()
-            .for_each(|particle| particle.render(canvas));
+        draw_list.submit(DrawLayer::World, move |canvas| {
+            self.terrain.render(canvas, camera, assets)
+        });
 
-        //crate::render_aabr(settings.player_collider.into_aabr(), canvas, 0xFFFF0000);
+        for unit in &self.units {
+            draw_list.submit(DrawLayer::Actors, move |canvas| {
+                unit.render(canvas, camera, assets)
+            });
+        }
+        for projectile in &self.projectiles {
+            draw_list.submit(DrawLayer::Actors, move |canvas| {
+                projectile.render(&self.physics, canvas, camera, assets)
+            });
+        }
 
-        match self.phase {
-            Phase::Buy => {
-                crate::sprite("buy-screen").render(canvas, Vec2::zero());
-                let font = crate::font();
-                let item_str = "Skipped in:";
-                font.render(
-                    item_str,
+        if self.phase != Phase::Buy {
+            draw_list.submit(DrawLayer::Actors, move |canvas| {
+                assets.sprite("flag").render(
+                    canvas,
+                    camera,
                     Vec2::new(
-                        SIZE.w / 2 - item_str.len() * font.char_size.w as usize / 2,
-                        2,
-                    )
-                    .as_(),
+                        self.max_distance,
+                        self.terrain.height(self.max_distance) - 40.0,
+                    ),
+                )
+            });
+        }
+
+        if matches!(self.phase, Phase::Dead | Phase::Fly) {
+            draw_list.submit(DrawLayer::Actors, move |canvas| {
+                self.animation.render(
+                    Iso::new(self.pos, self.rot),
                     canvas,
-                );
-                font.render(
-                    &format!("{}", self.buy_timeout.round()),
-                    Vec2::new(SIZE.w / 2 - font.char_size.w as usize / 2, 18).as_(),
+                    camera,
+                    &assets.animated_sprite(DINO_SPRITE),
+                )
+            });
+
+            if let Some(carrier) = &self.carrier {
+                draw_list.submit(DrawLayer::Actors, move |canvas| {
+                    let sprite = assets.sprite(&carrier.sprite_path);
+                    sprite.render(
+                        canvas,
+                        camera,
+                        self.pos
+                            - Vec2::new(
+                                sprite.width() as f64 / 2.0,
+                                sprite.height() as f64 + settings.flight.collider.h,
+                            ),
+                    );
+                });
+            }
+        }
+
+        if self.phase != Phase::Buy && self.pos.x < SIZE.w as f64 {
+            draw_list.submit(DrawLayer::Actors, move |canvas| {
+                assets.rotatable_sprite("cannon").render(
+                    Iso::new(
+                        settings.cannon_offset - settings.player_offset,
+                        self.initial_angle + std::f64::consts::FRAC_PI_2,
+                    ),
                     canvas,
-                );
+                    camera,
+                )
+            });
+        }
+
+        draw_list.submit(DrawLayer::Effects, move |canvas| {
+            self.particles
+                .iter()
+                .for_each(|particle| particle.render(canvas, camera));
+
+            //crate::render_aabr(settings.player_collider.into_aabr(), canvas, 0xFFFF0000);
+        });
 
-                let index = self.buy_item.floor().clamp(0.0, 3.0) as usize;
-                crate::sprite("buy-screen-selected-card")
-                    .render(canvas, Vec2::new([20, 116, 212][index] as f64, 64.0));
+        draw_list.submit(DrawLayer::Hud, move |canvas| {
+            self.render_hud(canvas, assets)
+        });
+    }
 
-                let buy_offset: Vec2<usize> = (settings.buy_meter_offset).as_();
-                for y in buy_offset.y..(buy_offset.y + settings.buy_meter_size.h as usize - 4) {
-                    let start = y * SIZE.w + buy_offset.x;
+    /// Draw the interface.
+    fn render_hud(&self, canvas: &mut Canvas, assets: &Assets) {
+        let settings = &self.settings;
+
+        match self.phase {
+            Phase::Buy => {
+                assets
+                    .sprite("buy-screen")
+                    .render_screen(canvas, Vec2::zero());
+
+                let selected = if self.gamble {
+                    let font = assets.font();
+                    let item_str = "Skipped in:";
+                    font.render(
+                        item_str,
+                        Vec2::new(
+                            SIZE.w / 2 - item_str.len() * font.char_size.w as usize / 2,
+                            2,
+                        )
+                        .as_(),
+                        canvas,
+                    );
+                    font.render(
+                        &format!("{}", self.buy_timeout.round()),
+                        Vec2::new(SIZE.w / 2 - font.char_size.w as usize / 2, 18).as_(),
+                        canvas,
+                    );
+
+                    let buy_offset: Vec2<i32> = (settings.buy_meter_offset).as_();
                     let buy_frac = self.buy_item / 3.0;
-                    let x4 = start + (buy_frac * (settings.buy_meter_size.w - 4.0)) as usize;
-                    canvas[x4..(x4 + 3)].fill(Color::White.as_u32());
+                    canvas.fill_rect(
+                        Rect::new(
+                            buy_offset.x + (buy_frac * (settings.buy_meter_size.w - 4.0)) as i32,
+                            buy_offset.y,
+                            3,
+                            settings.buy_meter_size.h as i32 - 4,
+                        ),
+                        Color::White.as_u32(),
+                    );
+
+                    Some(self.buy_item.floor().clamp(0.0, 2.0) as usize)
+                } else {
+                    let reroll_cost = self.reroll_cost();
+                    REROLL_BUTTON.render(
+                        &format!("Reroll {reroll_cost}"),
+                        reroll_cost <= self.money,
+                        self.mouse_pos,
+                        canvas,
+                        assets,
+                    );
+                    GAMBLE_BUTTON.render("Gamble", true, self.mouse_pos, canvas, assets);
+                    LAUNCH_BUTTON.render("Launch", true, self.mouse_pos, canvas, assets);
+
+                    self.hovered_card(self.mouse_pos)
+                };
+
+                if let Some(index) = selected {
+                    assets
+                        .sprite("buy-screen-selected-card")
+                        .render_screen(canvas, Vec2::new(CARD_X[index], CARD_Y));
                 }
 
-                self.card_options[0].render(Vec2::new(20.0, 64.0), canvas, &self.selected_cards);
-                self.card_options[1].render(Vec2::new(116.0, 64.0), canvas, &self.selected_cards);
-                self.card_options[2].render(Vec2::new(212.0, 64.0), canvas, &self.selected_cards);
+                for (card, x) in self.card_options.iter().zip(CARD_X) {
+                    if let Some(card) = card {
+                        card.render(
+                            Vec2::new(x, CARD_Y),
+                            canvas,
+                            &self.selected_cards,
+                            card.can_buy(self.money, &self.selected_cards),
+                            assets,
+                        );
+                    }
+                }
 
                 let pos = Vec2::new(3, 3).as_();
-                crate::font().render(&format!("  {}", self.money,), pos, canvas);
+                assets
+                    .font()
+                    .render(&format!("  {}", self.money,), pos, canvas);
 
-                let disk = crate::sprite("disk-icon");
-                disk.render(canvas, pos - (1.0, 1.0));
+                let disk = assets.sprite("disk-icon");
+                disk.render_screen(canvas, pos - (1.0, 1.0));
             }
             Phase::LaunchSetAngle => {
-                crate::font().render("Click to set the angle!", Vec2::new(10, 10).as_(), canvas);
+                self.render_text_panel("Click to set the angle!", canvas, assets);
             }
             Phase::LaunchSetSpeed => {
-                crate::font().render("Click to set the speed!", Vec2::new(10, 10).as_(), canvas);
-                let speed_offset: Vec2<usize> = (settings.speed_meter_offset).as_();
-
-                let speed_bar = crate::sprite("speed-bar");
-                speed_bar.render(canvas, speed_offset.as_() - (2.0, 2.0));
-                for y in speed_offset.y..(speed_offset.y + speed_bar.height() as usize - 4) {
-                    let start = y * SIZE.w + speed_offset.x;
-                    let x4 = start
-                        + ((self.initial_speed - settings.min_speed)
-                            / (settings.max_speed - settings.min_speed)
-                            * (speed_bar.width() - 4) as f64) as usize;
-                    canvas[x4..(x4 + 3)].fill(Color::White.as_u32());
-                }
+                self.render_text_panel("Click to set the speed!", canvas, assets);
+                let speed_offset: Vec2<i32> = (settings.speed_meter_offset).as_();
+
+                let speed_bar = assets.sprite("speed-bar");
+                speed_bar.render_screen(canvas, speed_offset.as_() - (2.0, 2.0));
+                let speed_frac = (self.initial_speed - settings.min_speed)
+                    / (settings.max_speed - settings.min_speed);
+                canvas.fill_rect(
+                    Rect::new(
+                        speed_offset.x + (speed_frac * (speed_bar.width() - 4) as f64) as i32,
+                        speed_offset.y,
+                        3,
+                        speed_bar.height() as i32 - 4,
+                    ),
+                    Color::White.as_u32(),
+                );
             }
             Phase::Dead | Phase::Fly => {
-                crate::rotatable_sprite("dino1").render(
-                    Iso::new(settings.player_offset + self.screen_shake_pos, self.rot),
+                self.render_panel(
                     canvas,
+                    Rect::new(0, 0, SIZE.w as i32, assets.font().char_size.h as i32 + 5),
                 );
 
                 let pos = Vec2::new(3, 3).as_();
-                crate::font().render(
+                assets.font().render(
                     &format!(
                         "  {:<4} [ {:<7} ] {}",
                         self.money,
@@ -489,93 +814,571 @@ impl GameState {
                     canvas,
                 );
 
-                let disk = crate::sprite("disk-icon");
-                disk.render(canvas, pos - (1.0, 1.0));
+                let disk = assets.sprite("disk-icon");
+                disk.render_screen(canvas, pos - (1.0, 1.0));
             }
         }
 
-        if self.phase == Phase::Fly && self.boost_delay <= 0.0 {
-            let boost_offset: Vec2<usize> = (settings.boost_meter_offset).as_();
+        if self.phase == Phase::Fly && (self.boost_delay <= 0.0 || self.boost_charges > 0) {
+            let boost_offset: Vec2<i32> = (settings.boost_meter_offset).as_();
+
+            let boost_bar = assets.sprite("boost-bar");
+            boost_bar.render_screen(canvas, boost_offset.as_() - (2.0, 2.0));
+            let boost_frac = self.boost
+                / (settings.boost_meter_safe_area
+                    + settings.boost_meter_penalty_area
+                    + settings.boost_meter_crit_area);
+            canvas.fill_rect(
+                Rect::new(
+                    boost_offset.x + (boost_frac * (boost_bar.width() as f64 - 4.0)) as i32,
+                    boost_offset.y,
+                    3,
+                    boost_bar.height() as i32 - 4,
+                ),
+                Color::White.as_u32(),
+            );
 
-            let boost_bar = crate::sprite("boost-bar");
-            boost_bar.render(canvas, boost_offset.as_() - (2.0, 2.0));
-            for y in boost_offset.y..(boost_offset.y + boost_bar.height() as usize - 4) {
-                let start = y * SIZE.w + boost_offset.x;
-                let boost_frac = self.boost
-                    / (settings.boost_meter_safe_area
-                        + settings.boost_meter_penalty_area
-                        + settings.boost_meter_crit_area);
-                let x4 = start + (boost_frac * (boost_bar.width() as f64 - 4.0)) as usize;
-                canvas[x4..(x4 + 3)].fill(Color::White.as_u32());
+            // Stored charges below the bar
+            for charge in 0..self.boost_charges as i32 {
+                canvas.fill_rect(
+                    Rect::new(
+                        boost_offset.x + charge * 5,
+                        boost_offset.y + boost_bar.height() as i32,
+                        3,
+                        3,
+                    ),
+                    Color::Yellow.as_u32(),
+                );
             }
         }
 
         if self.phase == Phase::Dead {
-            crate::font().render_centered(
+            self.render_panel(
+                canvas,
+                Rect::new(0, SIZE.h as i32 / 2 - 56, SIZE.w as i32, 42),
+            );
+
+            assets.font().render_centered(
                 &format!("Distance: {}", self.pos.x.round()),
                 Vec2::new(SIZE.w as f64 / 2.0, SIZE.h as f64 / 2.0 - 50.0),
                 canvas,
             );
-            crate::font().render_centered(
+            assets.font().render_centered(
                 &format!("Max Distance: {}", self.max_distance.round()),
                 Vec2::new(SIZE.w as f64 / 2.0, SIZE.h as f64 / 2.0 - 30.0),
                 canvas,
             );
         }
+    }
 
-        if self.phase != Phase::Buy && self.pos.x < SIZE.w as f64 {
-            crate::rotatable_sprite("cannon").render(
-                Iso::new(
-                    -self.pos + settings.cannon_offset + self.screen_shake_pos,
-                    self.initial_angle + std::f64::consts::FRAC_PI_2,
-                ),
-                canvas,
+    /// Draw a line of text in the top left on a panel.
+    fn render_text_panel(&self, text: &str, canvas: &mut Canvas, assets: &Assets) {
+        let font = assets.font();
+        self.render_panel(
+            canvas,
+            Rect::new(
+                6,
+                6,
+                (text.len() * font.char_size.w as usize) as i32 + 8,
+                font.char_size.h as i32 + 8,
+            ),
+        );
+
+        font.render(text, Vec2::new(10, 10).as_(), canvas);
+    }
+
+    /// Darken an area of the screen so the interface on top of it is readable over the world.
+    fn render_panel(&self, canvas: &mut Canvas, rect: Rect<i32, i32>) {
+        canvas.blend_rect(
+            rect,
+            Color::Black.as_u32(),
+            Blend::new(BlendMode::Alpha, self.settings.hud_panel_opacity),
+        );
+    }
+
+    /// Apply the effects of all bought cards to the settings.
+    pub fn update_settings(&mut self, assets: &Assets) {
+        let base = assets.settings();
+        self.settings_reload_ids = assets.settings_reload_ids(&self.card_ids).collect();
+
+        self.settings = Rc::new(
+            base.with_card_effects(&assets.cards(), &self.selected_cards)
+                .unwrap_or_else(|err| {
+                    log::error!("Error applying card effects: {err:?}");
+
+                    base.clone()
+                }),
+        );
+    }
+
+    /// Settings with the effects of all bought cards applied.
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Price of rerolling the offered cards, increases with every reroll in the same shop.
+    pub fn reroll_cost(&self) -> usize {
+        self.settings.reroll_cost + self.rerolls * self.settings.reroll_cost_increase
+    }
+
+    /// Collision box of the player in the world.
+    fn player_collider(&self) -> Rect<f64, f64> {
+        let settings = &self.settings;
+
+        // The collider is set relative to where the player is drawn without camera movement
+        Rect {
+            x: settings.player_collider.x + self.pos.x - settings.player_offset.x,
+            y: settings.player_collider.y + self.pos.y - settings.player_offset.y,
+            ..settings.player_collider
+        }
+    }
+
+    /// Spawn units ahead of the player, let them throw projectiles and handle everything hitting the player.
+    fn update_units(&mut self, dt: f64, assets: &Assets) {
+        puffin::profile_scope!("Update units");
+
+        let view = self.camera.view();
+        let unit_type = UnitType::EnemySpear;
+
+        // Spawn units before they come into view
+        while self.next_unit_x < view.max.x + view.size().w {
+            let x = self.next_unit_x;
+            self.units.push(Unit::new(
+                Vec2::new(x, self.terrain.height(x)),
+                unit_type,
+                assets,
+            ));
+            self.next_unit_x += unit_type
+                .settings(assets)
+                .spawn_distance
+                .value(&mut self.rng)
+                .max(1.0);
+        }
+
+        let target = Body {
+            pos: self.pos,
+            vel: self.vel,
+        };
+        let player_collider = self.player_collider();
+
+        let mut knocked_over = Vec::new();
+        for unit in self.units.iter_mut() {
+            if let Some(projectile) = unit.update(
+                &self.terrain,
+                target,
+                dt,
+                &mut self.rng,
+                &mut self.physics,
+                assets,
+            ) {
+                self.projectiles.push(projectile);
+            }
+
+            let money = unit.hit_by(player_collider, self.vel, assets);
+            if money > 0 {
+                self.money += money;
+                knocked_over.push(unit.collider(assets).center());
+            }
+        }
+        knocked_over
+            .into_iter()
+            .for_each(|pos| self.knock_over(pos));
+
+        // Forget units that are knocked over or left far behind
+        self.units
+            .retain(|unit| !unit.is_knocked_over() && unit.pos().x > view.min.x - view.size().w);
+    }
+
+    /// Move all projectiles and handle everything they hit.
+    fn update_projectiles(&mut self, dt: f64, assets: &Assets) {
+        puffin::profile_scope!("Update projectiles");
+
+        let player_collider = self.player_collider();
+
+        let mut triggered = Vec::new();
+        let mut knocked_over = Vec::new();
+        let mut bursts = Vec::new();
+
+        let removed = self
+            .projectiles
+            .extract_if(.., |projectile| {
+                if !projectile.update(&self.terrain, &mut self.physics, dt) {
+                    return true;
+                }
+
+                let physics = &self.physics;
+                let pos = projectile.body(physics).pos;
+                match projectile.allegiance {
+                    Allegiance::Enemy => {
+                        if projectile.collides(player_collider, physics, assets) {
+                            self.vel *= projectile.settings(assets).hit_slowdown;
+                            bursts.push((pos, Color::DarkGray));
+
+                            return true;
+                        }
+                    }
+                    Allegiance::Player => {
+                        if let Some(unit) = self.units.iter_mut().find(|unit| {
+                            projectile.collides(unit.collider(assets), physics, assets)
+                        }) {
+                            let money = unit.damage(projectile.settings(assets).damage, assets);
+                            if money > 0 {
+                                self.money += money;
+                                knocked_over.push(unit.collider(assets).center());
+                            } else {
+                                bursts.push((pos, Color::DarkGray));
+                            }
+
+                            return true;
+                        }
+                    }
+                }
+
+                // Projectiles can set off objects, but never move the player with them
+                let allegiance = projectile.allegiance;
+                if let Some(obj) = self.objects.iter_mut().find(|obj| {
+                    obj.on_collision
+                        .iter()
+                        .any(|effect| effect.is_triggered_by(allegiance))
+                        && projectile.collides(obj.collider(), physics, assets)
+                }) {
+                    let effects = obj
+                        .on_collision
+                        .iter()
+                        .filter(|effect| effect.is_triggered_by(allegiance))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    triggered.push((obj.pos, obj.sprite_path().to_string(), effects));
+                    obj.reset(self.pos, self.vel, &self.camera, &mut self.rng);
+
+                    return true;
+                }
+
+                false
+            })
+            .collect::<Vec<_>>();
+        removed
+            .into_iter()
+            .for_each(|projectile| projectile.delete(&mut self.physics));
+
+        for (pos, sprite_path, effects) in triggered {
+            effects
+                .iter()
+                .for_each(|effect| self.apply_effect(effect, pos, &sprite_path));
+        }
+        knocked_over
+            .into_iter()
+            .for_each(|pos| self.knock_over(pos));
+        bursts
+            .into_iter()
+            .for_each(|(pos, color)| self.particle_burst(pos, color));
+    }
+
+    /// Advance the animation of the player and handle the events of its frames.
+    fn update_animation(&mut self, dt: f64, assets: &Assets) {
+        let sprite = assets.animated_sprite(DINO_SPRITE);
+        let Some(event) = self.animation.update(dt, &sprite) else {
+            return;
+        };
+
+        match event {
+            // Kick up some dirt when a foot hits the ground
+            "step" => {
+                let settings = &self.settings;
+
+                let feet = self.pos + Vec2::new(0.0, settings.flight.collider.h / 2.0);
+                let color = self.terrain.surface(self.pos.x).particle_color;
+                for _ in 0..settings.step_particle_amount {
+                    self.particles.push(Particle::new(
+                        feet,
+                        -self.vel * settings.bounce_particle_vel_multiplier,
+                        settings.bounce_particle_force,
+                        color,
+                        true,
+                        settings.bounce_particle_life,
+                        &mut self.rng,
+                    ));
+                }
+            }
+            other => log::warn!("Unknown animation event '{other}'"),
+        }
+    }
+
+    /// Move the rigid body of the player.
+    ///
+    /// Returns whether it touched the ground.
+    fn update_physics(&mut self, dt: f64) -> bool {
+        puffin::profile_scope!("Update physics");
+
+        let settings = &self.settings;
+
+        // The ground under the player decides how it bounces
+        if let Some((ground_pos, ground_shape)) = self.terrain.changed_collider() {
+            self.ground.set_position(ground_pos, &mut self.physics);
+            self.ground.set_shape(ground_shape, &mut self.physics);
+        }
+        let surface = self.terrain.surface(self.pos.x);
+        self.ground.set_material(
+            surface.restitution * settings.restitution,
+            surface.friction * settings.friction,
+            &mut self.physics,
+        );
+
+        // Boosts, bombs and projectiles change the velocity directly
+        self.dino.set_position(self.pos, &mut self.physics);
+        self.dino.set_velocity(self.vel, &mut self.physics);
+        self.dino
+            .set_angular_velocity(self.ang_vel, &mut self.physics);
+
+        self.physics.move_grid(self.pos, &settings.physics);
+        self.physics.step(dt, &settings.physics);
+
+        self.pos = self.dino.position(&self.physics);
+        self.vel = self.dino.velocity(&self.physics);
+        self.rot = self.dino.orientation(&self.physics).to_radians();
+        self.ang_vel = self.dino.angular_velocity(&self.physics);
+
+        self.dino
+            .collision_keys_iter(&self.physics)
+            .any(|key| self.ground == key)
+    }
+
+    /// Fire a projectile backwards, pushing the player forward.
+    fn shoot(&mut self, assets: &Assets) {
+        let settings = &self.settings;
+
+        let Some(dir) = self.vel.try_normalized() else {
+            return;
+        };
+
+        self.projectiles.push(Projectile::new(
+            self.pos,
+            self.vel - dir * settings.shot_speed,
+            &settings.shot_projectile,
+            Allegiance::Player,
+            &mut self.physics,
+            assets,
+        ));
+        self.vel += dir * settings.shot_impulse;
+        self.shot_delay = settings.shot_delay;
+    }
+
+    /// Apply the effect of an object at a position the player collided with.
+    fn apply_effect(&mut self, effect: &Effect, pos: Vec2<f64>, sprite_path: &str) {
+        let settings = &self.settings;
+
+        match effect {
+            Effect::Money { amount } => {
+                self.money += amount;
+
+                for _ in 0..settings.topleft_particle_amount {
+                    self.particles.push(Particle::new(
+                        Vec2::new(33.0, 10.0),
+                        Vec2::zero(),
+                        settings.topleft_particle_force,
+                        Color::White,
+                        false,
+                        settings.topleft_particle_life,
+                        &mut self.rng,
+                    ));
+                }
+
+                self.screen_shake_time = settings.screen_shake_disk.duration;
+                self.screen_shake_force = settings.screen_shake_disk.force;
+
+                self.particle_burst(pos, Color::Brown);
+            }
+            Effect::Impulse { force } => {
+                let force = force.unwrap_or(settings.bomb_force) * settings.impulse_multiplier;
+                self.vel.x += force.x;
+                if self.vel.y.is_sign_positive() {
+                    self.vel.y = -force.y;
+                } else {
+                    self.vel.y -= force.y;
+                }
+            }
+            Effect::SlowDown { factor } => self.vel *= *factor,
+            Effect::BonusBoost { boost } => {
+                self.vel.x = (self.vel.x * boost.x).min(settings.max_boost_velocity.x);
+                self.vel.y = (self.vel.y * boost.y).min(settings.max_boost_velocity.y);
+            }
+            Effect::Explode => self.explode(pos),
+            Effect::Refuel => {
+                self.boost_charges = (self.boost_charges + 1).min(settings.max_boost_charges)
+            }
+            Effect::Carry { duration, velocity } => {
+                self.carrier = Some(Carrier {
+                    sprite_path: sprite_path.to_string(),
+                    vel: *velocity,
+                    time_left: *duration,
+                })
+            }
+        }
+    }
+
+    /// Explode a bomb, blowing a crater in the ground below it.
+    fn explode(&mut self, pos: Vec2<f64>) {
+        let settings = &self.settings;
+
+        for i in 0..settings.bomb_particle_amount {
+            // Half of the particles is smoke, the other half is glowing fire
+            let (color, blend_mode) = if i < settings.bomb_particle_amount / 2 {
+                (Color::DarkGray, BlendMode::Alpha)
+            } else {
+                (Color::Orange, BlendMode::Additive)
+            };
+
+            self.particles.push(
+                Particle::new(
+                    pos,
+                    self.vel * settings.bomb_particle_vel_multiplier,
+                    settings.bomb_particle_force,
+                    color,
+                    true,
+                    settings.bomb_particle_life,
+                    &mut self.rng,
+                )
+                .with_blend_mode(blend_mode),
             );
         }
+
+        self.screen_shake_time = settings.screen_shake_bomb.duration;
+        self.screen_shake_force = settings.screen_shake_bomb.force;
+
+        self.terrain.carve(
+            Vec2::new(pos.x, self.terrain.height(pos.x)),
+            settings.bomb_crater_radius,
+        );
     }
 
-    fn switch_to_buy(&mut self) {
-        let settings = crate::settings();
+    /// Show a unit getting knocked over.
+    fn knock_over(&mut self, pos: Vec2<f64>) {
+        self.particle_burst(pos, Color::Brown);
+
+        self.screen_shake_time = self.settings.screen_shake_disk.duration;
+        self.screen_shake_force = self.settings.screen_shake_disk.force;
+    }
+
+    /// Spawn particles flying away from a position.
+    fn particle_burst(&mut self, pos: Vec2<f64>, color: Color) {
+        let settings = &self.settings;
+
+        for _ in 0..settings.particle_amount {
+            self.particles.push(Particle::new(
+                pos,
+                self.vel * settings.particle_vel_multiplier,
+                settings.particle_force,
+                color,
+                true,
+                settings.particle_life,
+                &mut self.rng,
+            ));
+        }
+    }
+
+    /// Index of the offered card the mouse is on top of.
+    fn hovered_card(&self, mouse_pos: Vec2<i32>) -> Option<usize> {
+        CARD_X.iter().position(|x| {
+            Rect::new(*x, CARD_Y, CARD_SIZE.w, CARD_SIZE.h).contains_point(mouse_pos.as_())
+        })
+    }
+
+    /// Pick new cards to offer.
+    ///
+    /// Returns whether a rare card is offered.
+    fn roll_card_options(&mut self, guarantee_rare: bool, assets: &Assets) -> bool {
+        let settings = &self.settings;
+
+        let mut offers = Card::offers(
+            self.card_options.len(),
+            &self.selected_cards,
+            guarantee_rare,
+            &settings.rarity_weight,
+            &mut self.rng,
+            assets,
+        )
+        .into_iter();
+
+        let has_rare = offers
+            .as_slice()
+            .iter()
+            .any(|card| card.rarity == Rarity::Rare);
+
+        self.card_options = [offers.next(), offers.next(), offers.next()];
+
+        has_rare
+    }
+
+    fn switch_to_buy(&mut self, assets: &Assets) {
+        let settings = &self.settings;
 
         self.phase = Phase::Buy;
+        self.gamble = false;
         self.buy_timeout = settings.buy_time;
-        self.buy_item = fastrand::f64() * 3.0;
+        self.buy_item = self.rng.f64() * 3.0;
 
         self.initial_angle = settings.min_angle;
         self.initial_speed = settings.min_speed;
 
-        self.clouds
+        // Look at the launch position again
+        self.pos = Vec2::zero();
+        self.vel = Vec2::zero();
+        self.camera.teleport(self.pos, settings.player_offset);
+
+        self.objects
             .iter_mut()
-            .chain(self.trees.iter_mut())
-            .for_each(|obj| obj.reset(Vec2::zero(), Vec2::zero()));
+            .for_each(|obj| obj.reset(Vec2::zero(), Vec2::zero(), &self.camera, &mut self.rng));
+        self.terrain = Terrain::new(&mut self.rng, &settings.terrain, assets.surfaces());
+        self.units.clear();
+        self.projectiles
+            .drain(..)
+            .for_each(|projectile| projectile.delete(&mut self.physics));
+        self.next_unit_x = UnitType::EnemySpear.settings(assets).min_spawn_distance;
+
+        // Guarantee a rare card when none was offered for too many shop visits
+        self.rerolls = 0;
+        let guarantee_rare =
+            settings.rare_pity > 0 && self.shops_without_rare >= settings.rare_pity;
+        if self.roll_card_options(guarantee_rare, assets) {
+            self.shops_without_rare = 0;
+        } else {
+            self.shops_without_rare += 1;
+        }
 
-        self.card_options.iter_mut().for_each(|card| {
-            *card = Card::random(self.money, &self.selected_cards);
-        });
+        // Persist the progression
+        if let Some(storage) = &self.storage {
+            if let Err(err) = storage.save(&SaveData::from_game(self)) {
+                log::error!("Error saving progression: {err:?}");
+            }
+        }
     }
 }
 
 /// Game settings loaded from a file so it's easier to change them with hot-reloading.
-#[derive(Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Settings {
+    /// Fixed seed for the random number generator, a random one is picked when not set.
+    #[serde(default)]
+    pub seed: Option<u64>,
     pub min_angle: f64,
     pub max_angle: f64,
     pub angle_delta: f64,
     pub min_speed: f64,
     pub max_speed: f64,
     pub speed_delta: f64,
-    pub gravity: f64,
+    /// Speed added to the launch speed.
+    #[serde(default)]
+    pub extra_launch_speed: f64,
     pub cannon_offset: Vec2<f64>,
     pub player_offset: Vec2<f64>,
     pub boost_meter_offset: Vec2<f64>,
     pub speed_meter_offset: Vec2<f64>,
     pub buy_meter_offset: Vec2<f64>,
     pub buy_meter_size: Extent2<f64>,
-    pub rot_factor: Vec2<f64>,
-    pub rot_y_clamp: f64,
-    pub air_friction: f64,
-    pub restitution: Vec2<f64>,
-    pub halting_velocity: Vec2<f64>,
+    /// Opacity of the dark panels behind the text drawn over the world.
+    pub hud_panel_opacity: f64,
+    /// Object spawners filling the world, in the order they are drawn in.
+    pub objects: Vec<String>,
     pub tree_amount: usize,
     pub rock_amount: usize,
     pub boost_meter_speed: f64,
@@ -586,6 +1389,8 @@ pub struct Settings {
     pub boost_crit: Vec2<f64>,
     pub boost_safe: Vec2<f64>,
     pub boost_delay: f64,
+    /// Most boosts fuel cans can store at the same time.
+    pub max_boost_charges: usize,
     pub max_boost_velocity: Vec2<f64>,
     pub static_velocity_boost: Vec2<f64>,
     pub static_velocity_boost_treshold: f64,
@@ -608,6 +1413,8 @@ pub struct Settings {
     pub bounce_particle_force: f64,
     pub bounce_particle_vel_multiplier: Vec2<f64>,
     pub bounce_particle_life: f64,
+    /// Particles kicked up every step the player makes on the ground.
+    pub step_particle_amount: usize,
     pub bomb_particle_amount: usize,
     pub bomb_particle_gravity: f64,
     pub bomb_particle_force: f64,
@@ -621,7 +1428,71 @@ pub struct Settings {
     pub screen_shake_bomb: ScreenShake,
     pub screen_shake_disk: ScreenShake,
     pub attraction_distance: f64,
+    /// How fast items within the attraction distance move towards the player.
+    #[serde(default)]
+    pub attraction: f64,
+    /// Force of impulse effects without their own force, like bombs.
     pub bomb_force: Vec2<f64>,
+    /// Multiplier of the force of all impulse effects.
+    pub impulse_multiplier: Vec2<f64>,
+    /// Radius of the crater a bomb explosion leaves in the ground below it.
+    pub bomb_crater_radius: f64,
+    /// Projectile the player shoots backwards.
+    pub shot_projectile: String,
+    /// Speed the projectile is shot backwards with, relative to the player.
+    pub shot_speed: f64,
+    /// Speed the player gains forwards when shooting.
+    pub shot_impulse: f64,
+    /// Seconds between shots.
+    pub shot_delay: f64,
+    /// Chance of every card rarity tier being offered.
+    pub rarity_weight: RarityWeight,
+    /// After how many shops without a rare card one is guaranteed, `0` disables it.
+    pub rare_pity: usize,
+    /// Price of the first reroll of the offered cards in a shop.
+    pub reroll_cost: usize,
+    /// How much more every next reroll in the same shop costs.
+    pub reroll_cost_increase: usize,
+    /// Radians per second above which the player tumbles.
+    pub tumble_spin: f64,
+    /// Multiplier of the restitution of every surface, so cards can change how much the player bounces.
+    pub restitution: f64,
+    /// Multiplier of the friction of every surface.
+    pub friction: f64,
+    /// Flight model of the player.
+    pub flight: Flight,
+    /// Rigid body simulation.
+    pub physics: crate::physics::Settings,
+    /// Shape of the ground.
+    pub terrain: crate::terrain::Settings,
+    /// How the view follows the player.
+    pub camera: crate::camera::Settings,
+}
+
+impl Settings {
+    /// Apply the effects of the bought cards.
+    ///
+    /// Cards are applied in order of their name, each as many times as it's bought.
+    pub fn with_card_effects(
+        &self,
+        cards: &[Card],
+        selected_cards: &BTreeMap<String, usize>,
+    ) -> Result<Self> {
+        let mut table = match toml::Value::try_from(self).into_diagnostic()? {
+            toml::Value::Table(table) => table,
+            _ => unreachable!("Settings always serializes to a table"),
+        };
+
+        for card in cards {
+            for _ in 0..card.amount_bought(selected_cards) {
+                for effect in &card.effects {
+                    effect.apply(&mut table)?;
+                }
+            }
+        }
+
+        toml::Value::Table(table).try_into().into_diagnostic()
+    }
 }
 
 impl Asset for Settings {
@@ -630,8 +1501,516 @@ impl Asset for Settings {
     type Loader = TomlLoader;
 }
 /// Game settings loaded from a file so it's easier to change them with hot-reloading.
-#[derive(Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ScreenShake {
     pub force: f64,
     pub duration: f64,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, rc::Rc};
+
+    use vek::Vec2;
+
+    use super::{GameState, Phase, CARD_X, CARD_Y};
+    use crate::{
+        assets::Assets,
+        card::{Card, CARD_SIZE},
+        input::{ButtonState, Input},
+        object::{Effect, ObjectsSpawner},
+        projectile::Projectile,
+        unit::{Allegiance, Unit, UnitType},
+        UPDATES_PER_SECOND,
+    };
+
+    /// Update the game for a single tick.
+    fn tick(game: &mut GameState, input: &mut Input, assets: &Assets) {
+        game.update(input, (UPDATES_PER_SECOND as f64).recip(), assets);
+        input.update();
+    }
+
+    /// Press and release a button over two ticks.
+    fn press(
+        game: &mut GameState,
+        input: &mut Input,
+        assets: &Assets,
+        button: impl Fn(&mut Input) -> &mut ButtonState,
+    ) {
+        button(input).handle_bool(true);
+        tick(game, input, assets);
+        button(input).handle_bool(false);
+        tick(game, input, assets);
+    }
+
+    /// Game flying high above the ground without any objects.
+    fn flying(assets: &Assets) -> GameState {
+        let mut game = GameState::new(1234, assets);
+        game.phase = Phase::Fly;
+        game.pos = Vec2::new(1000.0, -200.0);
+        game.vel = Vec2::new(100.0, 0.0);
+        game.objects.clear();
+
+        game
+    }
+
+    /// Replace all objects with ones from an object file, placed on top of the player.
+    fn spawn_on_player(game: &mut GameState, object: &str) {
+        let spawner = toml::from_str::<ObjectsSpawner>(object).unwrap();
+        game.objects = spawner.to_objects(&game.camera, &mut game.rng);
+        for obj in game.objects.iter_mut() {
+            obj.pos = game.pos - Vec2::from(obj.collider().extent()) / 2.0;
+        }
+    }
+
+    /// Every effect of colliding with an object must change the player.
+    #[test]
+    fn test_effects() {
+        let assets = Assets::load();
+        let mut game = GameState::new(1234, &assets);
+        game.terrain.update(0.0, &game.settings.terrain);
+        Rc::make_mut(&mut game.settings).impulse_multiplier = Vec2::new(1.0, 2.0);
+
+        game.apply_effect(&Effect::Money { amount: 3 }, Vec2::zero(), "disk");
+        assert_eq!(game.money, 3);
+
+        game.vel = Vec2::new(100.0, 50.0);
+        game.apply_effect(
+            &Effect::Impulse {
+                force: Some(Vec2::new(10.0, 20.0)),
+            },
+            Vec2::zero(),
+            "bomb",
+        );
+        assert_eq!(game.vel, Vec2::new(110.0, -40.0));
+
+        Rc::make_mut(&mut game.settings).bomb_force = Vec2::new(5.0, 5.0);
+        game.apply_effect(&Effect::Impulse { force: None }, Vec2::zero(), "bomb");
+        assert_eq!(game.vel, Vec2::new(115.0, -50.0));
+
+        game.apply_effect(
+            &Effect::SlowDown {
+                factor: Vec2::new(0.5, 0.0),
+            },
+            Vec2::zero(),
+            "cactus",
+        );
+        assert_eq!(game.vel, Vec2::new(57.5, 0.0));
+
+        Rc::make_mut(&mut game.settings).max_boost_velocity = Vec2::new(100.0, 100.0);
+        game.apply_effect(
+            &Effect::BonusBoost {
+                boost: Vec2::new(2.0, 2.0),
+            },
+            Vec2::zero(),
+            "bonus",
+        );
+        assert_eq!(game.vel, Vec2::new(100.0, 0.0));
+
+        let x = 300.0;
+        let height = game.terrain.height(x);
+        game.apply_effect(&Effect::Explode, Vec2::new(x, height), "bomb");
+        assert!(game.terrain.height(x) > height);
+        assert!(!game.particles.is_empty());
+    }
+
+    /// Rerolling the cards in the shop must not count towards the rare guarantee.
+    #[test]
+    fn test_reroll_pity() {
+        let assets = Assets::load();
+        let mut game = GameState::new(1234, &assets);
+        let mut input = Input::default();
+        game.money = 1000;
+
+        let shops_without_rare = game.shops_without_rare;
+        for rerolls in 1..=10 {
+            press(&mut game, &mut input, &assets, |input| &mut input.r);
+
+            assert_eq!(game.phase, Phase::Buy);
+            assert_eq!(game.rerolls, rerolls);
+            assert_eq!(game.shops_without_rare, shops_without_rare);
+        }
+    }
+
+    /// Cards must be able to change the settings, including the bomb force.
+    #[test]
+    fn test_card_effects() {
+        let assets = Assets::load();
+        let settings = assets.settings();
+
+        let cards = assets.cards();
+        let selected_cards = cards
+            .iter()
+            .map(|card| (card.id.clone(), 1))
+            .collect::<BTreeMap<_, _>>();
+        assert!(settings.with_card_effects(&cards, &selected_cards).is_ok());
+
+        let card = toml::from_str::<Card>(
+            r#"
+            title = "Bigger bombs"
+            description = []
+
+            [[effects]]
+            target = "bomb_force.y"
+            operation = "multiply"
+            value = 2.0
+            "#,
+        )
+        .unwrap()
+        .with_id("card.bombs");
+        let selected_cards = BTreeMap::from([("bombs".to_string(), 1)]);
+        let changed = settings
+            .with_card_effects(&[card], &selected_cards)
+            .unwrap();
+        assert_eq!(changed.bomb_force.y, settings.bomb_force.y * 2.0);
+    }
+
+    /// Every card must be checked for hot-reloads, missing ones must be skipped instead of panicking.
+    #[test]
+    fn test_settings_reload_ids() {
+        let assets = Assets::load();
+        let game = GameState::new(1234, &assets);
+        assert!(!game.card_ids.is_empty());
+        assert_eq!(game.settings_reload_ids.len(), game.card_ids.len() + 1);
+
+        let card_ids = vec!["card.missing".to_string()];
+        assert_eq!(assets.settings_reload_ids(&card_ids).count(), 1);
+    }
+
+    /// Clicking cards in the shop must buy them while the money lasts, without leaving the shop.
+    #[test]
+    fn test_shop() {
+        let assets = Assets::load();
+        let mut game = GameState::new(1234, &assets);
+        let mut input = Input::default();
+        game.money = 1000;
+
+        let card_center = |index: usize| {
+            Vec2::new(
+                CARD_X[index] + CARD_SIZE.w / 2.0,
+                CARD_Y + CARD_SIZE.h / 2.0,
+            )
+            .as_()
+        };
+        for index in 0..3 {
+            assert_eq!(game.hovered_card(card_center(index)), Some(index));
+        }
+        assert_eq!(game.hovered_card(Vec2::zero()), None);
+
+        // Buy the same card twice and another one once
+        for index in [0, 0, 1] {
+            let money = game.money;
+            input.mouse_pos = card_center(index);
+            press(&mut game, &mut input, &assets, |input| {
+                &mut input.left_mouse
+            });
+
+            assert_eq!(game.phase, Phase::Buy);
+            assert!(game.money <= money);
+        }
+        let bought = |game: &GameState, index: usize| {
+            game.card_options[index]
+                .as_ref()
+                .unwrap()
+                .amount_bought(&game.selected_cards)
+        };
+        assert_eq!(bought(&game, 0), 2);
+        assert_eq!(bought(&game, 1), 1);
+
+        // Clicking a card that's too expensive must not buy it
+        game.money = 0;
+        let index = (0..3)
+            .find(|index| {
+                !game.card_options[*index]
+                    .as_ref()
+                    .unwrap()
+                    .can_buy(game.money, &game.selected_cards)
+            })
+            .unwrap();
+        let selected_cards = game.selected_cards.clone();
+        input.mouse_pos = card_center(index);
+        press(&mut game, &mut input, &assets, |input| {
+            &mut input.left_mouse
+        });
+        assert_eq!(game.phase, Phase::Buy);
+        assert_eq!(game.selected_cards, selected_cards);
+
+        // Only the launch button leaves the shop
+        input.mouse_pos = Vec2::new(256, 189);
+        press(&mut game, &mut input, &assets, |input| {
+            &mut input.left_mouse
+        });
+        assert_eq!(game.phase, Phase::LaunchSetAngle);
+        assert_eq!(game.selected_cards, selected_cards);
+    }
+
+    /// The gamble mode must buy the card under the moving cursor or nothing when the time runs out.
+    #[test]
+    fn test_gamble() {
+        let assets = Assets::load();
+        let mut input = Input::default();
+
+        let mut game = GameState::new(1234, &assets);
+        game.money = 1000;
+        press(&mut game, &mut input, &assets, |input| &mut input.g);
+        assert!(game.gamble);
+        assert_eq!(game.phase, Phase::Buy);
+
+        // Wait for the cursor to move to another card
+        for _ in 0..UPDATES_PER_SECOND {
+            tick(&mut game, &mut input, &assets);
+        }
+        assert_eq!(game.phase, Phase::Buy);
+
+        input.left_mouse.handle_bool(true);
+        tick(&mut game, &mut input, &assets);
+        let index = game.buy_item.floor() as usize;
+        input.left_mouse.handle_bool(false);
+        tick(&mut game, &mut input, &assets);
+        assert_eq!(game.phase, Phase::LaunchSetAngle);
+        assert_eq!(game.selected_cards.values().sum::<usize>(), 1);
+        assert_eq!(
+            game.card_options[index]
+                .as_ref()
+                .unwrap()
+                .amount_bought(&game.selected_cards),
+            1
+        );
+
+        let mut game = GameState::new(1234, &assets);
+        game.money = 1000;
+        press(&mut game, &mut input, &assets, |input| &mut input.g);
+        while game.phase == Phase::Buy {
+            tick(&mut game, &mut input, &assets);
+        }
+        assert_eq!(game.phase, Phase::LaunchSetAngle);
+        assert!(game.selected_cards.is_empty());
+        assert_eq!(game.money, 1000);
+    }
+
+    /// Overlapping an updraft must push the player up every tick.
+    #[test]
+    fn test_updraft() {
+        let assets = Assets::load();
+        let mut input = Input::default();
+
+        let mut free = flying(&assets);
+        let mut lifted = flying(&assets);
+        spawn_on_player(
+            &mut lifted,
+            r#"
+            repeat_distance = 1
+            amount = 1
+            sprite_path = "updraft"
+            collider = { w = 32, h = 240 }
+            overlap_acceleration = { x = 0.0, y = -600.0 }
+            "#,
+        );
+        for _ in 0..2 {
+            tick(&mut free, &mut input, &assets);
+            tick(&mut lifted, &mut input, &assets);
+        }
+
+        assert!(lifted.vel.y < free.vel.y - 600.0 / UPDATES_PER_SECOND as f64);
+        assert_eq!(lifted.objects.len(), 1);
+    }
+
+    /// Hitting a bird must carry the player with its velocity until it lets go.
+    #[test]
+    fn test_carry() {
+        let assets = Assets::load();
+        let mut input = Input::default();
+        let mut game = flying(&assets);
+        spawn_on_player(
+            &mut game,
+            r#"
+            repeat_distance = 1
+            amount = 1
+            sprite_path = "bird"
+            collider = { w = 20, h = 12 }
+
+            [[on_collision]]
+            type = "carry"
+            duration = 0.5
+            velocity = { x = 250.0, y = -80.0 }
+            "#,
+        );
+
+        tick(&mut game, &mut input, &assets);
+        assert!(game.carrier.is_some());
+
+        tick(&mut game, &mut input, &assets);
+        assert!((game.vel - Vec2::new(250.0, -80.0)).magnitude() < 10.0);
+
+        for _ in 0..UPDATES_PER_SECOND {
+            tick(&mut game, &mut input, &assets);
+        }
+        assert!(game.carrier.is_none());
+    }
+
+    /// A fuel can must store a charge that skips the boost delay.
+    #[test]
+    fn test_refuel() {
+        let assets = Assets::load();
+        let mut input = Input::default();
+        let mut game = flying(&assets);
+        game.boost_delay = game.settings.boost_delay;
+        spawn_on_player(
+            &mut game,
+            r#"
+            repeat_distance = 1
+            amount = 1
+            sprite_path = "fuel-can"
+            collider = { w = 12, h = 16 }
+
+            [[on_collision]]
+            type = "refuel"
+            "#,
+        );
+
+        tick(&mut game, &mut input, &assets);
+        assert_eq!(game.boost_charges, 1);
+        assert!(game.boost_delay > 0.0);
+
+        press(&mut game, &mut input, &assets, |input| {
+            &mut input.left_mouse
+        });
+        assert_eq!(game.boost_charges, 0);
+        assert_eq!(game.boost_delay, game.settings.boost_delay);
+
+        // Without charges the delay must be waited out
+        press(&mut game, &mut input, &assets, |input| {
+            &mut input.left_mouse
+        });
+        assert!(game.boost_delay < game.settings.boost_delay);
+
+        for _ in 0..10 {
+            game.apply_effect(&Effect::Refuel, game.pos, "fuel-can");
+        }
+        assert_eq!(game.boost_charges, game.settings.max_boost_charges);
+    }
+
+    /// Units must be spawned ahead, give money when knocked over and be forgotten far behind.
+    #[test]
+    fn test_units() {
+        let assets = Assets::load();
+        let mut input = Input::default();
+        let mut game = flying(&assets);
+        let money = game.money;
+
+        // Knocked over by flying into it fast enough
+        game.vel = Vec2::new(1000.0, 0.0);
+        let feet = game.player_collider().center() + Vec2::new(0.0, 7.0);
+        game.units
+            .push(Unit::new(feet, UnitType::EnemySpear, &assets));
+        // Left far behind
+        game.units.push(Unit::new(
+            Vec2::new(game.pos.x - 2000.0, 0.0),
+            UnitType::EnemySpear,
+            &assets,
+        ));
+
+        tick(&mut game, &mut input, &assets);
+
+        let settings = UnitType::EnemySpear.settings(&assets);
+        assert_eq!(game.money, money + settings.knock_over_money);
+        assert!(!game.units.is_empty());
+        assert!(game.units.iter().all(
+            |unit| unit.pos().x > settings.min_spawn_distance - 1.0 && !unit.is_knocked_over()
+        ));
+
+        let view = game.camera.view();
+        assert!(game.next_unit_x >= view.max.x + view.size().w);
+    }
+
+    /// Shooting must push the player forward and throw a projectile backwards.
+    #[test]
+    fn test_shoot() {
+        let assets = Assets::load();
+        let mut game = flying(&assets);
+        let vel = game.vel;
+
+        game.shoot(&assets);
+        assert_eq!(game.vel.x, vel.x + game.settings.shot_impulse);
+        assert_eq!(game.projectiles.len(), 1);
+        assert_eq!(game.projectiles[0].allegiance, Allegiance::Player);
+        assert!(game.projectiles[0].body(&game.physics).vel.x < vel.x);
+        assert!(game.shot_delay > 0.0);
+    }
+
+    /// Projectiles must slow down the player, damage units or trigger objects depending on who threw them.
+    #[test]
+    fn test_projectile_hits() {
+        let assets = Assets::load();
+        let dt = (UPDATES_PER_SECOND as f64).recip();
+
+        // Enemy spear hitting the player
+        let mut game = flying(&assets);
+        let vel = game.vel;
+        let center = game.player_collider().center();
+        game.projectiles.push(Projectile::new(
+            center,
+            Vec2::zero(),
+            "projectile.spear",
+            Allegiance::Enemy,
+            &mut game.physics,
+            &assets,
+        ));
+        let hit_slowdown = game.projectiles[0].settings(&assets).hit_slowdown;
+        game.update_projectiles(dt, &assets);
+        assert!(game.projectiles.is_empty());
+        assert_eq!(game.vel, vel * hit_slowdown);
+
+        // Player egg knocking over a unit
+        let mut game = flying(&assets);
+        let money = game.money;
+        let feet = Vec2::new(game.pos.x + 100.0, game.pos.y);
+        game.units
+            .push(Unit::new(feet, UnitType::EnemySpear, &assets));
+        let center = game.units[0].collider(&assets).center();
+        game.projectiles.push(Projectile::new(
+            center,
+            Vec2::zero(),
+            &game.settings.shot_projectile,
+            Allegiance::Player,
+            &mut game.physics,
+            &assets,
+        ));
+        game.update_projectiles(dt, &assets);
+        assert!(game.projectiles.is_empty());
+        assert!(game.units[0].is_knocked_over());
+        assert_eq!(
+            game.money,
+            money + UnitType::EnemySpear.settings(&assets).knock_over_money
+        );
+
+        // Only player projectiles collect money
+        let disk = r#"
+            repeat_distance = 1
+            amount = 1
+            sprite_path = "disk"
+            collider = { w = 16, h = 16 }
+
+            [[on_collision]]
+            type = "money"
+            amount = 5
+            "#;
+        for (allegiance, collected) in [(Allegiance::Enemy, 0), (Allegiance::Player, 5)] {
+            let mut game = flying(&assets);
+            let money = game.money;
+            spawn_on_player(&mut game, disk);
+            game.pos.y -= 100.0;
+            let center = game.objects[0].collider().center();
+            game.projectiles.push(Projectile::new(
+                center,
+                Vec2::zero(),
+                "projectile.spear",
+                allegiance,
+                &mut game.physics,
+                &assets,
+            ));
+            game.update_projectiles(dt, &assets);
+            assert_eq!(game.money, money + collected);
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use std::ops::{Index, Range};
+
+use vek::{Aabr, Extent2, Vec2};
+
+/// Grid of pixels that are either solid or empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitmap {
+    /// Whether each pixel is solid, row by row.
+    map: Vec<bool>,
+    /// Amount of pixels in both directions.
+    size: Extent2<usize>,
+}
+
+impl Bitmap {
+    /// Create a bitmap where every pixel is empty.
+    pub fn empty(size: Extent2<usize>) -> Self {
+        let map = vec![false; size.product()];
+
+        Self { map, size }
+    }
+
+    /// Set a single pixel.
+    pub fn set(&mut self, pos: Vec2<usize>, value: bool) {
+        let index = pos.x + pos.y * self.size.w;
+        self.set_at_index(index, value);
+    }
+
+    /// Set a single pixel by its index.
+    pub fn set_at_index(&mut self, index: usize, value: bool) {
+        self.map[index] = value;
+    }
+
+    /// Set a range of pixels by their indices.
+    pub fn set_at_index_range(&mut self, range: Range<usize>, value: bool) {
+        self.map[range].fill(value);
+    }
+
+    /// Whether a pixel is solid, returns `false` when out of bounds.
+    pub fn get(&self, pos: Vec2<i32>) -> bool {
+        if pos.x < 0 || pos.y < 0 || pos.x as usize >= self.size.w || pos.y as usize >= self.size.h
+        {
+            false
+        } else {
+            self.map[pos.x as usize + pos.y as usize * self.size.w]
+        }
+    }
+
+    /// Amount of pixels in both directions.
+    pub fn size(&self) -> Extent2<usize> {
+        self.size
+    }
+
+    /// Amount of pixels horizontally.
+    pub fn width(&self) -> usize {
+        self.size.w
+    }
+
+    /// Amount of pixels vertically.
+    pub fn height(&self) -> usize {
+        self.size.h
+    }
+
+    /// Whether no pixel is solid.
+    pub fn is_empty(&self) -> bool {
+        !self.map.contains(&true)
+    }
+
+    /// Shrink the size to fit the solid pixels with some empty space around them.
+    ///
+    /// Returns the position of the new top left pixel in the old bitmap.
+    pub fn shrink_with_padding(&mut self, padding: usize) -> Vec2<usize> {
+        let Some(bounds) = self.bounds() else {
+            return Vec2::zero();
+        };
+
+        // The padding can't go outside the top left, the bottom right is allowed to grow
+        let min = bounds.min.map(|value| value.saturating_sub(padding));
+        let max = bounds.max + padding;
+        let size = Extent2::new(max.x - min.x + 1, max.y - min.y + 1);
+
+        let mut shrunk = Self::empty(size);
+        let row_size = bounds.max.x - bounds.min.x + 1;
+        for y in bounds.min.y..=bounds.max.y {
+            let start = y * self.size.w + bounds.min.x;
+            let shrunk_start = (y - min.y) * size.w + bounds.min.x - min.x;
+            shrunk.map[shrunk_start..(shrunk_start + row_size)]
+                .copy_from_slice(&self.map[start..(start + row_size)]);
+        }
+        *self = shrunk;
+
+        min
+    }
+
+    /// Place this bitmap at an offset in an area of a size and only keep the part that fits in that area.
+    ///
+    /// Returns the position of the kept part in the area and the kept part itself.
+    pub fn clip(&self, offset: Vec2<i32>, size: Extent2<usize>) -> (Vec2<usize>, Self) {
+        let start = offset.map(|value| value.max(0)).as_::<usize>();
+        let end = Vec2::new(
+            (offset.x + self.size.w as i32).clamp(0, size.w as i32),
+            (offset.y + self.size.h as i32).clamp(0, size.h as i32),
+        )
+        .as_::<usize>();
+        let clipped_size =
+            Extent2::new(end.x.saturating_sub(start.x), end.y.saturating_sub(start.y));
+
+        let mut clipped = Self::empty(clipped_size);
+        for y in 0..clipped_size.h {
+            for x in 0..clipped_size.w {
+                let pos = (start + Vec2::new(x, y)).as_::<i32>() - offset;
+                clipped.map[x + y * clipped_size.w] = self.get(pos);
+            }
+        }
+
+        (start, clipped)
+    }
+
+    /// Empty every pixel that's solid in the mask placed at an offset.
+    ///
+    /// Returns a mask with the same size as the removal mask containing only the pixels that were actually removed.
+    pub fn apply_removal_mask(&mut self, removal_mask: &Self, offset: Vec2<usize>) -> Self {
+        let mut delta = Self::empty(removal_mask.size);
+        for y in 0..removal_mask
+            .size
+            .h
+            .min(self.size.h.saturating_sub(offset.y))
+        {
+            for x in 0..removal_mask
+                .size
+                .w
+                .min(self.size.w.saturating_sub(offset.x))
+            {
+                let mask_index = x + y * removal_mask.size.w;
+                let index = x + offset.x + (y + offset.y) * self.size.w;
+                if removal_mask.map[mask_index] && self.map[index] {
+                    self.map[index] = false;
+                    delta.map[mask_index] = true;
+                }
+            }
+        }
+
+        delta
+    }
+
+    /// Find a single pixel of every group of connected solid pixels.
+    pub fn islands(&self) -> Vec<Vec2<usize>> {
+        let mut visited = Self::empty(self.size);
+
+        (0..self.map.len())
+            .filter_map(|index| {
+                if !self.map[index] || visited.map[index] {
+                    return None;
+                }
+
+                let pos = Vec2::new(index % self.size.w, index / self.size.w);
+                self.floodfill(pos, |index| visited.map[index] = true);
+
+                Some(pos)
+            })
+            .collect()
+    }
+
+    /// Amount of solid pixels connected to a position.
+    pub fn area_from_shape_at_position(&self, pos: Vec2<usize>) -> f64 {
+        let mut area = 0.0;
+        self.floodfill(pos, |_| area += 1.0);
+
+        area
+    }
+
+    /// Empty all solid pixels connected to a position.
+    ///
+    /// Returns a bitmap with the same size containing only the emptied pixels.
+    pub fn zeroing_floodfill_with_copy(&mut self, pos: Vec2<usize>) -> Self {
+        let mut copy = Self::empty(self.size);
+        self.floodfill(pos, |index| copy.map[index] = true);
+
+        for (pixel, copied) in self.map.iter_mut().zip(copy.map.iter()) {
+            if *copied {
+                *pixel = false;
+            }
+        }
+
+        copy
+    }
+
+    /// Call a function with the index of every solid pixel connected horizontally or vertically to a position.
+    fn floodfill<F>(&self, pos: Vec2<usize>, mut f: F)
+    where
+        F: FnMut(usize),
+    {
+        let start = pos.x + pos.y * self.size.w;
+        if !self.map.get(start).copied().unwrap_or_default() {
+            return;
+        }
+
+        let mut seen = vec![false; self.map.len()];
+        seen[start] = true;
+        let mut stack = vec![start];
+        while let Some(index) = stack.pop() {
+            f(index);
+
+            let x = index % self.size.w;
+            let y = index / self.size.w;
+            let neighbours = [
+                (x > 0).then(|| index - 1),
+                (x + 1 < self.size.w).then(|| index + 1),
+                (y > 0).then(|| index - self.size.w),
+                (y + 1 < self.size.h).then(|| index + self.size.w),
+            ];
+            for neighbour in neighbours.into_iter().flatten() {
+                if self.map[neighbour] && !seen[neighbour] {
+                    seen[neighbour] = true;
+                    stack.push(neighbour);
+                }
+            }
+        }
+    }
+
+    /// Smallest rectangle containing all solid pixels, nothing when empty.
+    fn bounds(&self) -> Option<Aabr<usize>> {
+        self.map
+            .iter()
+            .enumerate()
+            .filter(|(_, solid)| **solid)
+            .map(|(index, _)| Vec2::new(index % self.size.w, index / self.size.w))
+            .fold(None, |bounds: Option<Aabr<usize>>, pos| {
+                Some(match bounds {
+                    Some(bounds) => bounds.expanded_to_contain_point(pos),
+                    None => Aabr { min: pos, max: pos },
+                })
+            })
+    }
+}
+
+impl Index<usize> for Bitmap {
+    type Output = bool;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.map[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vek::{Extent2, Vec2};
+
+    use super::Bitmap;
+
+    /// Removing a line through a rectangle must split it into two islands.
+    #[test]
+    fn test_islands() {
+        let mut bitmap = Bitmap::empty(Extent2::new(10, 5));
+        for y in 1..4 {
+            bitmap.set_at_index_range((y * 10 + 1)..(y * 10 + 9), true);
+        }
+        assert_eq!(bitmap.islands().len(), 1);
+        assert_eq!(bitmap.area_from_shape_at_position(Vec2::new(1, 1)), 24.0);
+
+        let mut line = Bitmap::empty(Extent2::new(1, 5));
+        line.set_at_index_range(0..5, true);
+        let delta = bitmap.apply_removal_mask(&line, Vec2::new(4, 0));
+        assert_eq!(delta.area_from_shape_at_position(Vec2::new(0, 1)), 3.0);
+        assert_eq!(bitmap.islands().len(), 2);
+
+        let island = bitmap.zeroing_floodfill_with_copy(Vec2::new(1, 1));
+        assert_eq!(island.area_from_shape_at_position(Vec2::new(1, 1)), 9.0);
+        assert_eq!(bitmap.islands(), vec![Vec2::new(5, 1)]);
+    }
+
+    /// Shrinking must keep the pixels at the same place relative to the returned offset.
+    #[test]
+    fn test_shrink() {
+        let mut bitmap = Bitmap::empty(Extent2::new(10, 10));
+        bitmap.set(Vec2::new(5, 6), true);
+
+        let offset = bitmap.shrink_with_padding(2);
+        assert_eq!(offset, Vec2::new(3, 4));
+        assert_eq!(bitmap.size(), Extent2::new(5, 5));
+        assert!(bitmap.get(Vec2::new(2, 2)));
+
+        let (start, clipped) = bitmap.clip(Vec2::new(-3, -3), Extent2::new(4, 4));
+        assert_eq!(start, Vec2::zero());
+        assert_eq!(clipped.size(), Extent2::new(2, 2));
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+
+use vek::{LineSegment2, Vec2};
+
+use super::bitmap::Bitmap;
+
+/// Outline of the solid pixels of a bitmap, generated with marching squares.
+///
+/// Every cell between four pixel centers has at most two line segments.
+#[derive(Debug, Clone, Default)]
+pub struct Isoline {
+    /// Line segments of every cell that has any, indexed by the `(y, x)` position of the top left pixel.
+    cells: BTreeMap<(i32, i32), Vec<LineSegment2<f64>>>,
+}
+
+impl Isoline {
+    /// Generate the outline of a bitmap.
+    pub fn from_bitmap(bitmap: &Bitmap) -> Self {
+        puffin::profile_scope!("Isoline from bitmap");
+
+        let mut this = Self::default();
+
+        // Start one pixel outside so shapes touching the edges are also closed
+        this.generate_cells(
+            bitmap,
+            Vec2::new(-1, -1),
+            Vec2::new(bitmap.width() as i32, bitmap.height() as i32),
+        );
+
+        this
+    }
+
+    /// Regenerate the part of the outline changed by a delta mask placed at an offset.
+    pub fn update(&mut self, bitmap: &Bitmap, delta_mask: &Bitmap, offset: Vec2<usize>) {
+        puffin::profile_scope!("Update isoline");
+
+        // Every cell touching a changed pixel needs to be regenerated
+        let min = offset.as_::<i32>() - 1;
+        let max = offset.as_::<i32>() + Vec2::new(delta_mask.width(), delta_mask.height()).as_();
+
+        self.cells
+            .retain(|(y, x), _| *x < min.x || *x > max.x || *y < min.y || *y > max.y);
+        self.generate_cells(bitmap, min, max);
+    }
+
+    /// All line segments of the outline.
+    pub fn segments(&self) -> impl Iterator<Item = &LineSegment2<f64>> {
+        self.cells.values().flatten()
+    }
+
+    /// Calculate the line segments of all cells in a rectangle, both inclusive.
+    fn generate_cells(&mut self, bitmap: &Bitmap, min: Vec2<i32>, max: Vec2<i32>) {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let segments = cell_segments(bitmap, Vec2::new(x, y));
+                if !segments.is_empty() {
+                    self.cells.insert((y, x), segments);
+                }
+            }
+        }
+    }
+}
+
+/// Line segments of a single cell, the position is of the top left pixel.
+fn cell_segments(bitmap: &Bitmap, pos: Vec2<i32>) -> Vec<LineSegment2<f64>> {
+    let case = bitmap.get(pos) as u8
+        | (bitmap.get(pos + (1, 0)) as u8) << 1
+        | (bitmap.get(pos + (1, 1)) as u8) << 2
+        | (bitmap.get(pos + (0, 1)) as u8) << 3;
+
+    // Points are in the middle of the edges between the pixel centers
+    let center = pos.as_::<f64>() + 0.5;
+    let top = center + (0.5, 0.0);
+    let right = center + (1.0, 0.5);
+    let bottom = center + (0.5, 1.0);
+    let left = center + (0.0, 0.5);
+
+    let line = |start, end| LineSegment2 { start, end };
+    match case {
+        1 | 14 => vec![line(left, top)],
+        2 | 13 => vec![line(top, right)],
+        3 | 12 => vec![line(left, right)],
+        4 | 11 => vec![line(right, bottom)],
+        5 => vec![line(left, top), line(right, bottom)],
+        6 | 9 => vec![line(top, bottom)],
+        7 | 8 => vec![line(bottom, left)],
+        10 => vec![line(top, right), line(bottom, left)],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vek::{Extent2, Vec2};
+
+    use super::Isoline;
+    use crate::gen::bitmap::Bitmap;
+
+    /// The outline of a rectangle must be closed and follow removed pixels.
+    #[test]
+    fn test_outline() {
+        let mut bitmap = Bitmap::empty(Extent2::new(6, 6));
+        for y in 1..5 {
+            bitmap.set_at_index_range((y * 6 + 1)..(y * 6 + 5), true);
+        }
+
+        let mut isoline = Isoline::from_bitmap(&bitmap);
+
+        // A 4x4 square has 4 corner cells and 3 cells for every side
+        assert_eq!(isoline.segments().count(), 16);
+
+        // Every point must be shared by exactly two segments for a closed outline
+        let points = isoline
+            .segments()
+            .flat_map(|segment| [segment.start, segment.end])
+            .collect::<Vec<_>>();
+        for point in &points {
+            assert_eq!(points.iter().filter(|other| *other == point).count(), 2);
+        }
+
+        // Removing a corner adds a diagonal edge
+        let mut removal = Bitmap::empty(Extent2::new(1, 1));
+        removal.set(Vec2::zero(), true);
+        let delta = bitmap.apply_removal_mask(&removal, Vec2::new(1, 1));
+        isoline.update(&bitmap, &delta, Vec2::new(1, 1));
+
+        assert_eq!(
+            isoline.segments().count(),
+            Isoline::from_bitmap(&bitmap).segments().count()
+        );
+    }
+}
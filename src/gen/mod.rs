@@ -0,0 +1,4 @@
+//! Procedural generation of shapes.
+
+pub mod bitmap;
+pub mod isoline;
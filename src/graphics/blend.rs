@@ -0,0 +1,92 @@
+/// How a drawn color is combined with the color already on the canvas.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Cover the canvas based on the transparency.
+    #[default]
+    Alpha,
+    /// Add the color to the canvas, making it brighter.
+    Additive,
+    /// Multiply the canvas with the color, making it darker.
+    Multiply,
+}
+
+/// Blend mode with an opacity for a single draw.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Blend {
+    /// How the colors are combined.
+    pub mode: BlendMode,
+    /// Factor from 0.0 for invisible to 1.0 for fully applied, multiplied with the alpha of the color.
+    pub opacity: f64,
+}
+
+impl Blend {
+    /// Blend mode with an opacity.
+    pub const fn new(mode: BlendMode, opacity: f64) -> Self {
+        Self { mode, opacity }
+    }
+
+    /// Combine a color with the color on the canvas.
+    ///
+    /// The result is always opaque.
+    pub fn apply(self, dst: u32, src: u32) -> u32 {
+        let alpha = (src >> 24) as f64 / 255.0 * self.opacity.clamp(0.0, 1.0);
+
+        let channel = |shift: u32| {
+            let dst = ((dst >> shift) & 0xff) as f64;
+            let src = ((src >> shift) & 0xff) as f64;
+            let blended = match self.mode {
+                BlendMode::Alpha => src,
+                BlendMode::Additive => dst + src,
+                BlendMode::Multiply => dst * src / 255.0,
+            };
+
+            ((dst + (blended - dst) * alpha).round().clamp(0.0, 255.0) as u32) << shift
+        };
+
+        0xff_00_00_00 | channel(16) | channel(8) | channel(0)
+    }
+
+    /// Whether it's the same as overwriting the canvas with an opaque color.
+    pub fn is_replace(self) -> bool {
+        self.mode == BlendMode::Alpha && self.opacity >= 1.0
+    }
+}
+
+impl Default for Blend {
+    fn default() -> Self {
+        Self::new(BlendMode::Alpha, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Blend, BlendMode};
+
+    /// Every mode must combine the channels as expected.
+    #[test]
+    fn test_modes() {
+        let dst = 0xff_80_40_20;
+
+        assert_eq!(Blend::default().apply(dst, 0xff_00_ff_00), 0xff_00_ff_00);
+        assert_eq!(
+            Blend::new(BlendMode::Alpha, 0.5).apply(dst, 0xff_00_c0_00),
+            0xff_40_80_10
+        );
+        assert_eq!(
+            Blend::new(BlendMode::Alpha, 1.0).apply(dst, 0x00_ff_ff_ff),
+            dst
+        );
+        assert_eq!(
+            Blend::new(BlendMode::Additive, 1.0).apply(dst, 0xff_c0_10_10),
+            0xff_ff_50_30
+        );
+        assert_eq!(
+            Blend::new(BlendMode::Multiply, 1.0).apply(dst, 0xff_80_ff_00),
+            0xff_40_40_00
+        );
+        assert_eq!(
+            Blend::new(BlendMode::Multiply, 0.0).apply(dst, 0xff_00_00_00),
+            dst
+        );
+    }
+}
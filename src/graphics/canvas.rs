@@ -0,0 +1,304 @@
+use blit::{Blit, BlitBuffer, BlitOptions};
+use vek::{Extent2, Rect, Vec2};
+
+use super::blend::Blend;
+
+/// Pixel buffer to draw on, knowing its own size.
+///
+/// Everything drawn outside of the buffer is clipped.
+pub struct Canvas<'a> {
+    /// Pixels row by row.
+    pixels: &'a mut [u32],
+    /// Size in pixels.
+    size: Extent2<usize>,
+}
+
+impl<'a> Canvas<'a> {
+    /// Draw on a buffer with a size.
+    pub fn new(pixels: &'a mut [u32], size: Extent2<usize>) -> Self {
+        assert_eq!(pixels.len(), size.w * size.h, "Buffer doesn't match size");
+
+        Self { pixels, size }
+    }
+
+    /// Size in pixels.
+    pub fn size(&self) -> Extent2<usize> {
+        self.size
+    }
+
+    /// Width in pixels.
+    pub fn width(&self) -> usize {
+        self.size.w
+    }
+
+    /// Height in pixels.
+    pub fn height(&self) -> usize {
+        self.size.h
+    }
+
+    /// Raw pixels row by row.
+    pub fn pixels(&self) -> &[u32] {
+        self.pixels
+    }
+
+    /// Color of a pixel, nothing when it's outside.
+    pub fn get(&self, pos: Vec2<i32>) -> Option<u32> {
+        self.index(pos).map(|index| self.pixels[index])
+    }
+
+    /// Set the color of a pixel, ignored when it's outside.
+    pub fn set(&mut self, pos: Vec2<i32>, color: u32) {
+        if let Some(index) = self.index(pos) {
+            self.pixels[index] = color;
+        }
+    }
+
+    /// Combine the color of a pixel with a color, ignored when it's outside.
+    pub fn blend(&mut self, pos: Vec2<i32>, color: u32, blend: Blend) {
+        if let Some(index) = self.index(pos) {
+            self.pixels[index] = blend.apply(self.pixels[index], color);
+        }
+    }
+
+    /// Set every pixel to the same color.
+    pub fn fill(&mut self, color: u32) {
+        self.pixels.fill(color);
+    }
+
+    /// Fill a rectangle.
+    pub fn fill_rect(&mut self, rect: Rect<i32, i32>, color: u32) {
+        self.map_rect(rect, |_| color);
+    }
+
+    /// Combine every pixel in a rectangle with a color.
+    pub fn blend_rect(&mut self, rect: Rect<i32, i32>, color: u32, blend: Blend) {
+        self.map_rect(rect, |pixel| blend.apply(pixel, color));
+    }
+
+    /// Change every pixel in a rectangle based on its current color.
+    pub fn map_rect(&mut self, rect: Rect<i32, i32>, mut f: impl FnMut(u32) -> u32) {
+        let Some(rect) = self.clip(rect) else {
+            return;
+        };
+
+        for y in rect.y..(rect.y + rect.h) {
+            let start = rect.x + y * self.size.w;
+            self.pixels[start..(start + rect.w)]
+                .iter_mut()
+                .for_each(|pixel| *pixel = f(*pixel));
+        }
+    }
+
+    /// Draw the one pixel wide outline of a rectangle.
+    pub fn rect(&mut self, rect: Rect<i32, i32>, color: u32) {
+        if rect.w <= 0 || rect.h <= 0 {
+            return;
+        }
+
+        let (right, bottom) = (rect.x + rect.w - 1, rect.y + rect.h - 1);
+        self.fill_rect(Rect::new(rect.x, rect.y, rect.w, 1), color);
+        self.fill_rect(Rect::new(rect.x, bottom, rect.w, 1), color);
+        self.fill_rect(Rect::new(rect.x, rect.y, 1, rect.h), color);
+        self.fill_rect(Rect::new(right, rect.y, 1, rect.h), color);
+    }
+
+    /// Draw a line including both ends.
+    pub fn line(&mut self, start: Vec2<i32>, end: Vec2<i32>, color: u32) {
+        self.dashed_line(start, end, 1, 0, color);
+    }
+
+    /// Draw a line alternating between dashes of drawn pixels and gaps of skipped pixels.
+    pub fn dashed_line(
+        &mut self,
+        start: Vec2<i32>,
+        end: Vec2<i32>,
+        dash: u32,
+        gap: u32,
+        color: u32,
+    ) {
+        let period = dash + gap;
+        if period == 0 {
+            return;
+        }
+
+        // Bresenham's line algorithm
+        let delta = Vec2::new((end.x - start.x).abs(), -(end.y - start.y).abs());
+        let step = Vec2::new((end.x - start.x).signum(), (end.y - start.y).signum());
+        let mut error = delta.x + delta.y;
+        let mut pos = start;
+        let mut i = 0;
+        loop {
+            if i % period < dash {
+                self.set(pos, color);
+            }
+            i += 1;
+
+            if pos == end {
+                break;
+            }
+
+            let error2 = error * 2;
+            if error2 >= delta.y {
+                error += delta.y;
+                pos.x += step.x;
+            }
+            if error2 <= delta.x {
+                error += delta.x;
+                pos.y += step.y;
+            }
+        }
+    }
+
+    /// Draw the one pixel wide outline of a circle.
+    pub fn circle(&mut self, center: Vec2<i32>, radius: i32, color: u32) {
+        self.circle_octants(radius, |canvas, x, y| {
+            for (x, y) in [(x, y), (y, x)] {
+                for pos in [
+                    Vec2::new(x, y),
+                    Vec2::new(-x, y),
+                    Vec2::new(x, -y),
+                    Vec2::new(-x, -y),
+                ] {
+                    canvas.set(center + pos, color);
+                }
+            }
+        });
+    }
+
+    /// Fill a circle.
+    pub fn fill_circle(&mut self, center: Vec2<i32>, radius: i32, color: u32) {
+        self.circle_octants(radius, |canvas, x, y| {
+            for (x, y) in [(x, y), (y, x)] {
+                for y in [-y, y] {
+                    canvas.fill_rect(Rect::new(center.x - x, center.y + y, x * 2 + 1, 1), color);
+                }
+            }
+        });
+    }
+
+    /// Draw a buffer with options.
+    pub fn blit(&mut self, buffer: &BlitBuffer, options: &BlitOptions) {
+        buffer.blit(self.pixels, self.size.into_tuple().into(), options);
+    }
+
+    /// Call a function for every point of the first octant of a circle around the origin.
+    fn circle_octants(&mut self, radius: i32, mut f: impl FnMut(&mut Self, i32, i32)) {
+        if radius < 0 {
+            return;
+        }
+
+        // Midpoint circle algorithm
+        let mut x = radius;
+        let mut y = 0;
+        let mut error = 1 - radius;
+        while y <= x {
+            f(self, x, y);
+
+            y += 1;
+            if error < 0 {
+                error += 2 * y + 1;
+            } else {
+                x -= 1;
+                error += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    /// Part of a rectangle that's inside, nothing when it's completely outside.
+    fn clip(&self, rect: Rect<i32, i32>) -> Option<Rect<usize, usize>> {
+        let min = Vec2::new(rect.x, rect.y).map(|v| v.max(0));
+        let max = Vec2::new(rect.x + rect.w, rect.y + rect.h)
+            .zip::<usize>(Vec2::from(self.size))
+            .map(|(v, size)| v.min(size as i32));
+        if min.x >= max.x || min.y >= max.y {
+            return None;
+        }
+
+        Some(Rect::new(
+            min.x as usize,
+            min.y as usize,
+            (max.x - min.x) as usize,
+            (max.y - min.y) as usize,
+        ))
+    }
+
+    /// Index of a pixel, nothing when it's outside.
+    fn index(&self, pos: Vec2<i32>) -> Option<usize> {
+        (pos.x >= 0
+            && pos.y >= 0
+            && (pos.x as usize) < self.size.w
+            && (pos.y as usize) < self.size.h)
+            .then(|| pos.x as usize + pos.y as usize * self.size.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vek::{Extent2, Rect, Vec2};
+
+    use super::Canvas;
+
+    /// Count the pixels with a color.
+    fn count(canvas: &Canvas, color: u32) -> usize {
+        canvas
+            .pixels()
+            .iter()
+            .filter(|pixel| **pixel == color)
+            .count()
+    }
+
+    /// Drawing outside of the edges must be clipped instead of panicking.
+    #[test]
+    fn test_clipping() {
+        let mut buffer = vec![0; 8 * 6];
+        let mut canvas = Canvas::new(&mut buffer, Extent2::new(8, 6));
+
+        canvas.set(Vec2::new(-1, 0), 1);
+        canvas.set(Vec2::new(8, 0), 1);
+        canvas.set(Vec2::new(0, 6), 1);
+        assert_eq!(count(&canvas, 1), 0);
+        assert_eq!(canvas.get(Vec2::new(-1, 0)), None);
+
+        canvas.fill_rect(Rect::new(-2, -2, 4, 4), 2);
+        assert_eq!(count(&canvas, 2), 4);
+
+        canvas.fill_rect(Rect::new(6, 4, 10, 10), 3);
+        assert_eq!(count(&canvas, 3), 4);
+
+        canvas.line(Vec2::new(-10, 3), Vec2::new(20, 3), 4);
+        assert_eq!(count(&canvas, 4), 8);
+
+        canvas.circle(Vec2::new(0, 0), 100, 5);
+        canvas.fill_circle(Vec2::new(100, 100), 3, 5);
+        assert_eq!(count(&canvas, 5), 0);
+    }
+
+    /// Primitives must cover the expected pixels.
+    #[test]
+    fn test_primitives() {
+        let mut buffer = vec![0; 16 * 16];
+        let mut canvas = Canvas::new(&mut buffer, Extent2::new(16, 16));
+
+        canvas.rect(Rect::new(1, 1, 4, 3), 1);
+        assert_eq!(count(&canvas, 1), 10);
+        assert_eq!(canvas.get(Vec2::new(2, 2)), Some(0));
+
+        canvas.line(Vec2::new(10, 0), Vec2::new(15, 5), 2);
+        assert_eq!(count(&canvas, 2), 6);
+        assert_eq!(canvas.get(Vec2::new(15, 5)), Some(2));
+
+        canvas.dashed_line(Vec2::new(0, 15), Vec2::new(15, 15), 2, 2, 3);
+        assert_eq!(count(&canvas, 3), 8);
+        assert_eq!(canvas.get(Vec2::new(2, 15)), Some(0));
+
+        canvas.circle(Vec2::new(8, 8), 3, 4);
+        assert_eq!(canvas.get(Vec2::new(11, 8)), Some(4));
+        assert_eq!(canvas.get(Vec2::new(8, 5)), Some(4));
+        assert_eq!(canvas.get(Vec2::new(8, 8)), Some(0));
+
+        canvas.fill_circle(Vec2::new(8, 8), 2, 5);
+        assert_eq!(canvas.get(Vec2::new(8, 8)), Some(5));
+        assert_eq!(canvas.get(Vec2::new(10, 8)), Some(5));
+        assert_ne!(canvas.get(Vec2::new(10, 10)), Some(5));
+    }
+}
@@ -0,0 +1,99 @@
+use serde::Deserialize;
+
+use super::canvas::Canvas;
+
+/// Named layers, drawn from back to front.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DrawLayer {
+    /// Far away background like clouds.
+    Sky,
+    /// Scenery moving slower than the world.
+    FarParallax,
+    /// Ground and everything moving along with it.
+    #[default]
+    World,
+    /// Scenery in front of the world.
+    Foreground,
+    /// Player, units and projectiles, in front of all scenery.
+    Actors,
+    /// Particles.
+    Effects,
+    /// Interface on top of everything.
+    Hud,
+}
+
+/// Single drawing operation.
+type DrawFn<'a> = Box<dyn FnOnce(&mut Canvas) + 'a>;
+
+/// Drawing operations collected during a frame, drawn sorted by their layer.
+///
+/// Operations on the same layer are drawn in the order they are submitted.
+#[derive(Default)]
+pub struct DrawList<'a> {
+    /// Layer and operation in the order they are submitted.
+    items: Vec<(DrawLayer, DrawFn<'a>)>,
+}
+
+impl<'a> DrawList<'a> {
+    /// Start an empty list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a drawing operation on a layer.
+    pub fn submit<F>(&mut self, layer: DrawLayer, draw: F)
+    where
+        F: FnOnce(&mut Canvas) + 'a,
+    {
+        self.items.push((layer, Box::new(draw)));
+    }
+
+    /// Draw everything from the back layer to the front layer.
+    pub fn render(mut self, canvas: &mut Canvas) {
+        puffin::profile_function!();
+
+        // Sorting is stable so the submission order in a layer is kept
+        self.items.sort_by_key(|(layer, _)| *layer);
+
+        self.items.into_iter().for_each(|(_, draw)| draw(canvas));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use vek::{Extent2, Vec2};
+
+    use super::{DrawLayer, DrawList};
+    use crate::graphics::canvas::Canvas;
+
+    /// Layers must be drawn back to front, keeping the submission order in a layer.
+    #[test]
+    fn test_order() {
+        let drawn = RefCell::new(Vec::new());
+
+        let mut draw_list = DrawList::new();
+        for (layer, id) in [
+            (DrawLayer::Hud, 0),
+            (DrawLayer::World, 1),
+            (DrawLayer::Sky, 2),
+            (DrawLayer::World, 3),
+            (DrawLayer::Foreground, 4),
+            (DrawLayer::Actors, 5),
+        ] {
+            let drawn = &drawn;
+            draw_list.submit(layer, move |canvas| {
+                canvas.set(Vec2::zero(), id);
+                drawn.borrow_mut().push(id);
+            });
+        }
+
+        let mut buffer = [0];
+        draw_list.render(&mut Canvas::new(&mut buffer, Extent2::new(1, 1)));
+
+        assert_eq!(drawn.into_inner(), vec![2, 1, 3, 4, 5, 0]);
+        assert_eq!(buffer[0], 0);
+    }
+}
@@ -0,0 +1,34 @@
+use vek::{Extent2, Rect, Vec2};
+
+use super::{canvas::Canvas, Color};
+use crate::camera::Camera;
+
+/// Draw a healthbar centered at a world position, nothing is drawn at full health.
+///
+/// The size is in screen pixels so it stays readable when zoomed out.
+pub fn healthbar(
+    health: f64,
+    max_health: f64,
+    pos: Vec2<f64>,
+    size: Extent2<f32>,
+    canvas: &mut Canvas,
+    camera: &Camera,
+) {
+    if health >= max_health {
+        return;
+    }
+
+    let size = size.as_::<f64>();
+    let top_left: Vec2<i32> = (camera.world_to_screen(pos) - Vec2::from(size) / 2.0).as_();
+    let size: Extent2<i32> = size.as_();
+    let filled = ((health / max_health).clamp(0.0, 1.0) * size.w as f64).ceil() as i32;
+
+    canvas.fill_rect(
+        Rect::new(top_left.x, top_left.y, filled, size.h),
+        Color::Green.as_u32(),
+    );
+    canvas.fill_rect(
+        Rect::new(top_left.x + filled, top_left.y, size.w - filled, size.h),
+        Color::Red.as_u32(),
+    );
+}
@@ -1,7 +1,14 @@
+pub mod blend;
+pub mod canvas;
+pub mod draw_list;
+pub mod healthbar;
+
+use serde::Deserialize;
+
 /// Different colors.
 ///
 /// Based on DB32 scale.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
 #[allow(dead_code)]
 pub enum Color {
     Black,
@@ -0,0 +1,110 @@
+use vek::Extent2;
+
+use crate::input::Input;
+
+/// Run the game without a window for a fixed amount of update ticks.
+///
+/// Before every tick `script` can change the input, after which the game is updated with a fixed timestep and a frame is rendered into a plain pixel buffer.
+///
+/// Returns the game state and the last rendered frame.
+pub fn run<G, S, U, R>(
+    mut game_state: G,
+    size: Extent2<usize>,
+    updates_per_second: u32,
+    ticks: u64,
+    mut script: S,
+    mut update: U,
+    mut render: R,
+) -> (G, Vec<u32>)
+where
+    S: FnMut(u64, &mut Input),
+    U: FnMut(&mut G, &Input, f32),
+    R: FnMut(&mut G, &mut [u32], f32),
+{
+    let dt = (updates_per_second as f32).recip();
+
+    let mut input = Input::default();
+    let mut buffer = vec![0u32; size.w * size.h];
+
+    for tick in 0..ticks {
+        script(tick, &mut input);
+
+        update(&mut game_state, &input, dt);
+
+        // Unset the released state, just like the window does
+        input.update();
+
+        render(&mut game_state, &mut buffer, dt);
+    }
+
+    (game_state, buffer)
+}
+
+/// Script that clicks the left mouse button and presses space once every interval of ticks.
+///
+/// Enough to move the game through all the phases, space leaves the shop.
+pub fn click_every(interval: u64) -> impl FnMut(u64, &mut Input) {
+    move |tick, input| {
+        input.left_mouse.handle_bool(tick % interval == 0);
+        input.space.handle_bool(tick % interval == 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        assets::Assets,
+        game::GameState,
+        graphics::{canvas::Canvas, Color},
+        SIZE, UPDATES_PER_SECOND,
+    };
+
+    /// Simulate a game and return the final player position and money.
+    fn simulate(seed: u64) -> (u64, u64, usize) {
+        let assets = Assets::load();
+
+        let (state, _) = super::run(
+            GameState::new(seed, &assets),
+            SIZE,
+            UPDATES_PER_SECOND,
+            UPDATES_PER_SECOND as u64 * 30,
+            super::click_every(UPDATES_PER_SECOND as u64 / 2),
+            |g, input, dt| g.update(input, dt as f64, &assets),
+            |_, _, _| (),
+        );
+
+        (state.pos.x.to_bits(), state.pos.y.to_bits(), state.money)
+    }
+
+    /// Playing for a while must fly the player away from the launch and draw every frame.
+    #[test]
+    fn test_smoke() {
+        let assets = Assets::load();
+
+        let mut frames = 0;
+        let (state, buffer) = super::run(
+            GameState::new(1234, &assets),
+            SIZE,
+            UPDATES_PER_SECOND,
+            UPDATES_PER_SECOND as u64 * 20,
+            super::click_every(UPDATES_PER_SECOND as u64 / 2),
+            |g, input, dt| g.update(input, dt as f64, &assets),
+            |g, buffer, frame_time| {
+                let mut canvas = Canvas::new(buffer, SIZE);
+                canvas.fill(Color::SkyBlue.as_u32());
+                g.render(&mut canvas, frame_time as f64, &assets);
+                frames += 1;
+            },
+        );
+
+        assert_eq!(frames, UPDATES_PER_SECOND * 20);
+        assert!(state.max_distance > 0.0);
+        assert!(buffer.iter().any(|pixel| *pixel != Color::SkyBlue.as_u32()));
+    }
+
+    /// The same seed with the same input must result in exactly the same game.
+    #[test]
+    fn test_deterministic() {
+        assert_eq!(simulate(1234), simulate(1234));
+    }
+}
@@ -1,7 +1,7 @@
 use vek::Vec2;
 
 /// Current input.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Input {
     pub mouse_pos: Vec2<i32>,
 
@@ -36,6 +36,42 @@ impl Input {
         self.n.update();
         self.x.update();
     }
+
+    /// All button states in a fixed order.
+    pub fn buttons(&self) -> [ButtonState; 12] {
+        [
+            self.left_mouse,
+            self.up,
+            self.down,
+            self.left,
+            self.right,
+            self.space,
+            self.r,
+            self.g,
+            self.c,
+            self.o,
+            self.n,
+            self.x,
+        ]
+    }
+
+    /// Mutable references to all button states, in the same order as [`Self::buttons`].
+    pub fn buttons_mut(&mut self) -> [&mut ButtonState; 12] {
+        [
+            &mut self.left_mouse,
+            &mut self.up,
+            &mut self.down,
+            &mut self.left,
+            &mut self.right,
+            &mut self.space,
+            &mut self.r,
+            &mut self.g,
+            &mut self.c,
+            &mut self.o,
+            &mut self.n,
+            &mut self.x,
+        ]
+    }
 }
 
 /// Input button state.
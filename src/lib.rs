@@ -0,0 +1,49 @@
+pub mod animation;
+pub mod aseprite;
+pub mod assets;
+pub mod button;
+pub mod camera;
+pub mod card;
+pub mod flight;
+pub mod font;
+pub mod game;
+pub mod gen;
+pub mod graphics;
+pub mod headless;
+pub mod input;
+pub mod math;
+pub mod object;
+pub mod particle;
+pub mod physics;
+pub mod projectile;
+pub mod random;
+pub mod replay;
+pub mod save;
+pub mod solid_shape;
+pub mod sprite;
+pub mod surface;
+pub mod terrain;
+pub mod timer;
+pub mod unit;
+pub mod window;
+
+use graphics::canvas::Canvas;
+use vek::{Aabr, Extent2, Rect};
+
+pub use assets::Assets;
+pub use game::{GameState, Settings};
+pub use input::Input;
+
+/// Window size.
+pub const SIZE: Extent2<usize> = Extent2::new(320, 240);
+/// Updates per second of the update loop.
+pub const UPDATES_PER_SECOND: u32 = 60;
+
+/// Draw the outline of a rectangle, used for debugging colliders.
+pub fn render_aabr(aabr: Aabr<f64>, canvas: &mut Canvas, color: u32) {
+    // Both the minimum and the maximum edges are drawn
+    let aabr: Aabr<i32> = aabr.as_();
+    let size = aabr.size() + 1;
+
+    canvas.rect(Rect::new(aabr.min.x, aabr.min.y, size.w, size.h), color);
+}
@@ -1,139 +1,247 @@
-mod assets;
-mod card;
-mod font;
-mod game;
-mod graphics;
-mod input;
-mod math;
-mod object;
-mod particle;
-mod random;
-mod sprite;
-mod timer;
-mod window;
-
-use std::sync::OnceLock;
-
-use assets::Assets;
-use assets_manager::{AssetGuard, Compound};
-use font::Font;
-use game::{GameState, Settings};
+use darwins_ascent::{
+    graphics::{canvas::Canvas, Color},
+    replay::{Replay, ReplayPlayer},
+    save::Storage,
+    window, Assets, GameState, Input, SIZE, UPDATES_PER_SECOND,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use darwins_ascent::{headless, replay::Recorder, save::SaveData};
 use miette::Result;
-use object::ObjectsSpawner;
-use sprite::{RotatableSprite, Sprite};
+#[cfg(not(target_arch = "wasm32"))]
+use miette::{miette, WrapErr};
 #[cfg(not(target_arch = "wasm32"))]
 use tokio::runtime::Runtime;
-use vek::{Aabr, Extent2, Vec2};
 
-use crate::graphics::Color;
+/// Update the game state, shared between the window and the headless runner.
+fn update(g: &mut GameState, input: &Input, dt: f32, assets: &Assets) {
+    puffin::profile_scope!("Update");
 
-/// Window size.
-pub const SIZE: Extent2<usize> = Extent2::new(320, 240);
-/// Updates per second of the update loop.
-const UPDATES_PER_SECOND: u32 = 60;
+    // Update the game
+    g.update(input, dt as f64, assets);
 
-/// The assets as a 'static reference.
-pub static ASSETS: OnceLock<Assets> = OnceLock::new();
+    puffin::GlobalProfiler::lock().new_frame();
+}
 
-/// Load an generic asset.
-pub fn asset<T>(path: &str) -> AssetGuard<T>
-where
-    T: Compound,
-{
-    puffin::profile_function!();
+/// Render the game state, shared between the window and the headless runner.
+fn render(g: &mut GameState, buffer: &mut [u32], frame_time: f32, assets: &Assets) {
+    let mut canvas = Canvas::new(buffer, SIZE);
 
-    ASSETS
-        .get()
-        .expect("Asset handling not initialized yet")
-        .asset(path)
-}
+    {
+        puffin::profile_scope!("Clear pixels");
+        canvas.fill(Color::SkyBlue.as_u32());
+    }
 
-/// Load the global settings.
-pub fn settings() -> AssetGuard<'static, Settings> {
-    ASSETS
-        .get()
-        .expect("Asset handling not initialized yet")
-        .settings()
-}
+    {
+        puffin::profile_scope!("Render");
 
-/// Load a sprite.
-pub fn sprite(path: &str) -> AssetGuard<Sprite> {
-    crate::asset(path)
+        // Draw the game
+        g.render(&mut canvas, frame_time as f64, assets);
+    }
 }
 
-pub fn objects(path: &str) -> AssetGuard<ObjectsSpawner> {
-    crate::asset(path)
+/// Pick the seed for the game.
+///
+/// A seed passed explicitly takes precedence over the one in the settings, when neither is set a random one is used.
+fn seed(seed: Option<u64>, assets: &Assets) -> u64 {
+    seed.or(assets.settings().seed)
+        .unwrap_or_else(|| fastrand::u64(..))
 }
 
-/// Load a rotatable sprite.
-pub fn rotatable_sprite(path: &str) -> AssetGuard<RotatableSprite> {
-    crate::asset(path)
-}
+async fn run(args: Args) -> Result<()> {
+    // Initialize the asset loader, it lives for the whole duration of the window
+    let assets: &'static Assets = Box::leak(Box::new(Assets::load()));
+    assets.enable_hot_reloading();
 
-/// Load a font.
-pub fn font() -> AssetGuard<'static, Font> {
-    crate::asset("Beachball")
-}
+    // Play back a recorded game instead of a live one
+    if let Some(replay) = args.replay {
+        log::info!("Replaying {} ticks with seed {}", replay.len(), replay.seed);
 
-pub fn render_aabr(aabr: Aabr<f64>, canvas: &mut [u32], color: u32) {
-    if aabr.min.x < 0.0
-        || aabr.min.y < 0.0
-        || aabr.max.x >= SIZE.w as f64
-        || aabr.max.y >= SIZE.h as f64
-    {
-        return;
-    }
+        window::run(
+            ReplayPlayer::new(replay, assets),
+            SIZE,
+            UPDATES_PER_SECOND,
+            |p, input, dt| p.update(input, dt as f64, assets),
+            |p, buffer, frame_time| {
+                let mut canvas = Canvas::new(buffer, SIZE);
+                canvas.fill(Color::SkyBlue.as_u32());
+                p.render(&mut canvas, frame_time as f64, assets);
+            },
+        )
+        .await?;
 
-    let aabr: Aabr<usize> = aabr.as_();
-    for y in aabr.min.y..aabr.max.y {
-        canvas[aabr.min.x + y * SIZE.w] = color;
-        canvas[aabr.max.x + y * SIZE.w] = color;
+        return Ok(());
     }
-    for x in aabr.min.x..aabr.max.x {
-        canvas[x + aabr.min.y * SIZE.w] = color;
-        canvas[x + aabr.max.y * SIZE.w] = color;
-    }
-}
-
-async fn run() -> Result<()> {
-    // Initialize the asset loader
-    let assets = ASSETS.get_or_init(Assets::load);
-    assets.enable_hot_reloading();
 
     // Construct the game
-    let state = GameState::new();
+    let seed = self::seed(args.seed, assets);
+    log::info!("Starting game with seed {seed}");
+    let state = match Storage::platform_default() {
+        Some(storage) => GameState::with_storage(seed, storage, assets),
+        None => {
+            log::warn!("No location to save progression to");
+
+            GameState::new(seed, assets)
+        }
+    };
+
+    // Record every input so the game can be replayed
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = args.record {
+        let mut recorder = Recorder::new(seed, SaveData::from_game(&state), path);
+
+        window::run(
+            state,
+            SIZE,
+            UPDATES_PER_SECOND,
+            move |g, input, dt| {
+                recorder.record(input);
+                update(g, input, dt, assets);
+            },
+            move |g, buffer, frame_time| render(g, buffer, frame_time, assets),
+        )
+        .await?;
+
+        return Ok(());
+    }
 
     window::run(
         state,
         SIZE,
         UPDATES_PER_SECOND,
-        |g, input, dt| {
-            puffin::profile_scope!("Update");
-
-            // Update the game
-            g.update(input, dt as f64);
-
-            puffin::GlobalProfiler::lock().new_frame();
-        },
-        |g, buffer, frame_time| {
-            {
-                puffin::profile_scope!("Clear pixels");
-                buffer.fill(Color::SkyBlue.as_u32());
-            }
-
-            {
-                puffin::profile_scope!("Render");
-
-                // Draw the game
-                g.render(buffer, frame_time as f64);
-            }
-        },
+        move |g, input, dt| update(g, input, dt, assets),
+        move |g, buffer, frame_time| render(g, buffer, frame_time, assets),
     )
     .await?;
 
     Ok(())
 }
 
+/// Simulate the game without opening a window and print the result.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_headless(args: Args) {
+    // Initialize the asset loader, hot-reloading makes no sense here
+    let assets = Assets::load();
+
+    let (state, ticks) = if let Some(replay) = args.replay {
+        // Feed the recorded input
+        let ticks = args.ticks.unwrap_or(replay.len() as u64);
+        let state = GameState::from_save(replay.seed, &replay.save, &assets);
+
+        let (state, _canvas) = headless::run(
+            state,
+            SIZE,
+            UPDATES_PER_SECOND,
+            ticks,
+            |tick, input| {
+                if let Some(recorded) = replay.input(tick as usize) {
+                    *input = recorded.clone();
+                }
+            },
+            |g, input, dt| update(g, input, dt, &assets),
+            |g, buffer, frame_time| render(g, buffer, frame_time, &assets),
+        );
+
+        (state, ticks)
+    } else {
+        let ticks = args.ticks.unwrap_or(Args::DEFAULT_HEADLESS_TICKS);
+        let state = GameState::new(self::seed(args.seed, &assets), &assets);
+
+        // Click once every second so the game moves through all phases
+        let (state, _canvas) = headless::run(
+            state,
+            SIZE,
+            UPDATES_PER_SECOND,
+            ticks,
+            headless::click_every(UPDATES_PER_SECOND as u64),
+            |g, input, dt| update(g, input, dt, &assets),
+            |g, buffer, frame_time| render(g, buffer, frame_time, &assets),
+        );
+
+        (state, ticks)
+    };
+
+    println!(
+        "Simulated {ticks} ticks with seed {}, distance: {}, max distance: {}, money: {}",
+        state.seed,
+        state.pos.x.round(),
+        state.max_distance.round(),
+        state.money
+    );
+}
+
+/// Command line arguments.
+#[derive(Debug, Default)]
+struct Args {
+    /// Run without a window.
+    headless: bool,
+    /// Amount of ticks to simulate when running without a window.
+    ticks: Option<u64>,
+    /// Seed for the random number generator.
+    seed: Option<u64>,
+    /// Path of the file to record the input to.
+    record: Option<String>,
+    /// Recorded game to play back.
+    replay: Option<Replay>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Args {
+    /// Default amount of ticks for a headless run when none is passed.
+    const DEFAULT_HEADLESS_TICKS: u64 = 60 * 60;
+
+    /// Parse the arguments passed to the binary.
+    ///
+    /// Fails on invalid values or a replay file that can't be loaded.
+    fn parse() -> Result<Self> {
+        let mut args = Self::default();
+
+        let mut iter = std::env::args().skip(1).peekable();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--headless" => {
+                    args.headless = true;
+
+                    // Optional amount of ticks
+                    args.ticks = iter
+                        .next_if(|next| !next.starts_with("--"))
+                        .map(|ticks| {
+                            ticks
+                                .parse()
+                                .map_err(|_| miette!("Invalid amount of ticks '{ticks}'"))
+                        })
+                        .transpose()?;
+                }
+                "--seed" => {
+                    let seed = iter
+                        .next()
+                        .ok_or_else(|| miette!("Missing value for '--seed'"))?;
+
+                    args.seed = Some(seed.parse().map_err(|_| miette!("Invalid seed '{seed}'"))?);
+                }
+                "--record" => {
+                    args.record = Some(
+                        iter.next()
+                            .ok_or_else(|| miette!("Missing path for '--record'"))?,
+                    );
+                }
+                "--replay" => {
+                    let path = iter
+                        .next()
+                        .ok_or_else(|| miette!("Missing path for '--replay'"))?;
+
+                    args.replay = Some(
+                        Replay::load(&path)
+                            .wrap_err_with(|| format!("Could not load replay '{path}'"))?,
+                    );
+                }
+                other => eprintln!("Ignoring unknown argument '{other}'"),
+            }
+        }
+
+        Ok(args)
+    }
+}
+
 /// Entry point starting either a WASM future or a Tokio runtime.
 fn main() {
     #[cfg(target_arch = "wasm32")]
@@ -141,11 +249,24 @@ fn main() {
         std::panic::set_hook(Box::new(console_error_panic_hook::hook));
         console_log::init_with_level(log::Level::Info).expect("error initializing logger");
 
-        wasm_bindgen_futures::spawn_local(async { run().await.unwrap() });
+        wasm_bindgen_futures::spawn_local(async { run(Args::default()).await.unwrap() });
     }
 
     #[cfg(not(target_arch = "wasm32"))]
     {
+        let args = match Args::parse() {
+            Ok(args) => args,
+            Err(err) => {
+                eprintln!("{err:?}");
+                std::process::exit(1);
+            }
+        };
+        if args.headless {
+            run_headless(args);
+
+            return;
+        }
+
         // Run puffin HTTP profiling server
         let server_addr = format!("0.0.0.0:{}", puffin_http::DEFAULT_PORT);
         let _puffin_server = puffin_http::Server::new(&server_addr).unwrap();
@@ -155,6 +276,6 @@ fn main() {
         puffin::set_scopes_on(true);
 
         let rt = Runtime::new().unwrap();
-        rt.block_on(async { run().await.unwrap() });
+        rt.block_on(async { run(args).await.unwrap() });
     }
 }
@@ -1,8 +1,112 @@
 use assets_manager::{loader::TomlLoader, Asset};
+use fastrand::Rng;
 use serde::Deserialize;
 use vek::{Extent2, Rect, Vec2};
 
-use crate::{random::RandomRangeF64, SIZE};
+use crate::{
+    assets::Assets,
+    camera::Camera,
+    graphics::{
+        blend::{Blend, BlendMode},
+        canvas::Canvas,
+        draw_list::DrawLayer,
+    },
+    random::RandomRangeF64,
+    unit::Allegiance,
+};
+
+/// What happens when the player collides with an object.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Effect {
+    /// Give the player money.
+    Money { amount: usize },
+    /// Push the player forward and up, with the `bomb_force` setting when no force is set.
+    Impulse {
+        #[serde(default)]
+        force: Option<Vec2<f64>>,
+    },
+    /// Multiply the velocity of the player with a factor per axis.
+    SlowDown { factor: Vec2<f64> },
+    /// Multiply the velocity of the player like hitting the boost meter, up to the maximum boost velocity.
+    BonusBoost { boost: Vec2<f64> },
+    /// Blow up, carving a crater in the ground below.
+    Explode,
+    /// Store a boost charge that can be used while the boost is recharging.
+    Refuel,
+    /// Carry the player with a fixed velocity for a while.
+    Carry { duration: f64, velocity: Vec2<f64> },
+}
+
+impl Effect {
+    /// Whether a projectile hitting the object also triggers the effect.
+    ///
+    /// Only projectiles shot by the player can collect money.
+    pub fn is_triggered_by(&self, allegiance: Allegiance) -> bool {
+        match self {
+            Effect::Money { .. } => allegiance == Allegiance::Player,
+            Effect::Explode => true,
+            Effect::Impulse { .. }
+            | Effect::SlowDown { .. }
+            | Effect::BonusBoost { .. }
+            | Effect::Refuel
+            | Effect::Carry { .. } => false,
+        }
+    }
+}
+
+/// Chance of an object being placed at a distance, linearly interpolated between the points.
+///
+/// Objects are always placed when there are no points.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct SpawnCurve(Vec<SpawnPoint>);
+
+impl SpawnCurve {
+    /// Chance between `0.0` and `1.0` of being placed at a horizontal distance.
+    pub fn chance(&self, distance: f64) -> f64 {
+        let (Some(first), Some(last)) = (self.0.first(), self.0.last()) else {
+            return 1.0;
+        };
+        if distance <= first.distance {
+            return first.chance;
+        }
+
+        self.0
+            .windows(2)
+            .find(|points| distance < points[1].distance)
+            .map(|points| {
+                let (a, b) = (points[0], points[1]);
+                let t = (distance - a.distance) / (b.distance - a.distance);
+
+                a.chance + (b.chance - a.chance) * t
+            })
+            .unwrap_or(last.chance)
+    }
+
+    /// Whether there are no points.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Chance of being placed at a horizontal distance.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct SpawnPoint {
+    pub distance: f64,
+    pub chance: f64,
+}
+
+/// Object carrying the player, like a bird.
+#[derive(Debug, Clone)]
+pub struct Carrier {
+    /// Sprite drawn above the player.
+    pub sprite_path: String,
+    /// Velocity the player is moved with.
+    pub vel: Vec2<f64>,
+    /// Seconds before the player is let go.
+    pub time_left: f64,
+}
 
 #[derive(Debug)]
 pub struct Object {
@@ -14,66 +118,130 @@ pub struct Object {
     lock_y: Option<f64>,
     start_at: Vec2<f64>,
     collider: Extent2<f64>,
+    /// Effects applied when the player collides with it, the object respawns afterwards.
+    pub on_collision: Vec<Effect>,
+    /// When it's drawn.
+    pub layer: DrawLayer,
+    /// How much the sprite covers what's behind it.
+    opacity: f64,
+    /// Whether it moves towards the player when the player has a magnet.
+    pub magnetic: bool,
+    /// Whether the vertical position is relative to the height of the ground.
+    pub on_ground: bool,
+    /// Acceleration applied to the player every second while overlapping it, the object doesn't respawn for it.
+    pub overlap_acceleration: Vec2<f64>,
+    /// Chance of being placed at a distance, objects are moved further ahead until they are placed.
+    spawn_curve: SpawnCurve,
 }
 
 impl Object {
-    pub fn reset(&mut self, pos: Vec2<f64>, vel: Vec2<f64>) {
+    /// Maximum amount of times the spawn curve is checked before placing an object anyway.
+    const MAX_SPAWN_TRIES: usize = 64;
+
+    /// Place the object again, ahead of the player when it's not the first time.
+    pub fn reset(&mut self, pos: Vec2<f64>, vel: Vec2<f64>, camera: &Camera, rng: &mut Rng) {
+        let view = camera.view();
+        let size = view.size();
+
         if pos.x == 0.0 {
             self.pos.x =
-                self.start_at.x + SIZE.w as f64 * 3.0 * fastrand::f64() * self.repeat_distance;
+                view.min.x + self.start_at.x + size.w * 3.0 * rng.f64() * self.repeat_distance;
             self.pos.y =
-                self.start_at.y + SIZE.h as f64 * 3.0 * fastrand::f64() * self.repeat_distance;
+                view.min.y + self.start_at.y + size.h * 3.0 * rng.f64() * self.repeat_distance;
         } else if self.lock_y.is_none() {
-            let vel_norm = vel.normalized().rotated_z(fastrand::f64() - 0.5);
+            let vel_norm = vel.normalized().rotated_z(rng.f64() - 0.5);
 
-            let biggest = SIZE.w.max(SIZE.h) as f64;
-            self.pos.x = (SIZE.w as f64 / 2.0 + vel_norm.x * biggest) * self.repeat_distance;
-            self.pos.y = (SIZE.h as f64 / 2.0 + vel_norm.y * biggest) * self.repeat_distance;
+            let biggest = size.w.max(size.h);
+            self.pos.x = view.min.x + (size.w / 2.0 + vel_norm.x * biggest) * self.repeat_distance;
+            self.pos.y = view.min.y + (size.h / 2.0 + vel_norm.y * biggest) * self.repeat_distance;
 
-            while self.pos.x < self.start_at.x - pos.x {
-                self.pos.x += SIZE.w as f64;
+            while self.pos.x < self.start_at.x {
+                self.pos.x += size.w;
             }
-            while self.pos.y > -self.start_at.y - pos.y {
-                self.pos.y -= SIZE.h as f64;
+            while self.pos.y > -self.start_at.y {
+                self.pos.y -= size.h;
             }
         } else {
-            self.pos.x = (SIZE.w as f64 * 2.0).max(self.start_at.x - pos.x)
-                + SIZE.w as f64 * fastrand::f64() * self.repeat_distance;
+            self.pos.x = (view.min.x + size.w * 2.0).max(self.start_at.x)
+                + size.w * rng.f64() * self.repeat_distance;
+        }
+
+        // Skip ahead until the distance allows it to be placed
+        if !self.spawn_curve.is_empty() {
+            for _ in 0..Self::MAX_SPAWN_TRIES {
+                if rng.f64() < self.spawn_curve.chance(self.pos.x) {
+                    break;
+                }
+
+                self.pos.x += size.w;
+            }
         }
     }
 
-    pub fn update(&mut self, pos: Vec2<f64>, vel: Vec2<f64>, player_offset: Vec2<f64>, dt: f64) {
+    pub fn update(
+        &mut self,
+        pos: Vec2<f64>,
+        vel: Vec2<f64>,
+        player_offset: Vec2<f64>,
+        camera: &Camera,
+        rng: &mut Rng,
+    ) {
+        // Parallax moves along with a part of the camera movement
         if let Some(lock_x) = self.lock_x {
-            self.pos.x = lock_x - pos.x + player_offset.x + self.start_at.x;
+            self.pos.x = lock_x + self.start_at.x;
         } else {
-            self.pos.x -= vel.x * dt * (1.0 - self.parallax.x);
+            self.pos.x += camera.delta().x * self.parallax.x;
         }
         if let Some(lock_y) = self.lock_y {
-            self.pos.y =
-                lock_y - pos.y * (1.0 - self.parallax.y) + player_offset.y + self.start_at.y;
+            self.pos.y = lock_y
+                + self.start_at.y
+                + (camera.view().min.y + player_offset.y) * self.parallax.y;
         } else {
-            self.pos.y -= vel.y * dt * (1.0 - self.parallax.y);
+            self.pos.y += camera.delta().y * self.parallax.y;
         }
 
-        if self.lock_x.is_none() && self.pos.x < -(SIZE.w as f64) {
-            self.reset(pos, vel);
+        let view = camera.view();
+        if self.lock_x.is_none() && self.pos.x < view.min.x - view.size().w {
+            self.reset(pos, vel, camera, rng);
+        }
+    }
+
+    /// Move towards a position when it's ahead of it and close enough.
+    pub fn attract(&mut self, pos: Vec2<f64>, distance: f64, speed: f64) {
+        let delta = pos - self.pos;
+        if delta.x <= 0.0 && delta.magnitude() < distance {
+            self.pos += delta.normalized() * speed;
         }
     }
 
     pub fn collides_user(&self, player_rect: Rect<f64, f64>) -> bool {
+        self.collider().collides_with_rect(player_rect)
+    }
+
+    /// Path of the sprite it's drawn with.
+    pub fn sprite_path(&self) -> &str {
+        &self.sprite_path
+    }
+
+    /// Collision box in the world.
+    pub fn collider(&self) -> Rect<f64, f64> {
         Rect::new(self.pos.x, self.pos.y, self.collider.w, self.collider.h)
-            .collides_with_rect(player_rect)
     }
 
-    pub fn render(&self, canvas: &mut [u32], screenshake: Vec2<f64>) {
+    pub fn render(&self, canvas: &mut Canvas, camera: &Camera, assets: &Assets) {
         /*
         let aabr = Rect::new(self.pos.x, self.pos.y, self.collider.w, self.collider.h).into_aabr();
         crate::render_aabr(aabr, canvas, 0xFFFF0000);
         */
 
-        crate::sprite(&self.sprite_path).render(
+        // Far away objects shake less
+        let shake = camera.shake() * self.parallax * camera.zoom() as f64;
+
+        assets.sprite(&self.sprite_path).render_blended(
             canvas,
-            self.pos + screenshake * (Vec2::new(1.0, 1.0) - self.parallax),
+            camera,
+            self.pos - shake,
+            Blend::new(BlendMode::Alpha, self.opacity),
         );
     }
 }
@@ -95,13 +263,34 @@ pub struct ObjectsSpawner {
     start_at: Vec2<f64>,
     #[serde(default)]
     collider: Extent2<f64>,
+    /// Effects applied when the player collides with an object.
+    #[serde(default)]
+    on_collision: Vec<Effect>,
+    /// When the objects are drawn.
+    #[serde(default)]
+    layer: DrawLayer,
+    /// How much the sprites cover what's behind them, from 0.0 for invisible to 1.0 for opaque.
+    #[serde(default = "ObjectsSpawner::default_opacity")]
+    opacity: f64,
+    /// Whether the objects move towards the player when the player has a magnet.
+    #[serde(default)]
+    magnetic: bool,
+    /// Whether the vertical position is relative to the height of the ground.
+    #[serde(default)]
+    on_ground: bool,
+    /// Acceleration applied to the player every second while overlapping an object.
+    #[serde(default)]
+    overlap_acceleration: Vec2<f64>,
+    /// Chance of an object being placed at a distance.
+    #[serde(default)]
+    spawn_curve: SpawnCurve,
 }
 
 impl ObjectsSpawner {
-    pub fn to_objects(&self) -> Vec<Object> {
+    pub fn to_objects(&self, camera: &Camera, rng: &mut Rng) -> Vec<Object> {
         let mut objects = (0..self.amount)
             .map(|_| {
-                let parallax_x = self.parallax_x.value();
+                let parallax_x = self.parallax_x.value(rng);
                 let mut obj = Object {
                     pos: Vec2::zero(),
                     repeat_distance: self.repeat_distance,
@@ -111,9 +300,16 @@ impl ObjectsSpawner {
                     lock_y: self.lock_y,
                     start_at: self.start_at,
                     collider: self.collider,
+                    on_collision: self.on_collision.clone(),
+                    layer: self.layer,
+                    opacity: self.opacity,
+                    magnetic: self.magnetic,
+                    on_ground: self.on_ground,
+                    overlap_acceleration: self.overlap_acceleration,
+                    spawn_curve: self.spawn_curve.clone(),
                 };
 
-                obj.reset(Vec2::zero(), Vec2::zero());
+                obj.reset(Vec2::zero(), Vec2::zero(), camera, rng);
 
                 obj
             })
@@ -123,6 +319,11 @@ impl ObjectsSpawner {
 
         objects
     }
+
+    /// Objects are opaque by default.
+    fn default_opacity() -> f64 {
+        1.0
+    }
 }
 
 impl Asset for ObjectsSpawner {
@@ -130,3 +331,85 @@ impl Asset for ObjectsSpawner {
 
     type Loader = TomlLoader;
 }
+
+#[cfg(test)]
+mod tests {
+    use vek::Vec2;
+
+    use super::{Effect, SpawnCurve, SpawnPoint};
+
+    /// Every effect kind must be readable from an object file.
+    #[test]
+    fn test_effect_deserialize() {
+        #[derive(serde::Deserialize)]
+        struct Effects {
+            on_collision: Vec<Effect>,
+        }
+
+        let effects: Effects = toml::from_str(
+            r#"
+            [[on_collision]]
+            type = "money"
+            amount = 2
+
+            [[on_collision]]
+            type = "impulse"
+            force = { x = 1.0, y = 2.0 }
+
+            [[on_collision]]
+            type = "slow_down"
+            factor = { x = 0.5, y = 1.0 }
+
+            [[on_collision]]
+            type = "bonus_boost"
+            boost = { x = 1.5, y = 1.1 }
+
+            [[on_collision]]
+            type = "explode"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            effects.on_collision,
+            vec![
+                Effect::Money { amount: 2 },
+                Effect::Impulse {
+                    force: Some(Vec2::new(1.0, 2.0))
+                },
+                Effect::SlowDown {
+                    factor: Vec2::new(0.5, 1.0)
+                },
+                Effect::BonusBoost {
+                    boost: Vec2::new(1.5, 1.1)
+                },
+                Effect::Explode,
+            ]
+        );
+    }
+
+    /// The chance must be interpolated between the points and clamped outside of them.
+    #[test]
+    fn test_spawn_curve() {
+        assert_eq!(SpawnCurve::default().chance(100.0), 1.0);
+
+        let curve = SpawnCurve(vec![
+            SpawnPoint {
+                distance: 1000.0,
+                chance: 0.0,
+            },
+            SpawnPoint {
+                distance: 2000.0,
+                chance: 1.0,
+            },
+            SpawnPoint {
+                distance: 4000.0,
+                chance: 0.5,
+            },
+        ]);
+        assert_eq!(curve.chance(0.0), 0.0);
+        assert_eq!(curve.chance(1500.0), 0.5);
+        assert_eq!(curve.chance(3000.0), 0.75);
+        assert_eq!(curve.chance(9000.0), 0.5);
+    }
+}
@@ -1,13 +1,27 @@
-use vek::Vec2;
+use fastrand::Rng;
+use vek::{Rect, Vec2};
 
-use crate::{graphics::Color, SIZE};
+use crate::{
+    camera::Camera,
+    graphics::{
+        blend::{Blend, BlendMode},
+        canvas::Canvas,
+        Color,
+    },
+    SIZE,
+};
 
 pub struct Particle {
+    /// Position in the world when affected by the world, otherwise on the screen.
     pos: Vec2<f64>,
     vel: Vec2<f64>,
     color: Color,
     affected_by_world: bool,
     life: f64,
+    /// Life when spawned, the particle fades out over it.
+    start_life: f64,
+    /// How the particle is combined with the canvas.
+    blend_mode: BlendMode,
 }
 
 impl Particle {
@@ -18,15 +32,16 @@ impl Particle {
         color: Color,
         affected_by_world: bool,
         life: f64,
+        rng: &mut Rng,
     ) -> Self {
-        debug_assert!(pos.x >= 0.0);
-        debug_assert!(pos.y >= 0.0);
-        debug_assert!(pos.x < SIZE.w as f64);
-        debug_assert!(pos.y < SIZE.h as f64);
+        debug_assert!(affected_by_world || pos.x >= 0.0);
+        debug_assert!(affected_by_world || pos.y >= 0.0);
+        debug_assert!(affected_by_world || pos.x < SIZE.w as f64);
+        debug_assert!(affected_by_world || pos.y < SIZE.h as f64);
 
         vel += Vec2::new(
-            fastrand::f64() * force * 2.0 - force,
-            fastrand::f64() * force * 2.0 - force,
+            rng.f64() * force * 2.0 - force,
+            rng.f64() * force * 2.0 - force,
         );
 
         Self {
@@ -35,28 +50,48 @@ impl Particle {
             color,
             affected_by_world,
             life,
+            start_life: life,
+            blend_mode: BlendMode::Alpha,
         }
     }
 
-    pub fn update(&mut self, vel: Vec2<f64>, gravity: f64, dt: f64) -> bool {
+    /// Combine the particle with the canvas using a different blend mode.
+    pub fn with_blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+
+        self
+    }
+
+    pub fn update(&mut self, gravity: f64, dt: f64, camera: &Camera) -> bool {
         self.pos += self.vel * dt;
-        if self.affected_by_world {
-            self.pos -= vel * dt;
-        }
         self.vel.y += gravity;
         self.life -= dt;
 
-        !(self.pos.x < 1.0
-            || self.pos.y < 1.0
-            || self.pos.x >= SIZE.w as f64 - 1.0
-            || self.pos.y >= SIZE.h as f64 - 1.0)
-            && self.life > 0.0
+        self.is_on_screen(camera) && self.life > 0.0
+    }
+
+    pub fn render(&self, canvas: &mut Canvas, camera: &Camera) {
+        let pos: Vec2<i32> = self.screen_pos(camera).as_();
+        let color = self.color.as_u32();
+        let blend = Blend::new(self.blend_mode, self.life / self.start_life);
+        canvas.blend(pos - (0, 1), color, blend);
+        canvas.blend_rect(Rect::new(pos.x - 1, pos.y, 3, 1), color, blend);
+        canvas.blend(pos + (0, 1), color, blend);
+    }
+
+    /// Whether the whole particle can be drawn.
+    fn is_on_screen(&self, camera: &Camera) -> bool {
+        let pos = self.screen_pos(camera);
+
+        pos.x >= 1.0 && pos.y >= 1.0 && pos.x < SIZE.w as f64 - 1.0 && pos.y < SIZE.h as f64 - 1.0
     }
 
-    pub fn render(&self, canvas: &mut [u32]) {
-        let index = self.pos.x as usize + self.pos.y as usize * SIZE.w;
-        canvas[index - SIZE.w] = self.color.as_u32();
-        canvas[(index - 1)..(index + 2)].fill(self.color.as_u32());
-        canvas[index + SIZE.w] = self.color.as_u32();
+    /// Position on the screen.
+    fn screen_pos(&self, camera: &Camera) -> Vec2<f64> {
+        if self.affected_by_world {
+            camera.world_to_screen(self.pos)
+        } else {
+            self.pos
+        }
     }
 }
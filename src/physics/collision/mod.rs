@@ -0,0 +1,526 @@
+//! Finding and resolving contacts between shapes.
+
+pub mod shape;
+
+use vek::Vec2;
+
+use self::shape::Shape;
+use super::{
+    rigidbody::{cross, RigidBody, RigidBodyKey},
+    Settings,
+};
+use crate::math::Iso;
+
+/// Point where two shapes overlap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contact {
+    /// Absolute position.
+    pub point: Vec2<f64>,
+    /// Direction pointing from the first shape into the second shape.
+    pub normal: Vec2<f64>,
+    /// How far the shapes overlap along the normal.
+    pub depth: f64,
+}
+
+impl Contact {
+    /// Swap which shape is first.
+    fn flipped(self) -> Self {
+        Self {
+            normal: -self.normal,
+            ..self
+        }
+    }
+}
+
+/// Find where two shapes in the world overlap.
+pub fn collide(a: &Shape, a_iso: Iso, b: &Shape, b_iso: Iso) -> Vec<Contact> {
+    match (a, b) {
+        (Shape::Circle { radius: a_radius }, Shape::Circle { radius: b_radius }) => {
+            circle_circle(a_iso.pos, *a_radius, b_iso.pos, *b_radius)
+                .into_iter()
+                .collect()
+        }
+        (Shape::Polygon { vertices }, Shape::Circle { radius }) => {
+            polygon_circle(&Shape::world_vertices(vertices, a_iso), b_iso.pos, *radius)
+                .into_iter()
+                .collect()
+        }
+        (Shape::Circle { radius }, Shape::Polygon { vertices }) => {
+            polygon_circle(&Shape::world_vertices(vertices, b_iso), a_iso.pos, *radius)
+                .map(Contact::flipped)
+                .into_iter()
+                .collect()
+        }
+        (
+            Shape::Polygon {
+                vertices: a_vertices,
+            },
+            Shape::Polygon {
+                vertices: b_vertices,
+            },
+        ) => polygon_polygon(
+            &Shape::world_vertices(a_vertices, a_iso),
+            &Shape::world_vertices(b_vertices, b_iso),
+        ),
+        (Shape::Heightmap { heights, spacing }, Shape::Circle { radius }) => {
+            heightmap_circle(heights, *spacing, a_iso.pos, b_iso.pos, *radius)
+                .into_iter()
+                .collect()
+        }
+        (Shape::Circle { radius }, Shape::Heightmap { heights, spacing }) => {
+            heightmap_circle(heights, *spacing, b_iso.pos, a_iso.pos, *radius)
+                .map(Contact::flipped)
+                .into_iter()
+                .collect()
+        }
+        (Shape::Heightmap { heights, spacing }, Shape::Polygon { vertices }) => heightmap_polygon(
+            heights,
+            *spacing,
+            a_iso.pos,
+            &Shape::world_vertices(vertices, b_iso),
+        ),
+        (Shape::Polygon { vertices }, Shape::Heightmap { heights, spacing }) => heightmap_polygon(
+            heights,
+            *spacing,
+            b_iso.pos,
+            &Shape::world_vertices(vertices, a_iso),
+        )
+        .into_iter()
+        .map(Contact::flipped)
+        .collect(),
+        // Static bodies never collide with each other
+        (Shape::Heightmap { .. }, Shape::Heightmap { .. }) => Vec::new(),
+    }
+}
+
+/// Contact between two rigid bodies being resolved.
+#[derive(Debug)]
+pub(super) struct ContactConstraint {
+    /// First body.
+    pub(super) a: RigidBodyKey,
+    /// Second body.
+    pub(super) b: RigidBodyKey,
+    /// Direction pointing from the first body into the second body.
+    normal: Vec2<f64>,
+    /// How far the bodies overlap.
+    depth: f64,
+    /// Contact point relative to the center of the first body.
+    a_offset: Vec2<f64>,
+    /// Contact point relative to the center of the second body.
+    b_offset: Vec2<f64>,
+    /// Mass felt by an impulse along the normal.
+    normal_mass: f64,
+    /// Mass felt by an impulse along the tangent.
+    tangent_mass: f64,
+    /// Velocity along the normal the bodies should have after resolving.
+    target_normal_vel: f64,
+    /// Velocity along the tangent the bodies should have after resolving.
+    target_tangent_vel: f64,
+    /// Total impulse applied along the normal.
+    normal_impulse: f64,
+}
+
+impl ContactConstraint {
+    /// Prepare resolving a contact.
+    pub(super) fn new(
+        a_key: RigidBodyKey,
+        a: &RigidBody,
+        b_key: RigidBodyKey,
+        b: &RigidBody,
+        contact: Contact,
+        settings: &Settings,
+    ) -> Self {
+        let normal = contact.normal;
+        let tangent = Vec2::new(-normal.y, normal.x);
+        let a_offset = contact.point - a.iso.pos;
+        let b_offset = contact.point - b.iso.pos;
+
+        let effective_mass = |dir: Vec2<f64>| {
+            let inv_mass = a.inv_mass
+                + b.inv_mass
+                + a.inv_inertia * cross(a_offset, dir).powi(2)
+                + b.inv_inertia * cross(b_offset, dir).powi(2);
+
+            if inv_mass > 0.0 {
+                inv_mass.recip()
+            } else {
+                0.0
+            }
+        };
+
+        let relative_vel = b.velocity_at(b_offset) - a.velocity_at(a_offset);
+
+        // Only bounce when hitting hard enough, otherwise resting bodies keep jittering
+        let normal_vel = relative_vel.dot(normal);
+        let target_normal_vel = if normal_vel < -settings.bounce_threshold {
+            -normal_vel * a.restitution.max(b.restitution)
+        } else {
+            0.0
+        };
+        let target_tangent_vel = relative_vel.dot(tangent) * (1.0 - a.friction.max(b.friction));
+
+        Self {
+            a: a_key,
+            b: b_key,
+            normal,
+            depth: contact.depth,
+            a_offset,
+            b_offset,
+            normal_mass: effective_mass(normal),
+            tangent_mass: effective_mass(tangent),
+            target_normal_vel,
+            target_tangent_vel,
+            normal_impulse: 0.0,
+        }
+    }
+
+    /// Apply an impulse to move the velocities closer to the targets.
+    pub(super) fn solve_velocity(&mut self, a: &mut RigidBody, b: &mut RigidBody) {
+        let relative_vel = b.velocity_at(self.b_offset) - a.velocity_at(self.a_offset);
+
+        // Bodies can only be pushed apart, so the total impulse can't become negative
+        let impulse = self.normal_mass * (self.target_normal_vel - relative_vel.dot(self.normal));
+        let total = (self.normal_impulse + impulse).max(0.0);
+        let impulse = total - self.normal_impulse;
+        self.normal_impulse = total;
+        self.apply(a, b, self.normal * impulse);
+
+        // Friction only works when the bodies are pressed together
+        if self.normal_impulse <= 0.0 {
+            return;
+        }
+
+        let tangent = Vec2::new(-self.normal.y, self.normal.x);
+        let relative_vel = b.velocity_at(self.b_offset) - a.velocity_at(self.a_offset);
+        let impulse = self.tangent_mass * (self.target_tangent_vel - relative_vel.dot(tangent));
+        self.apply(a, b, tangent * impulse);
+    }
+
+    /// Move the bodies apart so they don't overlap anymore.
+    pub(super) fn solve_position(&self, a: &mut RigidBody, b: &mut RigidBody, settings: &Settings) {
+        let inv_mass = a.inv_mass + b.inv_mass;
+        if inv_mass <= 0.0 {
+            return;
+        }
+
+        let correction = (self.depth - settings.allowed_penetration).max(0.0)
+            * settings.position_correction
+            / inv_mass;
+        a.iso.pos -= self.normal * correction * a.inv_mass;
+        b.iso.pos += self.normal * correction * b.inv_mass;
+    }
+
+    /// Push both bodies in opposite directions.
+    fn apply(&self, a: &mut RigidBody, b: &mut RigidBody, impulse: Vec2<f64>) {
+        a.apply_impulse_at(-impulse, self.a_offset);
+        b.apply_impulse_at(impulse, self.b_offset);
+    }
+}
+
+/// Contact between two circles.
+fn circle_circle(
+    a_center: Vec2<f64>,
+    a_radius: f64,
+    b_center: Vec2<f64>,
+    b_radius: f64,
+) -> Option<Contact> {
+    let delta = b_center - a_center;
+    let distance = delta.magnitude();
+    if distance >= a_radius + b_radius {
+        return None;
+    }
+
+    // Push down when both are at the exact same position
+    let normal = delta.try_normalized().unwrap_or(Vec2::unit_y());
+
+    Some(Contact {
+        point: a_center + normal * a_radius,
+        normal,
+        depth: a_radius + b_radius - distance,
+    })
+}
+
+/// Contact between a polygon and a circle, the normal points into the circle.
+fn polygon_circle(vertices: &[Vec2<f64>], center: Vec2<f64>, radius: f64) -> Option<Contact> {
+    // Find the edge the center is furthest outside of
+    let mut separation = f64::NEG_INFINITY;
+    let mut normal = Vec2::unit_y();
+    for (start, _, edge_normal) in edges(vertices) {
+        let edge_separation = (center - start).dot(edge_normal);
+        if edge_separation > radius {
+            return None;
+        }
+
+        if edge_separation > separation {
+            separation = edge_separation;
+            normal = edge_normal;
+        }
+    }
+
+    if separation <= 0.0 {
+        // Center is inside of the polygon
+        return Some(Contact {
+            point: center - normal * radius,
+            normal,
+            depth: radius - separation,
+        });
+    }
+
+    // Center is outside, find the closest point on the edges
+    let closest = edges(vertices)
+        .map(|(start, end, _)| {
+            let edge = end - start;
+            let frac = ((center - start).dot(edge) / edge.magnitude_squared()).clamp(0.0, 1.0);
+
+            start + edge * frac
+        })
+        .min_by(|a, b| {
+            a.distance_squared(center)
+                .total_cmp(&b.distance_squared(center))
+        })?;
+
+    let delta = center - closest;
+    let distance = delta.magnitude();
+    if distance >= radius {
+        return None;
+    }
+
+    Some(Contact {
+        point: closest,
+        normal: delta.try_normalized().unwrap_or(normal),
+        depth: radius - distance,
+    })
+}
+
+/// Contacts between two convex polygons, the normal points into the second polygon.
+///
+/// Uses the separating axis theorem, the contact points are found by clipping the edges against each other.
+fn polygon_polygon(a: &[Vec2<f64>], b: &[Vec2<f64>]) -> Vec<Contact> {
+    let (a_separation, a_edge) = max_separation(a, b);
+    if a_separation > 0.0 {
+        return Vec::new();
+    }
+    let (b_separation, b_edge) = max_separation(b, a);
+    if b_separation > 0.0 {
+        return Vec::new();
+    }
+
+    // The edge closest to separating the polygons is the reference, prefer the first polygon for stability
+    let (reference, incident, edge, flip) = if b_separation > a_separation + 0.01 {
+        (b, a, b_edge, true)
+    } else {
+        (a, b, a_edge, false)
+    };
+    let Some((start, end, normal)) = edges(reference).nth(edge) else {
+        return Vec::new();
+    };
+
+    // The edge of the other polygon facing the reference edge the most
+    let Some((incident_start, incident_end, _)) =
+        edges(incident).min_by(|(_, _, a), (_, _, b)| a.dot(normal).total_cmp(&b.dot(normal)))
+    else {
+        return Vec::new();
+    };
+
+    // Cut the incident edge off at the sides of the reference edge
+    let side = (end - start).normalized();
+    let Some(points) = clip([incident_start, incident_end], side, side.dot(start))
+        .and_then(|points| clip(points, -side, -side.dot(end)))
+    else {
+        return Vec::new();
+    };
+
+    points
+        .into_iter()
+        .filter_map(|point| {
+            let separation = (point - start).dot(normal);
+
+            (separation <= 0.0).then(|| {
+                let contact = Contact {
+                    point,
+                    normal,
+                    depth: -separation,
+                };
+
+                if flip {
+                    contact.flipped()
+                } else {
+                    contact
+                }
+            })
+        })
+        .collect()
+}
+
+/// Contact between a heightmap and a circle, the normal points out of the ground.
+fn heightmap_circle(
+    heights: &[f64],
+    spacing: f64,
+    origin: Vec2<f64>,
+    center: Vec2<f64>,
+    radius: f64,
+) -> Option<Contact> {
+    let (height, normal) = heightmap_surface(heights, spacing, origin, center.x)?;
+
+    // Distance from the slope below the center
+    let distance = (center.y - height) * normal.y;
+    if distance >= radius {
+        return None;
+    }
+
+    Some(Contact {
+        point: center - normal * radius,
+        normal,
+        depth: radius - distance,
+    })
+}
+
+/// Contacts between a heightmap and every vertex of a polygon below the ground.
+fn heightmap_polygon(
+    heights: &[f64],
+    spacing: f64,
+    origin: Vec2<f64>,
+    vertices: &[Vec2<f64>],
+) -> Vec<Contact> {
+    vertices
+        .iter()
+        .filter_map(|vertex| {
+            let (height, normal) = heightmap_surface(heights, spacing, origin, vertex.x)?;
+            if vertex.y <= height {
+                return None;
+            }
+
+            Some(Contact {
+                point: *vertex,
+                normal,
+                depth: (vertex.y - height) * -normal.y,
+            })
+        })
+        .collect()
+}
+
+/// Absolute height and normal pointing out of the ground of a heightmap at an absolute position.
+fn heightmap_surface(
+    heights: &[f64],
+    spacing: f64,
+    origin: Vec2<f64>,
+    x: f64,
+) -> Option<(f64, Vec2<f64>)> {
+    let x = x - origin.x;
+    let height = Shape::height_at(heights, spacing, x)?;
+
+    // Slope between the neighbours, so ramps launch and dips slow down
+    let left = Shape::height_at(heights, spacing, x - spacing).unwrap_or(height);
+    let right = Shape::height_at(heights, spacing, x + spacing).unwrap_or(height);
+    let slope = (right - left) / (spacing * 2.0);
+
+    Some((origin.y + height, Vec2::new(slope, -1.0).normalized()))
+}
+
+/// Maximum distance between any edge of the first polygon and the closest vertex of the second.
+///
+/// Returns the index of that edge, when the distance is positive the polygons don't overlap.
+fn max_separation(a: &[Vec2<f64>], b: &[Vec2<f64>]) -> (f64, usize) {
+    edges(a)
+        .enumerate()
+        .map(|(index, (start, _, normal))| {
+            let separation = b
+                .iter()
+                .map(|vertex| (*vertex - start).dot(normal))
+                .fold(f64::INFINITY, f64::min);
+
+            (separation, index)
+        })
+        .fold((f64::NEG_INFINITY, 0), |max, edge| {
+            if edge.0 > max.0 {
+                edge
+            } else {
+                max
+            }
+        })
+}
+
+/// Start, end and outward normal of every edge of a convex polygon.
+fn edges(vertices: &[Vec2<f64>]) -> impl Iterator<Item = (Vec2<f64>, Vec2<f64>, Vec2<f64>)> + '_ {
+    let center = vertices.iter().copied().sum::<Vec2<f64>>() / vertices.len().max(1) as f64;
+
+    vertices
+        .iter()
+        .zip(vertices.iter().cycle().skip(1))
+        .map(move |(start, end)| {
+            let edge = *end - *start;
+            let normal = Vec2::new(edge.y, -edge.x).normalized();
+
+            // Works for both windings
+            if normal.dot(*start - center) < 0.0 {
+                (*start, *end, -normal)
+            } else {
+                (*start, *end, normal)
+            }
+        })
+}
+
+/// Only keep the part of a line segment in the direction from an offset.
+fn clip(points: [Vec2<f64>; 2], dir: Vec2<f64>, offset: f64) -> Option<[Vec2<f64>; 2]> {
+    let start = points[0].dot(dir) - offset;
+    let end = points[1].dot(dir) - offset;
+
+    match (start >= 0.0, end >= 0.0) {
+        (true, true) => Some(points),
+        (false, false) => None,
+        (start_inside, _) => {
+            let intersection = points[0] + (points[1] - points[0]) * (start / (start - end));
+
+            if start_inside {
+                Some([points[0], intersection])
+            } else {
+                Some([intersection, points[1]])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vek::{Extent2, Vec2};
+
+    use super::{collide, shape::Shape};
+    use crate::math::Iso;
+
+    /// Overlapping boxes must be pushed apart along the axis with the least overlap.
+    #[test]
+    fn test_polygons() {
+        let shape = Shape::rectangle(Extent2::new(10.0, 10.0));
+
+        let contacts = collide(
+            &shape,
+            Iso::from_pos(Vec2::zero()),
+            &shape,
+            Iso::from_pos(Vec2::new(8.0, 1.0)),
+        );
+        assert_eq!(contacts.len(), 2);
+        for contact in contacts {
+            assert!((contact.normal - Vec2::unit_x()).magnitude() < 1e-6);
+            assert!((contact.depth - 2.0).abs() < 1e-6);
+        }
+
+        // Rotated box touching with a corner
+        let contacts = collide(
+            &shape,
+            Iso::from_pos(Vec2::zero()),
+            &shape,
+            Iso::new(Vec2::new(0.0, -11.0), 45f64.to_radians()),
+        );
+        assert_eq!(contacts.len(), 1);
+        assert!((contacts[0].normal + Vec2::unit_y()).magnitude() < 1e-6);
+
+        // Not touching
+        assert!(collide(
+            &shape,
+            Iso::from_pos(Vec2::zero()),
+            &Shape::Circle { radius: 2.0 },
+            Iso::from_pos(Vec2::new(8.0, 0.0)),
+        )
+        .is_empty());
+    }
+}
@@ -0,0 +1,118 @@
+use std::f64::consts::PI;
+
+use vek::{Aabr, Extent2, Vec2};
+
+use crate::math::Iso;
+
+/// Collider of a rigid body, relative to its center of mass.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    /// Circle around the center.
+    Circle { radius: f64 },
+    /// Convex polygon with the vertices around the center.
+    Polygon { vertices: Vec<Vec2<f64>> },
+    /// Ground that's solid below the heights, only for static bodies.
+    ///
+    /// The first height is at the position of the body, rotation is ignored.
+    Heightmap { heights: Vec<f64>, spacing: f64 },
+}
+
+impl Shape {
+    /// Rectangle around the center.
+    pub fn rectangle(size: Extent2<f64>) -> Self {
+        let (w, h) = (size.w / 2.0, size.h / 2.0);
+
+        Self::Polygon {
+            vertices: vec![
+                Vec2::new(-w, -h),
+                Vec2::new(w, -h),
+                Vec2::new(w, h),
+                Vec2::new(-w, h),
+            ],
+        }
+    }
+
+    /// Bounding box in the world.
+    pub fn aabr(&self, iso: Iso) -> Aabr<f64> {
+        match self {
+            Self::Circle { radius } => Aabr {
+                min: iso.pos - *radius,
+                max: iso.pos + *radius,
+            },
+            Self::Polygon { vertices } => {
+                let mut vertices = vertices.iter().map(|vertex| iso.translate(*vertex));
+                let first = vertices.next().unwrap_or(iso.pos);
+
+                vertices.fold(Aabr::new_empty(first), |aabr, vertex| {
+                    aabr.expanded_to_contain_point(vertex)
+                })
+            }
+            Self::Heightmap { heights, spacing } => {
+                let top = heights.iter().copied().fold(f64::INFINITY, f64::min);
+
+                // Everything below the ground is solid
+                Aabr {
+                    min: iso.pos + Vec2::new(0.0, top),
+                    max: Vec2::new(
+                        iso.pos.x + heights.len().saturating_sub(1) as f64 * spacing,
+                        f64::INFINITY,
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Mass and moment of inertia around the center.
+    ///
+    /// Heightmaps have no mass since they are infinitely deep.
+    pub fn mass_properties(&self, density: f64) -> (f64, f64) {
+        match self {
+            Self::Circle { radius } => {
+                let mass = PI * radius * radius * density;
+
+                (mass, mass * radius * radius / 2.0)
+            }
+            Self::Polygon { vertices } => {
+                // Sum the triangles from the center to every edge
+                let (area, inertia) = vertices.iter().zip(vertices.iter().cycle().skip(1)).fold(
+                    (0.0, 0.0),
+                    |(area, inertia), (a, b)| {
+                        let cross = (a.x * b.y - a.y * b.x).abs();
+
+                        (
+                            area + cross / 2.0,
+                            inertia + cross * (a.dot(*a) + a.dot(*b) + b.dot(*b)) / 12.0,
+                        )
+                    },
+                );
+
+                (area * density, inertia * density)
+            }
+            Self::Heightmap { .. } => (0.0, 0.0),
+        }
+    }
+
+    /// Vertices of a polygon in the world.
+    pub(super) fn world_vertices(vertices: &[Vec2<f64>], iso: Iso) -> Vec<Vec2<f64>> {
+        vertices
+            .iter()
+            .map(|vertex| iso.translate(*vertex))
+            .collect()
+    }
+
+    /// Height of a heightmap at a position relative to the first height, interpolated.
+    ///
+    /// Nothing when the position is outside of the heightmap.
+    pub(super) fn height_at(heights: &[f64], spacing: f64, x: f64) -> Option<f64> {
+        let index = x / spacing;
+        if heights.is_empty() || index < 0.0 || index > heights.len().saturating_sub(1) as f64 {
+            return None;
+        }
+
+        let left = index.floor() as usize;
+        let frac = index.fract();
+        let right = heights.get(left + 1).copied().unwrap_or(heights[left]);
+
+        Some(heights[left] * (1.0 - frac) + right * frac)
+    }
+}
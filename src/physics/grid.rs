@@ -0,0 +1,126 @@
+use vek::{Aabr, Extent2, Vec2};
+
+use super::rigidbody::RigidBodyKey;
+
+/// Broadphase dividing a part of the world in equally sized cells.
+///
+/// Only bodies sharing a cell can collide.
+#[derive(Debug, Default)]
+pub struct SpatialGrid {
+    /// World position of the top left corner.
+    offset: Vec2<f64>,
+    /// Width and height of a single cell.
+    cell_size: f64,
+    /// Amount of cells horizontally and vertically.
+    size: Extent2<usize>,
+    /// Bodies overlapping every cell, row by row.
+    cells: Vec<Vec<RigidBodyKey>>,
+}
+
+impl SpatialGrid {
+    /// Create an empty grid.
+    pub fn new(offset: Vec2<f64>, cell_size: f64, size: Extent2<usize>) -> Self {
+        let cells = vec![Vec::new(); size.product()];
+
+        Self {
+            offset,
+            cell_size,
+            size,
+            cells,
+        }
+    }
+
+    /// Remove all bodies.
+    pub fn clear(&mut self) {
+        self.cells.iter_mut().for_each(|cell| cell.clear());
+    }
+
+    /// Add a body to every cell its bounding box overlaps.
+    pub fn insert(&mut self, key: RigidBodyKey, aabr: Aabr<f64>) {
+        let Some((min, max)) = self.cell_range(aabr) else {
+            return;
+        };
+
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                self.cells[x + y * self.size.w].push(key);
+            }
+        }
+    }
+
+    /// Whether a bounding box overlaps any of the cells.
+    pub fn contains(&self, aabr: Aabr<f64>) -> bool {
+        self.cell_range(aabr).is_some()
+    }
+
+    /// All unique pairs of bodies sharing a cell, sorted.
+    pub fn pairs(&self) -> Vec<(RigidBodyKey, RigidBodyKey)> {
+        let mut pairs = Vec::new();
+        for cell in self.cells.iter() {
+            for (index, a) in cell.iter().enumerate() {
+                for b in cell[index + 1..].iter() {
+                    pairs.push((*a.min(b), *a.max(b)));
+                }
+            }
+        }
+
+        pairs.sort_unstable();
+        pairs.dedup();
+
+        pairs
+    }
+
+    /// First and last cell a bounding box overlaps, nothing when it's outside of the grid.
+    fn cell_range(&self, aabr: Aabr<f64>) -> Option<(Vec2<usize>, Vec2<usize>)> {
+        if self.cells.is_empty() || self.cell_size <= 0.0 {
+            return None;
+        }
+
+        let min = (aabr.min - self.offset) / self.cell_size;
+        let max = (aabr.max - self.offset) / self.cell_size;
+        let size = self.size.as_::<f64>();
+        if max.x < 0.0 || max.y < 0.0 || min.x >= size.w || min.y >= size.h {
+            return None;
+        }
+
+        let cell = |pos: Vec2<f64>| {
+            Vec2::new(
+                pos.x.floor().clamp(0.0, size.w - 1.0) as usize,
+                pos.y.floor().clamp(0.0, size.h - 1.0) as usize,
+            )
+        };
+
+        Some((cell(min), cell(max)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vek::{Aabr, Extent2, Vec2};
+
+    use super::SpatialGrid;
+    use crate::physics::rigidbody::RigidBodyKey;
+
+    /// Only bodies close to each other and on the grid must be paired.
+    #[test]
+    fn test_pairs() {
+        let mut grid = SpatialGrid::new(Vec2::zero(), 10.0, Extent2::new(10, 10));
+        let aabr = |x: f64, y: f64| Aabr {
+            min: Vec2::new(x, y),
+            max: Vec2::new(x + 5.0, y + 5.0),
+        };
+
+        let keys = (0..4)
+            .map(|index| RigidBodyKey::new(index, 0))
+            .collect::<Vec<_>>();
+        grid.insert(keys[0], aabr(1.0, 1.0));
+        grid.insert(keys[1], aabr(8.0, 8.0));
+        grid.insert(keys[2], aabr(50.0, 50.0));
+        // Outside of the grid
+        grid.insert(keys[3], aabr(-20.0, 1.0));
+
+        assert_eq!(grid.pairs(), vec![(keys[0], keys[1])]);
+        assert!(!grid.contains(aabr(-20.0, 1.0)));
+        assert!(grid.contains(aabr(95.0, 95.0)));
+    }
+}
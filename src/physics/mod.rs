@@ -0,0 +1,485 @@
+//! Small 2D rigid body physics engine.
+
+pub mod collision;
+pub mod grid;
+pub mod rigidbody;
+
+use serde::{Deserialize, Serialize};
+use vek::{Extent2, Vec2};
+
+use self::{
+    collision::{Contact, ContactConstraint},
+    grid::SpatialGrid,
+    rigidbody::{RigidBody, RigidBodyHandle, RigidBodyKey},
+};
+use crate::flight::IntegratorKind;
+
+/// Simulation of all rigid bodies.
+///
+/// Only bodies on the broadphase grid collide with each other.
+#[derive(Debug, Default)]
+pub struct Physics {
+    /// All rigid bodies, deleted ones leave an empty slot that will be reused.
+    slots: Vec<Slot>,
+    /// Indices of empty slots.
+    free: Vec<u32>,
+    /// Broadphase dividing the simulated part of the world in cells.
+    grid: SpatialGrid,
+    /// Pairs of rigid bodies that touched during the last step.
+    collisions: Vec<(RigidBodyKey, RigidBodyKey)>,
+}
+
+impl Physics {
+    /// Create an empty simulation.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Move the simulation forward in time.
+    pub fn step(&mut self, dt: f64, settings: &Settings) {
+        puffin::profile_scope!("Physics step");
+
+        self.collisions.clear();
+
+        let sub_steps = settings.sub_steps.max(1);
+        let sub_dt = dt / sub_steps as f64;
+        for _ in 0..sub_steps {
+            self.integrate(sub_dt, settings.integrator);
+
+            let mut constraints = self.detect_collisions(settings);
+            self.solve(&mut constraints, settings);
+        }
+
+        // Forces and torques only last a single step
+        self.rigidbodies_mut().for_each(|rigidbody| {
+            rigidbody.force = Vec2::zero();
+            rigidbody.torque = 0.0;
+
+            rigidbody.update_sleeping(dt, settings);
+        });
+
+        self.collisions.sort_unstable();
+        self.collisions.dedup();
+    }
+
+    /// Center the broadphase grid at a world position.
+    ///
+    /// Everything outside of it won't collide.
+    pub fn move_grid(&mut self, center: Vec2<f64>, settings: &Settings) {
+        let size = settings.grid_cells.as_::<f64>() * settings.grid_cell_size;
+
+        self.grid = SpatialGrid::new(
+            center - Vec2::from(size) / 2.0,
+            settings.grid_cell_size,
+            settings.grid_cells,
+        );
+    }
+
+    /// Whether the rigid body is inside the broadphase grid, which means it can collide.
+    pub fn is_rigidbody_on_grid(&self, rigidbody: &RigidBodyHandle) -> bool {
+        self.grid.contains(self.rigidbody(rigidbody.key()).aabr())
+    }
+
+    /// Amount of rigid bodies in the simulation.
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    /// Whether there are no rigid bodies in the simulation.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add a rigid body to the simulation.
+    fn add(&mut self, rigidbody: RigidBody) -> RigidBodyKey {
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.generation += 1;
+                slot.rigidbody = Some(rigidbody);
+
+                RigidBodyKey::new(index, slot.generation)
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    rigidbody: Some(rigidbody),
+                });
+
+                RigidBodyKey::new(self.slots.len() as u32 - 1, 0)
+            }
+        }
+    }
+
+    /// Remove a rigid body from the simulation.
+    fn remove(&mut self, key: RigidBodyKey) {
+        self.slot_mut(key).rigidbody = None;
+        self.free.push(key.index());
+        self.collisions.retain(|(a, b)| *a != key && *b != key);
+    }
+
+    /// Get a rigid body.
+    ///
+    /// # Panics
+    ///
+    /// When the rigid body doesn't exist in this simulation.
+    fn rigidbody(&self, key: RigidBodyKey) -> &RigidBody {
+        self.slots
+            .get(key.index() as usize)
+            .filter(|slot| slot.generation == key.generation())
+            .and_then(|slot| slot.rigidbody.as_ref())
+            .expect("Rigid body doesn't exist in this physics simulation")
+    }
+
+    /// Get a mutable rigid body.
+    ///
+    /// # Panics
+    ///
+    /// When the rigid body doesn't exist in this simulation.
+    fn rigidbody_mut(&mut self, key: RigidBodyKey) -> &mut RigidBody {
+        self.slot_mut(key)
+            .rigidbody
+            .as_mut()
+            .expect("Rigid body doesn't exist in this physics simulation")
+    }
+
+    /// Keys of all rigid bodies touching a rigid body during the last step.
+    fn collision_keys_iter(&self, key: RigidBodyKey) -> impl Iterator<Item = RigidBodyKey> + '_ {
+        self.collisions.iter().filter_map(move |(a, b)| {
+            if *a == key {
+                Some(*b)
+            } else if *b == key {
+                Some(*a)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Apply the forces and move all awake rigid bodies.
+    fn integrate(&mut self, dt: f64, integrator: IntegratorKind) {
+        self.rigidbodies_mut()
+            .filter(|rigidbody| rigidbody.is_dynamic() && !rigidbody.sleeping)
+            .for_each(|rigidbody| rigidbody.integrate(dt, integrator));
+    }
+
+    /// Find all contacts between rigid bodies on the grid.
+    fn detect_collisions(&mut self, settings: &Settings) -> Vec<ContactConstraint> {
+        self.grid.clear();
+        for (index, slot) in self.slots.iter().enumerate() {
+            if let Some(rigidbody) = slot
+                .rigidbody
+                .as_ref()
+                .filter(|rigidbody| rigidbody.collides())
+            {
+                self.grid.insert(
+                    RigidBodyKey::new(index as u32, slot.generation),
+                    rigidbody.aabr(),
+                );
+            }
+        }
+
+        let mut constraints = Vec::new();
+        for (a_key, b_key) in self.grid.pairs() {
+            let (a, b) = (self.rigidbody(a_key), self.rigidbody(b_key));
+
+            // At least one of the bodies must be able to move
+            let (a_dynamic, b_dynamic) = (a.is_dynamic(), b.is_dynamic());
+            let a_moves = a_dynamic && !a.sleeping;
+            let b_moves = b_dynamic && !b.sleeping;
+            if !a_moves && !b_moves {
+                continue;
+            }
+
+            if !a.aabr().collides_with_aabr(b.aabr()) {
+                continue;
+            }
+
+            let contacts = collision::collide(&a.shape, a.iso, &b.shape, b.iso);
+            if contacts.is_empty() {
+                continue;
+            }
+
+            // Something moving touched a sleeping body
+            if !a_moves && a_dynamic {
+                self.rigidbody_mut(a_key).wake();
+            }
+            if !b_moves && b_dynamic {
+                self.rigidbody_mut(b_key).wake();
+            }
+
+            let (a, b) = (self.rigidbody(a_key), self.rigidbody(b_key));
+            constraints.extend(contacts.into_iter().map(|contact: Contact| {
+                ContactConstraint::new(a_key, a, b_key, b, contact, settings)
+            }));
+            self.collisions.push((a_key.min(b_key), a_key.max(b_key)));
+        }
+
+        constraints
+    }
+
+    /// Apply impulses until the contacts are resolved and push the bodies apart.
+    fn solve(&mut self, constraints: &mut [ContactConstraint], settings: &Settings) {
+        for _ in 0..settings.iterations.max(1) {
+            for constraint in constraints.iter_mut() {
+                let (a, b) = self.pair_mut(constraint.a, constraint.b);
+                constraint.solve_velocity(a, b);
+            }
+        }
+
+        for constraint in constraints.iter() {
+            let (a, b) = self.pair_mut(constraint.a, constraint.b);
+            constraint.solve_position(a, b, settings);
+        }
+    }
+
+    /// Get two different rigid bodies mutably.
+    fn pair_mut(&mut self, a: RigidBodyKey, b: RigidBodyKey) -> (&mut RigidBody, &mut RigidBody) {
+        assert_ne!(a.index(), b.index(), "Rigid body can't collide with itself");
+
+        let (a_index, b_index) = (a.index() as usize, b.index() as usize);
+        let (a_slot, b_slot) = if a_index < b_index {
+            let (left, right) = self.slots.split_at_mut(b_index);
+            (&mut left[a_index], &mut right[0])
+        } else {
+            let (left, right) = self.slots.split_at_mut(a_index);
+            (&mut right[0], &mut left[b_index])
+        };
+
+        (
+            a_slot.rigidbody.as_mut().expect("Rigid body was removed"),
+            b_slot.rigidbody.as_mut().expect("Rigid body was removed"),
+        )
+    }
+
+    /// Get the slot of a key, checking whether it's still the same rigid body.
+    fn slot_mut(&mut self, key: RigidBodyKey) -> &mut Slot {
+        self.slots
+            .get_mut(key.index() as usize)
+            .filter(|slot| slot.generation == key.generation())
+            .expect("Rigid body doesn't exist in this physics simulation")
+    }
+
+    /// Iterate over all rigid bodies mutably.
+    fn rigidbodies_mut(&mut self) -> impl Iterator<Item = &mut RigidBody> {
+        self.slots
+            .iter_mut()
+            .filter_map(|slot| slot.rigidbody.as_mut())
+    }
+}
+
+/// Storage of a single rigid body.
+#[derive(Debug)]
+struct Slot {
+    /// Incremented every time the slot is reused, so old keys can't access new bodies.
+    generation: u32,
+    /// Body, nothing when it's deleted.
+    rigidbody: Option<RigidBody>,
+}
+
+/// Physics settings loaded from a file so it's easier to change them with hot-reloading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Settings {
+    /// Numerical method used to move the bodies.
+    #[serde(default)]
+    pub integrator: IntegratorKind,
+    /// Amount of steps every update is split in, more is more accurate.
+    pub sub_steps: usize,
+    /// Amount of times the contacts are solved every sub step, more is more stable.
+    pub iterations: usize,
+    /// Fraction of the overlap of bodies resolved every sub step.
+    pub position_correction: f64,
+    /// Overlap in pixels that is allowed so resting bodies don't jitter.
+    pub allowed_penetration: f64,
+    /// Velocity into each other below which bodies don't bounce anymore.
+    pub bounce_threshold: f64,
+    /// Velocity below which a body can fall asleep.
+    pub sleep_velocity: f64,
+    /// Angular velocity below which a body can fall asleep.
+    pub sleep_angular_velocity: f64,
+    /// Seconds a body must be slow before it falls asleep.
+    pub time_before_sleep: f64,
+    /// Size of a single cell of the broadphase grid.
+    pub grid_cell_size: f64,
+    /// Amount of cells of the broadphase grid.
+    pub grid_cells: Extent2<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use vek::{Extent2, Vec2};
+
+    use super::{
+        collision::shape::Shape,
+        rigidbody::{RigidBodyBuilder, RigidBodyHandle},
+        Physics, Settings,
+    };
+    use crate::flight::IntegratorKind;
+
+    fn settings() -> Settings {
+        Settings {
+            integrator: IntegratorKind::SemiImplicitEuler,
+            sub_steps: 4,
+            iterations: 8,
+            position_correction: 0.5,
+            allowed_penetration: 0.1,
+            bounce_threshold: 10.0,
+            sleep_velocity: 2.0,
+            sleep_angular_velocity: 0.1,
+            time_before_sleep: 0.5,
+            grid_cell_size: 64.0,
+            grid_cells: Extent2::new(16, 16),
+        }
+    }
+
+    /// Physics with a flat or sloped ground at a height of zero.
+    fn ground(slope: f64, restitution: f64, friction: f64) -> (Physics, RigidBodyHandle) {
+        let mut physics = Physics::new();
+        physics.move_grid(Vec2::zero(), &settings());
+
+        let heights = (0..=200).map(|x| x as f64 * slope).collect();
+        let ground = RigidBodyBuilder::new_static(Vec2::new(-100.0, -100.0 * slope))
+            .with_collider(Shape::Heightmap {
+                heights,
+                spacing: 1.0,
+            })
+            .with_restitution(restitution)
+            .with_friction(friction)
+            .spawn(&mut physics);
+
+        (physics, ground)
+    }
+
+    /// A falling box must come to a rest on the ground and fall asleep.
+    #[test]
+    fn test_rest() {
+        let settings = settings();
+        let (mut physics, ground) = ground(0.0, 0.2, 0.5);
+        let rigidbody = RigidBodyBuilder::new_dynamic(Vec2::new(0.0, -50.0))
+            .with_collider(Shape::rectangle(Extent2::new(10.0, 10.0)))
+            .with_gravity(100.0)
+            .spawn(&mut physics);
+
+        let mut touched = false;
+        for _ in 0..600 {
+            physics.step(1.0 / 60.0, &settings);
+            touched |= rigidbody
+                .collision_keys_iter(&physics)
+                .any(|key| ground == key);
+        }
+
+        assert!(touched);
+        assert!(rigidbody.is_sleeping(&physics));
+        assert!((rigidbody.position(&physics).y + 5.0).abs() < 0.5);
+        assert!(rigidbody.orientation(&physics).to_degrees().abs() < 1.0);
+    }
+
+    /// A body without collisions must fall through the ground and never fall asleep.
+    #[test]
+    fn test_without_collisions() {
+        let settings = settings();
+        let (mut physics, _) = ground(0.0, 0.2, 0.5);
+        let rigidbody = RigidBodyBuilder::new_dynamic(Vec2::new(0.0, -10.0))
+            .with_collider(Shape::rectangle(Extent2::new(10.0, 10.0)))
+            .without_collisions()
+            .spawn(&mut physics);
+        for _ in 0..60 {
+            physics.step(1.0 / 60.0, &settings);
+        }
+        assert!(!rigidbody.is_sleeping(&physics));
+        assert_eq!(rigidbody.collision_keys_iter(&physics).count(), 0);
+
+        rigidbody.set_velocity(Vec2::new(0.0, 100.0), &mut physics);
+        physics.step(1.0, &settings);
+        assert!(rigidbody.position(&physics).y > 50.0);
+    }
+
+    /// Bouncing must keep the fraction of the velocity set by the restitution and turn friction into spin.
+    #[test]
+    fn test_bounce() {
+        let settings = settings();
+
+        // Flat ground
+        let (mut physics, _) = ground(0.0, 0.5, 0.4);
+        let rigidbody = RigidBodyBuilder::new_dynamic(Vec2::new(0.0, -6.0))
+            .with_collider(Shape::rectangle(Extent2::new(10.0, 10.0)))
+            .with_velocity(Vec2::new(100.0, 100.0))
+            .spawn(&mut physics);
+        physics.step(1.0 / 30.0, &settings);
+
+        let vel = rigidbody.velocity(&physics);
+        assert!((vel.y + 50.0).abs() < 1.0);
+        assert!(vel.x < 100.0);
+        assert!(rigidbody.angular_velocity(&physics) > 0.0);
+
+        // Ramp going up to the right, the horizontal speed is turned upwards
+        let (mut physics, _) = ground(-1.0, 0.5, 0.4);
+        let rigidbody = RigidBodyBuilder::new_dynamic(Vec2::new(-10.0, 2.0))
+            .with_collider(Shape::Circle { radius: 5.0 })
+            .with_velocity(Vec2::new(100.0, 0.0))
+            .spawn(&mut physics);
+        physics.step(1.0 / 30.0, &settings);
+
+        assert!(rigidbody.velocity(&physics).y < 0.0);
+    }
+
+    /// Torque must spin a body, deleting it must free the slot.
+    #[test]
+    fn test_torque() {
+        let settings = settings();
+        let mut physics = Physics::new();
+
+        let rigidbody = RigidBodyBuilder::new_dynamic(Vec2::zero())
+            .with_collider(Shape::rectangle(Extent2::new(10.0, 4.0)))
+            .spawn(&mut physics);
+        rigidbody.apply_torque(1000.0, &mut physics);
+        physics.step(1.0 / 60.0, &settings);
+        let ang_vel = 1000.0 / rigidbody.inertia(&physics) / 60.0;
+        assert!((rigidbody.angular_velocity(&physics) - ang_vel).abs() < 1e-9);
+
+        // Torque only lasts a single step
+        physics.step(1.0 / 60.0, &settings);
+        assert!((rigidbody.angular_velocity(&physics) - ang_vel).abs() < 1e-9);
+
+        // Outside of the grid, it's not moved there yet
+        assert!(!physics.is_rigidbody_on_grid(&rigidbody));
+        physics.move_grid(Vec2::zero(), &settings);
+        assert!(physics.is_rigidbody_on_grid(&rigidbody));
+
+        rigidbody.delete(&mut physics);
+        assert!(physics.is_empty());
+    }
+
+    /// Drag must slow a body down without blowing up when it's out of range, deleting it must free the slot.
+    #[test]
+    fn test_drag() {
+        let settings = settings();
+        let mut physics = Physics::new();
+
+        let slowed = RigidBodyBuilder::new_dynamic(Vec2::zero())
+            .with_velocity(Vec2::new(100.0, 0.0))
+            .with_angular_velocity(1.0)
+            .with_linear_drag(0.5)
+            .with_angular_drag(0.5)
+            .spawn(&mut physics);
+        let stopped = RigidBodyBuilder::new_dynamic(Vec2::new(100.0, 0.0))
+            .with_velocity(Vec2::new(100.0, 0.0))
+            .with_angular_velocity(1.0)
+            .with_linear_drag(1.0)
+            .with_angular_drag(2.0)
+            .spawn(&mut physics);
+        for _ in 0..60 {
+            physics.step(1.0 / 60.0, &settings);
+        }
+
+        assert!((slowed.velocity(&physics).x - 50.0).abs() < 1.0);
+        assert!((slowed.angular_velocity(&physics) - 0.5).abs() < 0.01);
+        assert!(stopped.velocity(&physics).x.abs() < 1.0);
+        assert!(stopped.angular_velocity(&physics).abs() < 0.01);
+        assert!(stopped.position(&physics).x.is_finite());
+
+        slowed.delete(&mut physics);
+        stopped.delete(&mut physics);
+        assert!(physics.is_empty());
+    }
+}
@@ -0,0 +1,470 @@
+use vek::{Aabr, Vec2};
+
+use super::{collision::shape::Shape, Physics, Settings};
+use crate::{
+    flight::{Body, Integrator, IntegratorKind},
+    math::{Iso, Rotation},
+};
+
+/// Highest fraction of the velocity drag can remove every second.
+const MAX_DRAG: f64 = 0.999;
+
+/// Identifies a rigid body in the physics simulation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RigidBodyKey {
+    /// Slot in the simulation.
+    index: u32,
+    /// Which time the slot is used, so keys of deleted bodies don't point to new ones.
+    generation: u32,
+}
+
+impl RigidBodyKey {
+    /// Construct from a slot.
+    pub(super) fn new(index: u32, generation: u32) -> Self {
+        Self { index, generation }
+    }
+
+    /// Slot in the simulation.
+    pub(super) fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Which time the slot is used.
+    pub(super) fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+/// Owned reference to a rigid body in the physics simulation.
+///
+/// Can't be cloned, so a body can only be deleted through its single owner.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RigidBodyHandle(RigidBodyKey);
+
+impl RigidBodyHandle {
+    /// Key to compare with collisions.
+    pub fn key(&self) -> RigidBodyKey {
+        self.0
+    }
+
+    /// Absolute position of the center of mass.
+    pub fn position(&self, physics: &Physics) -> Vec2<f64> {
+        physics.rigidbody(self.0).iso.pos
+    }
+
+    /// Move the body to an absolute position.
+    pub fn set_position(&self, pos: Vec2<f64>, physics: &mut Physics) {
+        let rigidbody = physics.rigidbody_mut(self.0);
+        rigidbody.iso.pos = pos;
+        rigidbody.wake();
+    }
+
+    /// Direction the body is rotated in.
+    pub fn orientation(&self, physics: &Physics) -> Rotation {
+        physics.rigidbody(self.0).iso.rot
+    }
+
+    /// Rotate the body.
+    pub fn set_orientation(&self, rot: Rotation, physics: &mut Physics) {
+        let rigidbody = physics.rigidbody_mut(self.0);
+        rigidbody.iso.rot = rot;
+        rigidbody.wake();
+    }
+
+    /// Position and rotation.
+    pub fn iso(&self, physics: &Physics) -> Iso {
+        physics.rigidbody(self.0).iso
+    }
+
+    /// Pixels per second.
+    pub fn velocity(&self, physics: &Physics) -> Vec2<f64> {
+        physics.rigidbody(self.0).vel
+    }
+
+    /// Change the pixels per second.
+    pub fn set_velocity(&self, vel: Vec2<f64>, physics: &mut Physics) {
+        let rigidbody = physics.rigidbody_mut(self.0);
+        rigidbody.vel = vel;
+        rigidbody.wake();
+    }
+
+    /// Radians per second.
+    pub fn angular_velocity(&self, physics: &Physics) -> f64 {
+        physics.rigidbody(self.0).ang_vel
+    }
+
+    /// Change the radians per second.
+    pub fn set_angular_velocity(&self, ang_vel: f64, physics: &mut Physics) {
+        let rigidbody = physics.rigidbody_mut(self.0);
+        rigidbody.ang_vel = ang_vel;
+        rigidbody.wake();
+    }
+
+    /// Instantly change the velocity by pushing at the center of mass.
+    pub fn apply_impulse(&self, impulse: Vec2<f64>, physics: &mut Physics) {
+        let rigidbody = physics.rigidbody_mut(self.0);
+        rigidbody.vel += impulse * rigidbody.inv_mass;
+        rigidbody.wake();
+    }
+
+    /// Push at the center of mass during the next step.
+    pub fn apply_force(&self, force: Vec2<f64>, physics: &mut Physics) {
+        let rigidbody = physics.rigidbody_mut(self.0);
+        rigidbody.force += force;
+        rigidbody.wake();
+    }
+
+    /// Rotate during the next step, positive is clockwise.
+    pub fn apply_torque(&self, torque: f64, physics: &mut Physics) {
+        let rigidbody = physics.rigidbody_mut(self.0);
+        rigidbody.torque += torque;
+        rigidbody.wake();
+    }
+
+    /// Resistance against rotating, torque is divided by it.
+    pub fn inertia(&self, physics: &Physics) -> f64 {
+        recip_or_zero(physics.rigidbody(self.0).inv_inertia)
+    }
+
+    /// Replace the collider, the mass stays the same.
+    pub fn set_shape(&self, shape: Shape, physics: &mut Physics) {
+        physics.rigidbody_mut(self.0).shape = shape;
+    }
+
+    /// Change how bouncy and rough the body is.
+    pub fn set_material(&self, restitution: f64, friction: f64, physics: &mut Physics) {
+        let rigidbody = physics.rigidbody_mut(self.0);
+        rigidbody.restitution = restitution;
+        rigidbody.friction = friction;
+    }
+
+    /// Keys of all bodies this body touched during the last step.
+    pub fn collision_keys_iter<'a>(
+        &self,
+        physics: &'a Physics,
+    ) -> impl Iterator<Item = RigidBodyKey> + 'a {
+        physics.collision_keys_iter(self.0)
+    }
+
+    /// Whether the body is resting and not simulated until something touches it.
+    pub fn is_sleeping(&self, physics: &Physics) -> bool {
+        physics.rigidbody(self.0).sleeping
+    }
+
+    /// Remove the body from the simulation.
+    pub fn delete(self, physics: &mut Physics) {
+        physics.remove(self.0);
+    }
+}
+
+impl PartialEq<RigidBodyKey> for RigidBodyHandle {
+    fn eq(&self, other: &RigidBodyKey) -> bool {
+        self.0 == *other
+    }
+}
+
+/// Construct a rigid body.
+#[derive(Debug, Clone)]
+pub struct RigidBodyBuilder {
+    /// Whether the body can move.
+    dynamic: bool,
+    /// Position and rotation.
+    iso: Iso,
+    /// Pixels per second.
+    vel: Vec2<f64>,
+    /// Radians per second.
+    ang_vel: f64,
+    /// Shape used for collisions and the mass.
+    shape: Shape,
+    /// Whether it touches other bodies.
+    collides: bool,
+    /// Mass of a single square pixel.
+    density: f64,
+    /// Fraction of the velocity into another body kept when bouncing.
+    restitution: f64,
+    /// Fraction of the velocity along another body lost when touching.
+    friction: f64,
+    /// Downwards acceleration.
+    gravity: f64,
+    /// Fraction of the velocity lost every second.
+    linear_drag: f64,
+    /// Fraction of the angular velocity lost every second.
+    angular_drag: f64,
+}
+
+impl RigidBodyBuilder {
+    /// Body that moves and rotates.
+    pub fn new_dynamic(pos: Vec2<f64>) -> Self {
+        Self::new(pos, true)
+    }
+
+    /// Body that never moves, other bodies collide with it.
+    pub fn new_static(pos: Vec2<f64>) -> Self {
+        Self::new(pos, false)
+    }
+
+    /// Shape used for collisions, also determines the mass.
+    pub fn with_collider(mut self, shape: Shape) -> Self {
+        self.shape = shape;
+
+        self
+    }
+
+    /// Pass through all other bodies, the shape is only used for the mass.
+    pub fn without_collisions(mut self) -> Self {
+        self.collides = false;
+
+        self
+    }
+
+    /// Mass of a single square pixel of the collider.
+    pub fn with_density(mut self, density: f64) -> Self {
+        self.density = density;
+
+        self
+    }
+
+    /// Start rotated.
+    pub fn with_orientation(mut self, rot: Rotation) -> Self {
+        self.iso.rot = rot;
+
+        self
+    }
+
+    /// Start moving.
+    pub fn with_velocity(mut self, vel: Vec2<f64>) -> Self {
+        self.vel = vel;
+
+        self
+    }
+
+    /// Start rotating.
+    pub fn with_angular_velocity(mut self, ang_vel: f64) -> Self {
+        self.ang_vel = ang_vel;
+
+        self
+    }
+
+    /// Fraction of the velocity into another body kept when bouncing.
+    ///
+    /// The bounciest of both bodies is used.
+    pub fn with_restitution(mut self, restitution: f64) -> Self {
+        self.restitution = restitution;
+
+        self
+    }
+
+    /// Fraction of the velocity along another body lost when touching.
+    ///
+    /// The roughest of both bodies is used.
+    pub fn with_friction(mut self, friction: f64) -> Self {
+        self.friction = friction;
+
+        self
+    }
+
+    /// Downwards acceleration.
+    pub fn with_gravity(mut self, gravity: f64) -> Self {
+        self.gravity = gravity;
+
+        self
+    }
+
+    /// Fraction of the velocity lost every second to air resistance.
+    ///
+    /// Clamped below 1.0 because losing everything can't be integrated.
+    pub fn with_linear_drag(mut self, linear_drag: f64) -> Self {
+        self.linear_drag = linear_drag.clamp(0.0, MAX_DRAG);
+
+        self
+    }
+
+    /// Fraction of the angular velocity lost every second to air resistance.
+    ///
+    /// Clamped below 1.0 like the linear drag.
+    pub fn with_angular_drag(mut self, angular_drag: f64) -> Self {
+        self.angular_drag = angular_drag.clamp(0.0, MAX_DRAG);
+
+        self
+    }
+
+    /// Add the body to the simulation.
+    pub fn spawn(self, physics: &mut Physics) -> RigidBodyHandle {
+        let (inv_mass, inv_inertia) = if self.dynamic {
+            let (mass, inertia) = self.shape.mass_properties(self.density);
+
+            (recip_or_zero(mass), recip_or_zero(inertia))
+        } else {
+            (0.0, 0.0)
+        };
+
+        let rigidbody = RigidBody {
+            dynamic: self.dynamic,
+            iso: self.iso,
+            vel: self.vel,
+            ang_vel: self.ang_vel,
+            force: Vec2::zero(),
+            torque: 0.0,
+            inv_mass,
+            inv_inertia,
+            shape: self.shape,
+            collides: self.collides,
+            restitution: self.restitution,
+            friction: self.friction,
+            gravity: self.gravity,
+            linear_drag: self.linear_drag,
+            angular_drag: self.angular_drag,
+            sleeping: false,
+            slow_time: 0.0,
+        };
+
+        RigidBodyHandle(physics.add(rigidbody))
+    }
+
+    /// Body without a shape at a position.
+    fn new(pos: Vec2<f64>, dynamic: bool) -> Self {
+        Self {
+            dynamic,
+            iso: Iso::from_pos(pos),
+            vel: Vec2::zero(),
+            ang_vel: 0.0,
+            shape: Shape::Circle { radius: 1.0 },
+            collides: true,
+            density: 1.0,
+            restitution: 0.0,
+            friction: 0.0,
+            gravity: 0.0,
+            linear_drag: 0.0,
+            angular_drag: 0.0,
+        }
+    }
+}
+
+/// Simulated body, only accessible through its handle.
+#[derive(Debug)]
+pub(super) struct RigidBody {
+    /// Whether the body can move.
+    dynamic: bool,
+    /// Position of the center of mass and rotation.
+    pub(super) iso: Iso,
+    /// Pixels per second.
+    pub(super) vel: Vec2<f64>,
+    /// Radians per second.
+    pub(super) ang_vel: f64,
+    /// Force applied during the next step.
+    pub(super) force: Vec2<f64>,
+    /// Torque applied during the next step.
+    pub(super) torque: f64,
+    /// One divided by the mass, zero for static bodies.
+    pub(super) inv_mass: f64,
+    /// One divided by the moment of inertia, zero for static bodies.
+    pub(super) inv_inertia: f64,
+    /// Collider.
+    pub(super) shape: Shape,
+    /// Whether it touches other bodies.
+    collides: bool,
+    /// Fraction of the velocity into another body kept when bouncing.
+    pub(super) restitution: f64,
+    /// Fraction of the velocity along another body lost when touching.
+    pub(super) friction: f64,
+    /// Downwards acceleration.
+    gravity: f64,
+    /// Fraction of the velocity lost every second.
+    linear_drag: f64,
+    /// Fraction of the angular velocity lost every second.
+    angular_drag: f64,
+    /// Whether the body is resting and not simulated.
+    pub(super) sleeping: bool,
+    /// Seconds the body has been slow enough to fall asleep.
+    slow_time: f64,
+}
+
+impl RigidBody {
+    /// Whether the body can move.
+    pub(super) fn is_dynamic(&self) -> bool {
+        self.dynamic
+    }
+
+    /// Whether it touches other bodies.
+    pub(super) fn collides(&self) -> bool {
+        self.collides
+    }
+
+    /// Bounding box of the collider in the world.
+    pub(super) fn aabr(&self) -> Aabr<f64> {
+        self.shape.aabr(self.iso)
+    }
+
+    /// Velocity of a point relative to the center of mass.
+    pub(super) fn velocity_at(&self, offset: Vec2<f64>) -> Vec2<f64> {
+        self.vel + Vec2::new(-offset.y, offset.x) * self.ang_vel
+    }
+
+    /// Change the velocity by pushing at a point relative to the center of mass.
+    pub(super) fn apply_impulse_at(&mut self, impulse: Vec2<f64>, offset: Vec2<f64>) {
+        self.vel += impulse * self.inv_mass;
+        self.ang_vel += cross(offset, impulse) * self.inv_inertia;
+    }
+
+    /// Simulate again.
+    pub(super) fn wake(&mut self) {
+        self.sleeping = false;
+        self.slow_time = 0.0;
+    }
+
+    /// Apply the forces and move forward in time.
+    pub(super) fn integrate(&mut self, dt: f64, integrator: IntegratorKind) {
+        // Exponential decay written as an acceleration so it doesn't depend on the time step
+        let drag = -(1.0 - self.linear_drag).ln();
+        let acceleration =
+            |vel: Vec2<f64>| Vec2::new(0.0, self.gravity) + self.force * self.inv_mass - vel * drag;
+
+        let mut body = Body {
+            pos: self.iso.pos,
+            vel: self.vel,
+        };
+        integrator.step(&mut body, &acceleration, dt);
+        self.iso.pos = body.pos;
+        self.vel = body.vel;
+
+        self.ang_vel += self.torque * self.inv_inertia * dt;
+        self.ang_vel *= (1.0 - self.angular_drag).powf(dt);
+        self.iso.rot += self.ang_vel * dt;
+    }
+
+    /// Fall asleep when the body has been slow for long enough.
+    ///
+    /// Bodies without collisions can't be woken up by touching, so they never fall asleep.
+    pub(super) fn update_sleeping(&mut self, dt: f64, settings: &Settings) {
+        if !self.dynamic || !self.collides || self.sleeping {
+            return;
+        }
+
+        if self.vel.magnitude() < settings.sleep_velocity
+            && self.ang_vel.abs() < settings.sleep_angular_velocity
+        {
+            self.slow_time += dt;
+            if self.slow_time >= settings.time_before_sleep {
+                self.sleeping = true;
+                self.vel = Vec2::zero();
+                self.ang_vel = 0.0;
+            }
+        } else {
+            self.slow_time = 0.0;
+        }
+    }
+}
+
+/// Perpendicular dot product.
+pub(super) fn cross(a: Vec2<f64>, b: Vec2<f64>) -> f64 {
+    a.x * b.y - a.y * b.x
+}
+
+/// One divided by the value, or zero when it's too small to divide by.
+fn recip_or_zero(value: f64) -> f64 {
+    if value > f64::EPSILON {
+        value.recip()
+    } else {
+        0.0
+    }
+}
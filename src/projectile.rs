@@ -1,16 +1,23 @@
-use vek::Vec2;
+use assets_manager::{loader::TomlLoader, Asset, AssetGuard};
+use serde::Deserialize;
+use vek::{Extent2, Rect, Vec2};
 
 use crate::{
+    assets::Assets,
     camera::Camera,
+    flight::Body,
+    graphics::canvas::Canvas,
     math::Rotation,
-    object::ObjectSettings,
-    physics::{rigidbody::RigidBodyHandle, Physics},
-    unit::Unit,
+    physics::{
+        collision::shape::Shape,
+        rigidbody::{RigidBodyBuilder, RigidBodyHandle},
+        Physics,
+    },
+    terrain::Terrain,
+    unit::Allegiance,
 };
 
-/// Spear asset path.
-const ASSET_PATH: &str = "projectile.spear-1";
-/// Airflow torque strength.
+/// Airflow torque strength, relative to the inertia so it works the same for every size.
 const AIRFLOW_TORQUE: f64 = 30.0;
 /// Angular velocity of the projectile must be lower than this.
 const AIRFLOW_ANG_VEL_CUTOFF: f64 = 1.0;
@@ -20,77 +27,183 @@ const AIRFLOW_VEL_TRESHOLD: f64 = 50.0;
 const AIRFLOW_ROT_RANGE: f64 = 0.5;
 
 /// Projectile that can fly.
+#[derive(Debug)]
 pub struct Projectile {
-    /// Reference to the physics rigid body.
-    pub rigidbody: RigidBodyHandle,
+    /// Body in the physics simulation, passing through everything.
+    rigidbody: RigidBodyHandle,
+    /// Asset path of both the sprite and the settings.
+    asset_path: String,
+    /// Who threw the projectile.
+    pub allegiance: Allegiance,
+    /// Seconds until it disappears.
+    life: f64,
 }
 
 impl Projectile {
     /// Create a new projectile.
-    pub fn new(pos: Vec2<f64>, vel: Vec2<f64>, physics: &mut Physics) -> Self {
-        puffin::profile_function!();
-
-        // Load the object definition for properties of the object
-        let object = crate::asset::<ObjectSettings>(ASSET_PATH);
-
-        let rigidbody = object
-            .rigidbody_builder(pos)
+    pub fn new(
+        pos: Vec2<f64>,
+        vel: Vec2<f64>,
+        asset_path: &str,
+        allegiance: Allegiance,
+        physics: &mut Physics,
+        assets: &Assets,
+    ) -> Self {
+        let settings = assets.asset::<Settings>(asset_path);
+        // Set the rotation towards the direction so the torque won't need to adjust too much
+        let rot = Rotation::from_direction(vel.try_normalized().unwrap_or(Vec2::unit_y()));
+        let rigidbody = RigidBodyBuilder::new_dynamic(pos)
+            .with_collider(Shape::rectangle(settings.collider))
+            .without_collisions()
+            .with_orientation(rot)
             .with_velocity(vel)
-            // Set the rotation towards the direction so the torque won't need to adjust too much
-            .with_orientation_from_direction(vel.try_normalized().unwrap_or(Vec2::unit_y()))
+            .with_gravity(settings.gravity)
             .spawn(physics);
+        let asset_path = asset_path.to_string();
+        let life = settings.life;
 
-        Self { rigidbody }
+        Self {
+            rigidbody,
+            asset_path,
+            allegiance,
+            life,
+        }
     }
 
-    /// Update the physics of the projectile.
+    /// Spin the projectile during the next physics step, it's moved by the physics.
     ///
     /// Returns whether it should stay alive.
-    pub fn update(&self, physics: &mut Physics, units: &mut [Unit], dt: f64) -> bool {
+    pub fn update(&mut self, terrain: &Terrain, physics: &mut Physics, dt: f64) -> bool {
         puffin::profile_scope!("Projectile update");
 
-        let velocity = self.rigidbody.velocity(physics).magnitude();
+        self.life -= dt;
+
+        let Body { pos, vel } = self.body(physics);
+        let velocity = vel.magnitude();
         if velocity >= AIRFLOW_VEL_TRESHOLD {
-            // Let the projectile rotate toward the projectile, simulating air flow
-            let dir = Rotation::from_direction(self.rigidbody.velocity(physics).normalized());
+            // Let the projectile rotate toward the direction it flies in, simulating air flow
+            let dir = Rotation::from_direction(vel / velocity);
             let delta_angle = (dir - self.rigidbody.orientation(physics)).to_radians();
 
             // Only apply when the angular velocity isn't too much already
             if delta_angle.abs() < AIRFLOW_ROT_RANGE
                 && self.rigidbody.angular_velocity(physics).abs() < AIRFLOW_ANG_VEL_CUTOFF
             {
-                // The furture away from the required angle the less of an effect we want
-                self.rigidbody
-                    .apply_torque(delta_angle * AIRFLOW_TORQUE * dt, physics);
+                // The further away from the required angle the less of an effect we want
+                let torque = delta_angle * AIRFLOW_TORQUE * self.rigidbody.inertia(physics);
+                self.rigidbody.apply_torque(torque, physics);
             }
         }
 
-        let mut collided = false;
-        {
-            puffin::profile_scope!("Projectile collision detection");
-
-            // Detect and handle collisions with units
-            for collision_key in self.rigidbody.collision_keys_iter(physics) {
-                if let Some(unit) = units
-                    .iter_mut()
-                    .find(move |unit| unit.rigidbody == collision_key)
-                {
-                    collided = true;
-                    unit.health -= 50.0;
-                }
-            }
-        }
+        // Destroy when it hits the ground or flew for too long
+        self.life > 0.0 && pos.y < terrain.height(pos.x)
+    }
 
-        // Destroy when collided, sleeping or out of range
-        !collided
-            && !self.rigidbody.is_sleeping(physics)
-            && physics.is_rigidbody_on_grid(&self.rigidbody)
+    /// Whether the collider of the projectile overlaps a rectangle in the world.
+    pub fn collides(&self, rect: Rect<f64, f64>, physics: &Physics, assets: &Assets) -> bool {
+        let collider = self.settings(assets).collider;
+        let pos = self.rigidbody.position(physics);
+
+        Rect::new(
+            pos.x - collider.w / 2.0,
+            pos.y - collider.h / 2.0,
+            collider.w,
+            collider.h,
+        )
+        .collides_with_rect(rect)
     }
 
     /// Render the projectile.
-    pub fn render(&self, canvas: &mut [u32], camera: &Camera, physics: &Physics) {
+    pub fn render(&self, physics: &Physics, canvas: &mut Canvas, camera: &Camera, assets: &Assets) {
         puffin::profile_function!();
 
-        crate::rotatable_sprite(ASSET_PATH).render(self.rigidbody.iso(physics), canvas, camera);
+        assets.rotatable_sprite(&self.asset_path).render(
+            self.rigidbody.iso(physics),
+            canvas,
+            camera,
+        );
+    }
+
+    /// Position and velocity.
+    pub fn body(&self, physics: &Physics) -> Body {
+        Body {
+            pos: self.rigidbody.position(physics),
+            vel: self.rigidbody.velocity(physics),
+        }
+    }
+
+    /// Remove the projectile from the physics simulation.
+    pub fn delete(self, physics: &mut Physics) {
+        self.rigidbody.delete(physics);
+    }
+
+    /// The settings for this projectile.
+    pub fn settings<'a>(&self, assets: &'a Assets) -> AssetGuard<'a, Settings> {
+        assets.asset(&self.asset_path)
+    }
+}
+
+/// Projectile settings loaded from a file so it's easier to change them with hot-reloading.
+///
+/// The file is shared with the rotatable sprite metadata.
+#[derive(Debug, Deserialize)]
+pub struct Settings {
+    /// Downwards acceleration.
+    pub gravity: f64,
+    /// Seconds before the projectile disappears.
+    pub life: f64,
+    /// Size of the collision box around the center.
+    pub collider: Extent2<f64>,
+    /// Fraction of the velocity the dino keeps when hit.
+    pub hit_slowdown: Vec2<f64>,
+    /// Health a unit loses when hit.
+    pub damage: f64,
+}
+
+impl Asset for Settings {
+    const EXTENSION: &'static str = "toml";
+
+    type Loader = TomlLoader;
+}
+
+#[cfg(test)]
+mod tests {
+    use fastrand::Rng;
+    use vek::Vec2;
+
+    use super::Projectile;
+    use crate::{
+        assets::Assets, math::Rotation, physics::Physics, terrain::Terrain, unit::Allegiance,
+    };
+
+    /// Airflow must rotate the projectile towards the direction it flies in through the physics.
+    #[test]
+    fn test_airflow() {
+        let assets = Assets::load();
+        let settings = assets.settings();
+        let terrain = Terrain::new(&mut Rng::with_seed(0), &settings.terrain, assets.surfaces());
+        let mut physics = Physics::new();
+        let dt = 1.0 / 60.0;
+
+        let mut projectile = Projectile::new(
+            Vec2::new(0.0, -100.0),
+            Vec2::new(200.0, 0.0),
+            "projectile.spear",
+            Allegiance::Enemy,
+            &mut physics,
+            &assets,
+        );
+        let dir = Rotation::from_direction(Vec2::unit_x());
+        projectile
+            .rigidbody
+            .set_orientation(dir + 0.3, &mut physics);
+
+        assert!(projectile.update(&terrain, &mut physics, dt));
+        physics.step(dt, &settings.physics);
+        assert!(projectile.rigidbody.angular_velocity(&physics) < 0.0);
+        assert!(projectile.body(&physics).pos.x > 0.0);
+
+        projectile.delete(&mut physics);
+        assert!(physics.is_empty());
     }
 }
@@ -1,3 +1,4 @@
+use fastrand::Rng;
 use serde::Deserialize;
 
 /// Either a number or a random range.
@@ -12,22 +13,38 @@ pub enum RandomRangeF64 {
 
 impl RandomRangeF64 {
     /// Calculate the value.
-    pub fn value(&self) -> f64 {
+    pub fn value(&self, rng: &mut Rng) -> f64 {
         match self {
             RandomRangeF64::Static(val) => *val,
-            RandomRangeF64::Range { min, max } => fastrand::f64() * (max - min) + min,
+            RandomRangeF64::Range { min, max } => rng.f64() * (max - min) + min,
         }
     }
 }
 
-impl From<RandomRangeF64> for f64 {
-    fn from(value: RandomRangeF64) -> Self {
-        value.value()
-    }
-}
-
 impl Default for RandomRangeF64 {
     fn default() -> Self {
         Self::Static(0.0)
     }
 }
+
+/// Pick a random index where every item has a chance proportional to its weight.
+///
+/// Returns nothing when all weights are zero.
+pub fn weighted_index(weights: &[f64], rng: &mut Rng) -> Option<usize> {
+    let total = weights.iter().map(|weight| weight.max(0.0)).sum::<f64>();
+    if total <= 0.0 {
+        return None;
+    }
+
+    let mut picked = rng.f64() * total;
+    for (index, weight) in weights.iter().enumerate() {
+        let weight = weight.max(0.0);
+        if weight > 0.0 && picked < weight {
+            return Some(index);
+        }
+        picked -= weight;
+    }
+
+    // Floating point rounding can skip the last item
+    weights.iter().rposition(|weight| *weight > 0.0)
+}
@@ -0,0 +1,371 @@
+use miette::{miette, Result};
+use vek::Vec2;
+
+use crate::{
+    assets::Assets,
+    game::GameState,
+    graphics::canvas::Canvas,
+    input::{ButtonState, Input},
+    save::SaveData,
+    UPDATES_PER_SECOND,
+};
+
+/// Bytes every replay file starts with.
+const MAGIC: &[u8; 4] = b"DARW";
+/// Version of the binary format, must be increased when the format changes.
+///
+/// Version `1` didn't contain the save data yet.
+const VERSION: u8 = 2;
+/// Size in bytes of a single encoded run of equal input.
+const RUN_SIZE: usize = 4 + 2 + 2 + 3;
+/// Most ticks a replay can contain, protects against corrupt files allocating huge amounts of memory.
+const MAX_TICKS: u64 = UPDATES_PER_SECOND as u64 * 60 * 60 * 4;
+
+/// Recorded input of every update tick together with the seed and progression of the game.
+///
+/// Because the game is deterministic this is enough to reproduce a full run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    /// Seed the game was started with.
+    pub seed: u64,
+    /// Progression the game was started with.
+    pub save: SaveData,
+    /// Input for every tick.
+    inputs: Vec<Input>,
+}
+
+impl Replay {
+    /// Start a new empty recording.
+    pub fn new(seed: u64, save: SaveData) -> Self {
+        let inputs = Vec::new();
+
+        Self { seed, save, inputs }
+    }
+
+    /// Record the input for the next tick.
+    pub fn record(&mut self, input: &Input) {
+        self.inputs.push(input.clone());
+    }
+
+    /// Input for a tick, nothing when the replay is finished.
+    pub fn input(&self, tick: usize) -> Option<&Input> {
+        self.inputs.get(tick)
+    }
+
+    /// Amount of recorded ticks.
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Whether nothing is recorded yet.
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Encode into the compact binary format.
+    ///
+    /// Consecutive ticks with the same input are stored as a single run.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 1 + 8);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+
+        // Progression is stored as a length prefixed TOML string
+        let save = self.save.to_toml()?;
+        bytes.extend_from_slice(&(save.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(save.as_bytes());
+
+        let mut iter = self.inputs.iter().peekable();
+        while let Some(input) = iter.next() {
+            // Count how many times the same input repeats
+            let mut length = 1u32;
+            while length < u32::MAX && iter.next_if_eq(&input).is_some() {
+                length += 1;
+            }
+
+            bytes.extend_from_slice(&length.to_le_bytes());
+            bytes.extend_from_slice(
+                &(input.mouse_pos.x.clamp(i16::MIN as i32, i16::MAX as i32) as i16).to_le_bytes(),
+            );
+            bytes.extend_from_slice(
+                &(input.mouse_pos.y.clamp(i16::MIN as i32, i16::MAX as i32) as i16).to_le_bytes(),
+            );
+
+            // Pack every button state in two bits
+            let buttons = input
+                .buttons()
+                .iter()
+                .enumerate()
+                .fold(0u32, |packed, (i, button)| {
+                    packed | (button_to_bits(*button) << (i * 2))
+                });
+            bytes.extend_from_slice(&buttons.to_le_bytes()[..3]);
+        }
+
+        Ok(bytes)
+    }
+
+    /// Decode from the compact binary format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let header_size = MAGIC.len() + 1 + 8;
+        if bytes.len() < header_size || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(miette!("Not a replay file"));
+        }
+
+        let version = bytes[MAGIC.len()];
+        if version == 0 || version > VERSION {
+            return Err(miette!(
+                "Unsupported replay version {version}, expected {VERSION}"
+            ));
+        }
+
+        let seed = u64::from_le_bytes(bytes[(MAGIC.len() + 1)..header_size].try_into().unwrap());
+
+        let (save, runs) = if version >= 2 {
+            let rest = &bytes[header_size..];
+            if rest.len() < 4 {
+                return Err(miette!("Replay file is truncated"));
+            }
+
+            let save_size = u32::from_le_bytes(rest[0..4].try_into().unwrap()) as usize;
+            let save_bytes = rest
+                .get(4..(4 + save_size))
+                .ok_or_else(|| miette!("Replay file is truncated"))?;
+            let save = std::str::from_utf8(save_bytes)
+                .map_err(|err| miette!("Invalid save data in replay: {err}"))?;
+
+            (SaveData::from_toml(save)?, &rest[(4 + save_size)..])
+        } else {
+            // Older replays always started without any progression
+            (SaveData::default(), &bytes[header_size..])
+        };
+
+        if !runs.len().is_multiple_of(RUN_SIZE) {
+            return Err(miette!("Replay file is truncated"));
+        }
+
+        let mut inputs = Vec::new();
+        let mut ticks = 0u64;
+        for run in runs.chunks_exact(RUN_SIZE) {
+            let length = u32::from_le_bytes(run[0..4].try_into().unwrap());
+            ticks = ticks
+                .checked_add(length as u64)
+                .filter(|ticks| *ticks <= MAX_TICKS)
+                .ok_or_else(|| miette!("Replay is longer than {MAX_TICKS} ticks"))?;
+            let mouse_x = i16::from_le_bytes(run[4..6].try_into().unwrap());
+            let mouse_y = i16::from_le_bytes(run[6..8].try_into().unwrap());
+            let buttons = u32::from_le_bytes([run[8], run[9], run[10], 0]);
+
+            let mut input = Input {
+                mouse_pos: Vec2::new(mouse_x as i32, mouse_y as i32),
+                ..Default::default()
+            };
+            for (i, button) in input.buttons_mut().into_iter().enumerate() {
+                *button = bits_to_button((buttons >> (i * 2)) & 0b11)?;
+            }
+
+            inputs.extend(std::iter::repeat_n(input, length as usize));
+        }
+
+        Ok(Self { seed, save, inputs })
+    }
+
+    /// Write the replay to a file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: &str) -> Result<()> {
+        use miette::IntoDiagnostic;
+
+        std::fs::write(path, self.to_bytes()?).into_diagnostic()
+    }
+
+    /// Read a replay from a file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &str) -> Result<Self> {
+        use miette::IntoDiagnostic;
+
+        Self::from_bytes(&std::fs::read(path).into_diagnostic()?)
+    }
+}
+
+/// Records the input of a live game to a file.
+///
+/// The file is rewritten every few seconds and when the recorder is dropped, so a crash or closed window loses little.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Recorder {
+    /// Everything recorded so far.
+    replay: Replay,
+    /// File to write the replay to.
+    path: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Recorder {
+    /// Interval in ticks at which the replay is written to disk.
+    const SAVE_INTERVAL: usize = crate::UPDATES_PER_SECOND as usize * 5;
+
+    /// Start recording a game with a seed and the progression it starts with.
+    pub fn new(seed: u64, save: SaveData, path: String) -> Self {
+        let replay = Replay::new(seed, save);
+
+        Self { replay, path }
+    }
+
+    /// Record the input for the next tick.
+    pub fn record(&mut self, input: &Input) {
+        self.replay.record(input);
+
+        if self.replay.len().is_multiple_of(Self::SAVE_INTERVAL) {
+            self.save();
+        }
+    }
+
+    /// Write the replay, only logging errors since losing a recording shouldn't stop the game.
+    fn save(&self) {
+        if let Err(err) = self.replay.save(&self.path) {
+            log::error!("Error saving replay to '{}': {err:?}", self.path);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.save();
+    }
+}
+
+/// Plays back a replay instead of using the live input.
+///
+/// The live input is only used for controlling the playback:
+/// - `<SPACE>` pauses and resumes.
+/// - `<RIGHT>` steps a single tick while paused.
+pub struct ReplayPlayer {
+    /// Game being replayed.
+    pub game: GameState,
+    /// Recording to play back.
+    replay: Replay,
+    /// Tick of the replay that will be played next.
+    tick: usize,
+    /// Whether the playback is paused.
+    paused: bool,
+}
+
+impl ReplayPlayer {
+    /// Start playing a replay from the first tick.
+    pub fn new(replay: Replay, assets: &Assets) -> Self {
+        let game = GameState::from_save(replay.seed, &replay.save, assets);
+        let tick = 0;
+        let paused = false;
+
+        Self {
+            game,
+            replay,
+            tick,
+            paused,
+        }
+    }
+
+    /// Handle the playback controls and update the game with the recorded input.
+    pub fn update(&mut self, input: &Input, dt: f64, assets: &Assets) {
+        if input.space.is_released() {
+            self.paused = !self.paused;
+        }
+
+        if self.paused && !input.right.is_released() {
+            return;
+        }
+
+        // Stop updating once the replay is finished
+        if let Some(recorded) = self.replay.input(self.tick) {
+            self.game.update(recorded, dt, assets);
+            self.tick += 1;
+        }
+    }
+
+    /// Draw the game with the playback state on top.
+    pub fn render(&mut self, canvas: &mut Canvas, frame_time: f64, assets: &Assets) {
+        self.game.render(canvas, frame_time, assets);
+
+        let state = if self.tick >= self.replay.len() {
+            "END"
+        } else if self.paused {
+            "PAUSED"
+        } else {
+            "PLAY"
+        };
+        assets.font().render(
+            &format!("{state} {}/{}", self.tick, self.replay.len()),
+            Vec2::new(3.0, canvas.height() as f64 - 13.0),
+            canvas,
+        );
+    }
+}
+
+/// Convert a button state to two bits.
+fn button_to_bits(button: ButtonState) -> u32 {
+    match button {
+        ButtonState::None => 0,
+        ButtonState::Released => 1,
+        ButtonState::Pressed => 2,
+    }
+}
+
+/// Convert two bits to a button state.
+fn bits_to_button(bits: u32) -> Result<ButtonState> {
+    match bits {
+        0 => Ok(ButtonState::None),
+        1 => Ok(ButtonState::Released),
+        2 => Ok(ButtonState::Pressed),
+        _ => Err(miette!("Invalid button state in replay")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vek::Vec2;
+
+    use super::{Replay, MAX_TICKS, RUN_SIZE};
+    use crate::{input::Input, save::SaveData};
+
+    /// Encoding and decoding must result in the same replay.
+    #[test]
+    fn test_roundtrip() {
+        let mut replay = Replay::new(0xDEAD_BEEF, SaveData::default());
+
+        let mut input = Input::default();
+        for tick in 0..100 {
+            input.left_mouse.handle_bool(tick % 10 < 3);
+            input.space.handle_bool(tick % 33 == 0);
+            input.mouse_pos = Vec2::new(tick / 7, -tick);
+
+            replay.record(&input);
+            input.update();
+        }
+
+        replay.save.money = 42;
+
+        let decoded = Replay::from_bytes(&replay.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded, replay);
+
+        assert!(Replay::from_bytes(b"nope").is_err());
+    }
+
+    /// Run lengths adding up to more than the maximum amount of ticks must be rejected.
+    #[test]
+    fn test_oversized() {
+        let mut replay = Replay::new(0, SaveData::default());
+        replay.record(&Input::default());
+
+        let mut bytes = replay.to_bytes().unwrap();
+        let run = bytes.len() - RUN_SIZE;
+        bytes[run..(run + 4)].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Replay::from_bytes(&bytes).is_err());
+
+        bytes[run..(run + 4)].copy_from_slice(&(MAX_TICKS as u32 + 1).to_le_bytes());
+        assert!(Replay::from_bytes(&bytes).is_err());
+
+        bytes[run..(run + 4)].copy_from_slice(&100u32.to_le_bytes());
+        assert_eq!(Replay::from_bytes(&bytes).unwrap().len(), 100);
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+
+use miette::{miette, IntoDiagnostic, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::game::GameState;
+
+/// Version of the save data format, must be increased when the format changes.
+///
+/// When increasing it a migration from the previous version must be added to [`MIGRATIONS`].
+pub const VERSION: u32 = 2;
+
+/// Migrations from every older version to the next one.
+///
+/// The item at index `0` migrates version `1` to version `2`, and so on.
+const MIGRATIONS: [fn(&mut toml::value::Table) -> Result<()>; VERSION as usize - 1] =
+    [migrate_v1_to_v2];
+
+/// Progression that persists between sessions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaveData {
+    /// Version of the format this was written with.
+    pub version: u32,
+    /// Money not spent yet.
+    pub money: usize,
+    /// Furthest distance ever flown.
+    pub max_distance: f64,
+    /// How many shops in a row didn't offer a rare card.
+    #[serde(default)]
+    pub shops_without_rare: usize,
+    /// How many times each card is bought, by card asset name.
+    ///
+    /// The effects of the upgrades are derived from this.
+    pub selected_cards: BTreeMap<String, usize>,
+}
+
+impl SaveData {
+    /// Take the progression from a game.
+    pub fn from_game(game: &GameState) -> Self {
+        Self {
+            version: VERSION,
+            money: game.money,
+            max_distance: game.max_distance,
+            shops_without_rare: game.shops_without_rare,
+            selected_cards: game.selected_cards.clone(),
+        }
+    }
+
+    /// Apply the progression to a game.
+    ///
+    /// [`GameState::update_settings`] must be called afterwards for the card effects to apply.
+    pub fn apply(&self, game: &mut GameState) {
+        game.money = self.money;
+        game.max_distance = self.max_distance;
+        game.selected_cards = self.selected_cards.clone();
+        game.shops_without_rare = self.shops_without_rare;
+    }
+
+    /// Serialize to TOML.
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).into_diagnostic()
+    }
+
+    /// Deserialize from TOML, migrating older versions to the current one.
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        let mut table: toml::value::Table = toml::from_str(toml).into_diagnostic()?;
+
+        let version = table
+            .get("version")
+            .and_then(|version| version.as_integer())
+            .ok_or_else(|| miette!("Save data is missing a version"))?;
+        if version < 1 || version > VERSION as i64 {
+            return Err(miette!(
+                "Unsupported save data version {version}, expected at most {VERSION}"
+            ));
+        }
+
+        // Apply every migration step from the old version to the current one
+        for migration in &MIGRATIONS[(version as usize - 1)..] {
+            migration(&mut table)?;
+        }
+        table.insert("version".to_string(), (VERSION as i64).into());
+
+        toml::Value::Table(table).try_into().into_diagnostic()
+    }
+}
+
+impl Default for SaveData {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            money: 0,
+            max_distance: 0.0,
+            shops_without_rare: 0,
+            selected_cards: BTreeMap::new(),
+        }
+    }
+}
+
+/// Version `1` stored the bought upgrades as separate values, now they are derived from the bought cards.
+fn migrate_v1_to_v2(table: &mut toml::value::Table) -> Result<()> {
+    for removed in ["extra_initial_speed", "extra_gravity", "attraction"] {
+        table.remove(removed);
+    }
+
+    Ok(())
+}
+
+/// Where the save data is persisted.
+///
+/// On native this is a file in the XDG data directory, in the browser it's local storage.
+#[derive(Debug, Clone)]
+pub struct Storage {
+    /// File to read and write.
+    #[cfg(not(target_arch = "wasm32"))]
+    path: std::path::PathBuf,
+    /// Local storage key to read and write.
+    #[cfg(target_arch = "wasm32")]
+    key: String,
+}
+
+impl Storage {
+    /// Name used for the directory or key of the save data.
+    const NAME: &'static str = "darwins_ascent";
+
+    /// Default location for the platform.
+    ///
+    /// Returns nothing when the location can't be determined.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn platform_default() -> Option<Self> {
+        use std::path::PathBuf;
+
+        // Follow the XDG base directory specification, which ignores relative paths
+        let data_dir = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+            })?;
+
+        Some(Self::from_path(data_dir.join(Self::NAME).join("save.toml")))
+    }
+
+    /// Default location for the platform.
+    ///
+    /// Returns nothing when the location can't be determined.
+    #[cfg(target_arch = "wasm32")]
+    pub fn platform_default() -> Option<Self> {
+        let key = format!("{}.save", Self::NAME);
+
+        Some(Self { key })
+    }
+
+    /// Use a specific file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_path<P>(path: P) -> Self
+    where
+        P: Into<std::path::PathBuf>,
+    {
+        let path = path.into();
+
+        Self { path }
+    }
+
+    /// Read the save data, returns nothing when nothing is saved yet.
+    pub fn load(&self) -> Result<Option<SaveData>> {
+        self.read()?
+            .map(|toml| SaveData::from_toml(&toml))
+            .transpose()
+    }
+
+    /// Write the save data.
+    pub fn save(&self, data: &SaveData) -> Result<()> {
+        self.write(&data.to_toml()?)
+    }
+
+    /// Read the raw contents.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn read(&self) -> Result<Option<String>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).into_diagnostic(),
+        }
+    }
+
+    /// Write the raw contents.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn write(&self, contents: &str) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).into_diagnostic()?;
+        }
+
+        std::fs::write(&self.path, contents).into_diagnostic()
+    }
+
+    /// Read the raw contents.
+    #[cfg(target_arch = "wasm32")]
+    fn read(&self) -> Result<Option<String>> {
+        local_storage()?
+            .get_item(&self.key)
+            .map_err(|err| miette!("Error reading local storage: {err:?}"))
+    }
+
+    /// Write the raw contents.
+    #[cfg(target_arch = "wasm32")]
+    fn write(&self, contents: &str) -> Result<()> {
+        local_storage()?
+            .set_item(&self.key, contents)
+            .map_err(|err| miette!("Error writing local storage: {err:?}"))
+    }
+}
+
+/// Get the local storage of the browser.
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Result<web_sys::Storage> {
+    web_sys::window()
+        .ok_or_else(|| miette!("No browser window"))?
+        .local_storage()
+        .map_err(|err| miette!("Error accessing local storage: {err:?}"))?
+        .ok_or_else(|| miette!("Local storage is not available"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SaveData;
+
+    /// Serializing and deserializing must result in the same data.
+    #[test]
+    fn test_roundtrip() {
+        let mut data = SaveData {
+            money: 12,
+            max_distance: 9000.5,
+            ..Default::default()
+        };
+        data.selected_cards.insert("wings".to_string(), 1);
+
+        let toml = data.to_toml().unwrap();
+        assert_eq!(SaveData::from_toml(&toml).unwrap(), data);
+
+        // Saves from the future can't be read
+        let future = toml.replace("version = 2", "version = 999");
+        assert!(SaveData::from_toml(&future).is_err());
+    }
+
+    /// Older versions must be migrated to the current version.
+    #[test]
+    fn test_migrate_v1() {
+        let v1 = r#"
+            version = 1
+            money = 3
+            max_distance = 100.0
+            extra_initial_speed = 100.0
+            extra_gravity = -10.0
+            attraction = 0.0
+
+            [selected_cards]
+            cannon = 1
+            wings = 1
+        "#;
+
+        let data = SaveData::from_toml(v1).unwrap();
+        assert_eq!(data.version, super::VERSION);
+        assert_eq!(data.money, 3);
+        assert_eq!(data.selected_cards.get("wings"), Some(&1));
+    }
+}
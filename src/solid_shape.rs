@@ -1,9 +1,10 @@
+use std::cell::OnceCell;
+
 use vek::{Extent2, Rect, Vec2};
 
 use crate::{
     gen::{bitmap::Bitmap, isoline::Isoline},
     graphics::Color,
-    physics::collision::shape::Shape,
     sprite::{Sprite, SpriteOffset},
 };
 
@@ -12,7 +13,8 @@ const OUTLINE_SIZE: usize = 2;
 
 /// Procedurally generatable shape with a solid color and an outline.
 ///
-/// Will automatically recreate the sprite when the shape gets changed.
+/// The sprite and the collider are only generated when they are first used,
+/// after that they are automatically updated when the shape gets changed.
 /// Splitting of the sprite can also be detected.
 pub struct SolidShape {
     /// Shape that generates the sprite and the collider.
@@ -23,14 +25,16 @@ pub struct SolidShape {
     ///
     /// Outline is assumed to be 2 pixels big.
     outline_color: Color,
+    /// Where the sprite is drawn relative to the position.
+    sprite_offset: SpriteOffset,
     /// Generated sprite from the shape with an outline.
     ///
     /// Must be updated whenever the shape is updated.
-    sprite: Sprite,
+    sprite: OnceCell<Sprite>,
     /// Generated collider from the shape.
     ///
     /// Must be updated whenever the shape is updated.
-    collider: Isoline,
+    collider: OnceCell<Isoline>,
 }
 
 impl SolidShape {
@@ -54,22 +58,14 @@ impl SolidShape {
             shape.set_at_index_range(index_start..index_end, true);
         }
 
-        // Use an empty sprite, will be generated later
-        let sprite = Sprite::from_buffer(&vec![0; size.w * size.h], size, SpriteOffset::LeftTop);
-
-        let collider = Isoline::from_bitmap(&shape);
-
-        let mut this = Self {
+        Self {
             shape,
             fill_color,
             outline_color,
-            sprite,
-            collider,
-        };
-
-        this.generate_sprite();
-
-        this
+            sprite_offset: SpriteOffset::LeftTop,
+            sprite: OnceCell::new(),
+            collider: OnceCell::new(),
+        }
     }
 
     /// Create the shape as a heightmap where only the top edge has multiple subdivisions.
@@ -88,9 +84,7 @@ impl SolidShape {
         debug_assert!(!heights.is_empty());
 
         // Find the highest point so we know the max height
-        let highest = heights
-            .iter()
-            .fold(std::f64::NEG_INFINITY, |a, &b| a.max(b));
+        let highest = heights.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
         let total_width = heights.len() + OUTLINE_SIZE * 2;
         let total_height = (highest + height) as usize + OUTLINE_SIZE * 2;
         let size = Extent2::new(total_width, total_height);
@@ -109,23 +103,14 @@ impl SolidShape {
             }
         }
 
-        // Use an empty sprite, will be generated later
-        let sprite = Sprite::from_buffer(&vec![0; size.w * size.h], size, SpriteOffset::LeftTop);
-
-        // Generate the first collider
-        let collider = Isoline::from_bitmap(&shape);
-
-        let mut this = Self {
+        Self {
             shape,
             fill_color,
             outline_color,
-            sprite,
-            collider,
-        };
-
-        this.generate_sprite();
-
-        this
+            sprite_offset: SpriteOffset::LeftTop,
+            sprite: OnceCell::new(),
+            collider: OnceCell::new(),
+        }
     }
 
     /// Create from an existing bitmap.
@@ -137,45 +122,25 @@ impl SolidShape {
         // Make the memory layout efficient
         let offset = shape.shrink_with_padding(OUTLINE_SIZE);
 
-        // Use an empty sprite, will be generated later
-        let sprite = Sprite::from_buffer(
-            &vec![0; shape.size().product()],
-            shape.size(),
-            SpriteOffset::Custom(offset.as_()),
-        );
-
-        // Generate the first collider
-        let collider = Isoline::from_bitmap(&shape);
-
-        let mut this = Self {
+        Self {
             shape,
             fill_color,
             outline_color,
-            sprite,
-            collider,
-        };
-
-        this.generate_sprite();
-
-        this
-    }
-
-    /// Generate the sprite from the shape.
-    pub fn generate_sprite(&mut self) {
-        puffin::profile_scope!("Generate sprite");
-
-        // Redraw the full rectangle
-        self.redraw_sprite_rectangle(self.rect());
+            sprite_offset: SpriteOffset::Custom(offset.as_()),
+            sprite: OnceCell::new(),
+            collider: OnceCell::new(),
+        }
     }
 
-    /// Get the sprite.
+    /// Get the sprite, generating it the first time.
     pub fn sprite(&self) -> &Sprite {
-        &self.sprite
+        self.sprite.get_or_init(|| self.generate_sprite())
     }
 
-    /// Get the collider shape.
-    pub fn to_collider(&self) -> Shape {
-        self.collider.to_collider()
+    /// Get the outline used for collisions, generating it the first time.
+    pub fn collider(&self) -> &Isoline {
+        self.collider
+            .get_or_init(|| Isoline::from_bitmap(&self.shape))
     }
 
     /// Whether a point collides.
@@ -259,7 +224,7 @@ impl SolidShape {
             puffin::profile_scope!("Converting islands to new shapes");
 
             // Find all islands in the subsection
-            let mut biggest_area = f64::MAX;
+            let mut biggest_area = 0.0;
             let subsection_islands = shape_subsection
                 .islands()
                 .into_iter()
@@ -271,7 +236,7 @@ impl SolidShape {
                     let area = self.shape.area_from_shape_at_position(coord);
 
                     // Update biggest area
-                    if biggest_area < area {
+                    if area > biggest_area {
                         biggest_area = area;
                     }
 
@@ -279,8 +244,6 @@ impl SolidShape {
                 })
                 .collect::<Vec<_>>();
 
-            dbg!(biggest_area);
-
             // TODO: remove connected items
             // Convert all remaing islands to new shapes
             subsection_islands
@@ -316,25 +279,43 @@ impl SolidShape {
         // Do a partial update on the main shape
         puffin::profile_scope!("Partial shape update");
 
-        // Redraw the sprite
-        self.redraw_sprite_rectangle(redraw_rect);
+        // Redraw the sprite when it's already generated
+        if let Some(mut sprite) = self.sprite.take() {
+            self.redraw_sprite_rectangle(&mut sprite, redraw_rect);
+            self.sprite = OnceCell::from(sprite);
+        }
 
-        // Remove the part of the collider
-        if !delta_mask.is_empty() {
-            self.collider.update(&self.shape, &delta_mask, offset);
+        // Remove the part of the collider when it's already generated
+        if let Some(collider) = self.collider.get_mut() {
+            if !delta_mask.is_empty() {
+                collider.update(&self.shape, &delta_mask, offset);
+            }
         }
 
         new_shapes
     }
 
+    /// Generate the sprite from the shape.
+    fn generate_sprite(&self) -> Sprite {
+        puffin::profile_scope!("Generate sprite");
+
+        let size = self.shape.size();
+        let mut sprite = Sprite::from_buffer(&vec![0; size.product()], size, self.sprite_offset);
+
+        // Draw the full rectangle
+        self.redraw_sprite_rectangle(&mut sprite, self.rect());
+
+        sprite
+    }
+
     /// Redraw the sprite pixels of a rectangle, which will be clamped if outside of range.
-    fn redraw_sprite_rectangle(&mut self, mut rect: Rect<usize, usize>) {
+    fn redraw_sprite_rectangle(&self, sprite: &mut Sprite, mut rect: Rect<usize, usize>) {
         puffin::profile_scope!("Redraw sprite rectangle");
 
-        debug_assert_eq!(self.shape.size(), self.sprite.size().as_());
+        debug_assert_eq!(self.shape.size(), sprite.size().as_());
 
         // Nothing to draw
-        if rect.x >= self.shape.width() && rect.y >= self.shape.height() {
+        if rect.x >= self.shape.width() || rect.y >= self.shape.height() {
             return;
         }
 
@@ -347,7 +328,7 @@ impl SolidShape {
             let index_start = (y + rect.y) * self.shape.width();
             for x in 0..rect.w {
                 let index = index_start + x + rect.x;
-                self.set_sprite_pixel_unchecked(index, Vec2::new(x, y) + rect.position());
+                self.set_sprite_pixel_unchecked(sprite, index, Vec2::new(x, y) + rect.position());
             }
         }
     }
@@ -372,8 +353,8 @@ impl SolidShape {
 
     /// Set a sprite pixel without checking the bounds.
     #[inline(always)]
-    fn set_sprite_pixel_unchecked(&mut self, index: usize, pixel: Vec2<usize>) {
-        self.sprite.pixels_mut()[index] = if self.shape[index] {
+    fn set_sprite_pixel_unchecked(&self, sprite: &mut Sprite, index: usize, pixel: Vec2<usize>) {
+        sprite.pixels_mut()[index] = if self.shape[index] {
             // Solid fill
             self.fill_color.as_u32()
         } else if self.is_outline(pixel) {
@@ -424,3 +405,34 @@ mod outline_offsets {
                   (-1,  2), ( 0,  2), ( 1,  2),
     ];
 }
+
+#[cfg(test)]
+mod tests {
+    use vek::Vec2;
+
+    use super::SolidShape;
+    use crate::graphics::Color;
+
+    /// The sprite must only be generated when it's used, and look the same as one updated by removals.
+    #[test]
+    fn test_lazy_sprite() {
+        let heights = (0..32).map(|x| 10.0 + x as f64 / 4.0).collect::<Vec<_>>();
+        let mut lazy = SolidShape::from_heights(&heights, 0.0, Color::Green, Color::DarkGreen);
+        let mut updated = SolidShape::from_heights(&heights, 0.0, Color::Green, Color::DarkGreen);
+        updated.sprite();
+
+        lazy.remove_circle(Vec2::new(16.0, 8.0), 5.0);
+        updated.remove_circle(Vec2::new(16.0, 8.0), 5.0);
+        assert!(lazy.sprite.get().is_none());
+        assert!(lazy.collider.get().is_none());
+
+        let (lazy, updated) = (lazy.sprite(), updated.sprite());
+        assert_eq!(lazy.size(), updated.size());
+        for y in 0..lazy.height() {
+            for x in 0..lazy.width() {
+                let pixel = Vec2::new(x, y);
+                assert_eq!(lazy.pixel(pixel), updated.pixel(pixel));
+            }
+        }
+    }
+}
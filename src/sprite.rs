@@ -1,15 +1,20 @@
-use std::{borrow::Cow, f64::consts::TAU, num::NonZeroU16};
+use std::{borrow::Cow, collections::BTreeMap, f64::consts::TAU, num::NonZeroU16};
 
 use assets_manager::{
     loader::{Loader, TomlLoader},
     AnyCache, Asset, BoxedError, Compound, SharedString,
 };
-use blit::{Blit, BlitBuffer, BlitOptions, ToBlitBuffer};
+use blit::{BlitBuffer, BlitOptions, ToBlitBuffer};
 use image::ImageFormat;
 use serde::Deserialize;
 use vek::{Extent2, Vec2};
 
-use crate::{math::Iso, SIZE};
+use crate::{
+    aseprite::{Aseprite, Direction, Tag},
+    camera::Camera,
+    graphics::{blend::Blend, canvas::Canvas},
+    math::Iso,
+};
 
 /// Sprite that can be drawn on the  canvas.
 #[derive(Debug)]
@@ -29,18 +34,74 @@ impl Sprite {
         Self { sprite, offset }
     }
 
-    /// Draw the sprite.
-    pub fn render(&self, canvas: &mut [u32], offset: Vec2<f64>) {
+    /// Draw the sprite at a world position.
+    pub fn render(&self, canvas: &mut Canvas, camera: &Camera, pos: Vec2<f64>) {
+        self.render_blended(canvas, camera, pos, Blend::default());
+    }
+
+    /// Draw the sprite at a world position combined with the canvas.
+    ///
+    /// When the camera is zoomed out only every nth pixel is drawn.
+    pub fn render_blended(
+        &self,
+        canvas: &mut Canvas,
+        camera: &Camera,
+        pos: Vec2<f64>,
+        blend: Blend,
+    ) {
+        puffin::profile_function!();
+
+        let zoom = camera.zoom();
+        if zoom == 1 {
+            self.render_screen_blended(canvas, camera.world_to_screen(pos), blend);
+
+            return;
+        }
+
+        let screen_pos = camera.world_to_screen(pos + self.offset.as_());
+        self.blend_pixels(canvas, screen_pos.as_(), zoom, blend);
+    }
+
+    /// Draw the sprite at a screen position, ignoring the camera.
+    pub fn render_screen(&self, canvas: &mut Canvas, offset: Vec2<f64>) {
+        self.render_screen_blended(canvas, offset, Blend::default());
+    }
+
+    /// Draw the sprite at a screen position combined with the canvas, ignoring the camera.
+    fn render_screen_blended(&self, canvas: &mut Canvas, offset: Vec2<f64>, blend: Blend) {
         puffin::profile_function!();
 
-        self.sprite.blit(
-            canvas,
-            SIZE.into_tuple().into(),
-            &BlitOptions::new_position(
-                offset.x as i32 + self.offset.x,
-                offset.y as i32 + self.offset.y,
-            ),
+        let pos = Vec2::new(
+            offset.x as i32 + self.offset.x,
+            offset.y as i32 + self.offset.y,
         );
+
+        // Blitting is a lot faster but can only overwrite pixels
+        if blend.is_replace() {
+            canvas.blit(&self.sprite, &BlitOptions::new_position(pos.x, pos.y));
+        } else {
+            self.blend_pixels(canvas, pos, 1, blend);
+        }
+    }
+
+    /// Combine every nth pixel of the image with the canvas.
+    fn blend_pixels(&self, canvas: &mut Canvas, pos: Vec2<i32>, zoom: u32, blend: Blend) {
+        // All visible pixels are opaque so they can be copied directly
+        let replace = blend.is_replace();
+
+        let size = self.size() / zoom;
+        for y in 0..size.h {
+            for x in 0..size.w {
+                if let Some(pixel) = self.pixel(Vec2::new(x, y) * zoom) {
+                    let pos = pos + Vec2::new(x, y).as_();
+                    if replace {
+                        canvas.set(pos, pixel);
+                    } else {
+                        canvas.blend(pos, pixel, blend);
+                    }
+                }
+            }
+        }
     }
 
     /// Whether a pixel on the image is transparent.
@@ -53,6 +114,17 @@ impl Sprite {
         pixel == 0
     }
 
+    /// Color of a pixel on the image, nothing when it's transparent.
+    pub fn pixel(&self, pixel: Vec2<u32>) -> Option<u32> {
+        let index = pixel.x + pixel.y * self.sprite.width();
+
+        self.sprite
+            .pixels()
+            .get(index as usize)
+            .copied()
+            .filter(|pixel| *pixel != 0)
+    }
+
     /// Width of the image.
     pub fn width(&self) -> u32 {
         self.sprite.width()
@@ -166,17 +238,19 @@ impl RotatableSprite {
     }
 
     /// Draw the nearest sprite based on the rotation with a camera offset.
-    pub fn render(&self, iso: Iso, canvas: &mut [u32]) {
-        let rotation = iso.rot.to_radians();
+    pub fn render(&self, iso: Iso, canvas: &mut Canvas, camera: &Camera) {
+        self.sprite(iso.rot.to_radians())
+            .render(canvas, camera, iso.pos);
+    }
 
+    /// Sprite pre-rendered with the nearest rotation in radians.
+    pub fn sprite(&self, rotation: f64) -> &Sprite {
         // Calculate rotation based on nearest point
         let index = (rotation / TAU * self.0.len() as f64)
             .round()
             .rem_euclid(self.0.len() as f64) as usize;
 
-        let sprite = &self.0[index];
-
-        sprite.render(canvas, iso.pos);
+        &self.0[index]
     }
 }
 
@@ -192,6 +266,295 @@ impl Compound for RotatableSprite {
     }
 }
 
+/// Sprite with frames shown after each other, loaded from an Aseprite file.
+///
+/// Every tag is a clip that can be played, every frame is pre-rendered with rotations.
+#[derive(Debug)]
+pub struct AnimatedSprite {
+    /// All frames in order.
+    frames: Vec<AnimationFrame>,
+    /// Clips by tag name.
+    clips: Vec<Clip>,
+    /// Clip with all frames, played when a clip doesn't exist.
+    all: Clip,
+}
+
+impl AnimatedSprite {
+    /// Create from the flattened frames of an Aseprite file.
+    pub fn from_aseprite(aseprite: &Aseprite, metadata: &AnimatedSpriteMetadata) -> Self {
+        let rotatable = RotatableSpriteMetadata {
+            rotations: metadata.rotations,
+            offset: metadata.offset,
+        };
+        let frames = aseprite
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(index, frame)| {
+                let sprite = Sprite::from_buffer(&frame.pixels, aseprite.size, metadata.offset);
+                let frame_metadata = metadata.frames.iter().find(|frame| frame.index == index);
+
+                AnimationFrame {
+                    sprite: RotatableSprite::with_fill_circle(sprite, rotatable, 0.0),
+                    duration: frame.duration,
+                    pivot: frame_metadata.map(|frame| frame.pivot).unwrap_or_default(),
+                    event: frame_metadata.and_then(|frame| frame.event.clone()),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let clips = aseprite
+            .tags
+            .iter()
+            .map(|tag| Clip::from_tag(tag, metadata.clips.get(&tag.name), frames.len()))
+            .collect();
+        let all = Clip {
+            name: String::new(),
+            frames: (0..frames.len()).collect(),
+            mode: PlayMode::Loop,
+            next: None,
+        };
+
+        Self { frames, clips, all }
+    }
+
+    /// Index of the frame shown after playing a clip for a time.
+    ///
+    /// All frames are looped when there's no clip with the name.
+    pub fn frame_index(&self, clip: &str, time: f64) -> usize {
+        let clip = self.clip(clip);
+
+        let total = self.duration(clip);
+        let mut time = match clip.mode {
+            PlayMode::Once => time,
+            PlayMode::Loop | PlayMode::PingPong if total > 0.0 => time.rem_euclid(total),
+            PlayMode::Loop | PlayMode::PingPong => 0.0,
+        };
+
+        // One-shot clips stay on their last frame when finished
+        clip.frames
+            .iter()
+            .copied()
+            .find(|index| {
+                time -= self.frames[*index].duration;
+
+                time < 0.0
+            })
+            .unwrap_or(clip.frames[clip.frames.len() - 1])
+    }
+
+    /// Frame shown after playing a clip for a time, without rotation.
+    pub fn frame(&self, clip: &str, time: f64) -> &Sprite {
+        self.frames[self.frame_index(clip, time)].sprite.sprite(0.0)
+    }
+
+    /// Whether a clip is played once instead of looping.
+    pub fn is_one_shot(&self, clip: &str) -> bool {
+        self.clip(clip).mode == PlayMode::Once
+    }
+
+    /// Whether a one-shot clip played for a time is finished, looping clips never finish.
+    pub fn is_finished(&self, clip: &str, time: f64) -> bool {
+        let clip = self.clip(clip);
+
+        clip.mode == PlayMode::Once && time >= self.duration(clip)
+    }
+
+    /// Clip to play after a one-shot clip is finished.
+    pub fn next_clip(&self, clip: &str) -> Option<&str> {
+        self.clip(clip).next.as_deref()
+    }
+
+    /// Event triggered when a frame is shown.
+    pub fn event(&self, index: usize) -> Option<&str> {
+        self.frames
+            .get(index)
+            .and_then(|frame| frame.event.as_deref())
+    }
+
+    /// Draw the nearest rotation of the frame shown after playing a clip for a time.
+    pub fn render(&self, iso: Iso, canvas: &mut Canvas, camera: &Camera, clip: &str, time: f64) {
+        let frame = &self.frames[self.frame_index(clip, time)];
+
+        // The pivot of the frame is drawn at the position
+        let pos = iso.translate(-frame.pivot);
+        frame.sprite.render(Iso { pos, ..iso }, canvas, camera);
+    }
+
+    /// Clip with a name, all frames when it doesn't exist.
+    fn clip(&self, name: &str) -> &Clip {
+        self.clips
+            .iter()
+            .find(|clip| clip.name == name)
+            .unwrap_or(&self.all)
+    }
+
+    /// Seconds it takes to play all frames of a clip once.
+    fn duration(&self, clip: &Clip) -> f64 {
+        clip.frames
+            .iter()
+            .map(|index| self.frames[*index].duration)
+            .sum()
+    }
+}
+
+impl Compound for AnimatedSprite {
+    fn load(cache: AnyCache, id: &SharedString) -> Result<Self, BoxedError> {
+        let aseprite = cache.load::<Aseprite>(id)?.read();
+
+        // The metadata is optional
+        let metadata = cache
+            .load::<AnimatedSpriteMetadata>(id)
+            .map(|metadata| metadata.read().clone())
+            .unwrap_or_default();
+
+        Ok(Self::from_aseprite(&aseprite, &metadata))
+    }
+}
+
+/// Single image of an animation.
+#[derive(Debug)]
+struct AnimationFrame {
+    /// Image with all rotations.
+    sprite: RotatableSprite,
+    /// Seconds it's shown.
+    duration: f64,
+    /// Pixels from the center that are drawn at the position, rotated along.
+    pivot: Vec2<f64>,
+    /// Triggered when the frame is shown.
+    event: Option<String>,
+}
+
+/// Frames of a tag and how they are played.
+#[derive(Debug)]
+struct Clip {
+    /// Name of the tag.
+    name: String,
+    /// Indices of the frames in the order they are played.
+    frames: Vec<usize>,
+    /// How the frames are played.
+    mode: PlayMode,
+    /// Clip to play after a one-shot clip is finished.
+    next: Option<String>,
+}
+
+impl Clip {
+    /// Create from a tag, the mode is derived from the tag when not set.
+    fn from_tag(tag: &Tag, metadata: Option<&ClipMetadata>, frame_count: usize) -> Self {
+        let last = tag.to.min(frame_count - 1);
+        let first = tag.from.min(last);
+        let mut frames = (first..=last).collect::<Vec<_>>();
+        if matches!(
+            tag.direction,
+            Direction::Reverse | Direction::PingPongReverse
+        ) {
+            frames.reverse();
+        }
+
+        let mode = metadata
+            .and_then(|metadata| metadata.mode)
+            .unwrap_or(match tag.direction {
+                Direction::PingPong | Direction::PingPongReverse => PlayMode::PingPong,
+                Direction::Forward | Direction::Reverse if tag.repeat == 1 => PlayMode::Once,
+                Direction::Forward | Direction::Reverse => PlayMode::Loop,
+            });
+
+        // Don't show the outer frames twice when bouncing back
+        if mode == PlayMode::PingPong && frames.len() > 2 {
+            let back = frames[1..frames.len() - 1]
+                .iter()
+                .rev()
+                .copied()
+                .collect::<Vec<_>>();
+            frames.extend(back);
+        }
+
+        Self {
+            name: tag.name.clone(),
+            frames,
+            mode,
+            next: metadata.and_then(|metadata| metadata.next.clone()),
+        }
+    }
+}
+
+/// How the frames of a clip are played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayMode {
+    /// Start again after the last frame.
+    Loop,
+    /// Stay on the last frame.
+    Once,
+    /// Play the frames back again after the last frame, and start again after the first.
+    PingPong,
+}
+
+/// Animated sprite metadata to load.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnimatedSpriteMetadata {
+    /// How many rotations of every frame are pre-rendered.
+    #[serde(default = "AnimatedSpriteMetadata::default_rotations")]
+    rotations: NonZeroU16,
+    /// Center of where sprite will be rendered.
+    #[serde(default)]
+    offset: SpriteOffset,
+    /// How clips are played, by tag name.
+    #[serde(default)]
+    clips: BTreeMap<String, ClipMetadata>,
+    /// Pivots and events of frames.
+    #[serde(default)]
+    frames: Vec<FrameMetadata>,
+}
+
+impl AnimatedSpriteMetadata {
+    /// Only render the sprite without rotations.
+    fn default_rotations() -> NonZeroU16 {
+        NonZeroU16::MIN
+    }
+}
+
+impl Default for AnimatedSpriteMetadata {
+    fn default() -> Self {
+        Self {
+            rotations: Self::default_rotations(),
+            offset: SpriteOffset::default(),
+            clips: BTreeMap::new(),
+            frames: Vec::new(),
+        }
+    }
+}
+
+impl Asset for AnimatedSpriteMetadata {
+    const EXTENSION: &'static str = "toml";
+
+    type Loader = TomlLoader;
+}
+
+/// How a clip is played.
+#[derive(Debug, Clone, Deserialize)]
+struct ClipMetadata {
+    /// Overrides the mode derived from the tag.
+    #[serde(default)]
+    mode: Option<PlayMode>,
+    /// Clip to play after a one-shot clip is finished.
+    #[serde(default)]
+    next: Option<String>,
+}
+
+/// Extra information of a single frame.
+#[derive(Debug, Clone, Deserialize)]
+struct FrameMetadata {
+    /// Index of the frame in the Aseprite file.
+    index: usize,
+    /// Pixels from the center that are drawn at the position.
+    #[serde(default)]
+    pivot: Vec2<f64>,
+    /// Triggered when the frame is shown.
+    #[serde(default)]
+    event: Option<String>,
+}
+
 /// Center of the sprite.
 #[derive(Debug, Clone, Copy, Deserialize, PartialEq, Default)]
 #[serde(rename_all = "snake_case")]
@@ -241,3 +604,49 @@ impl Asset for RotatableSpriteMetadata {
 
     type Loader = TomlLoader;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{AnimatedSprite, AnimatedSpriteMetadata, PlayMode};
+    use crate::aseprite::Aseprite;
+
+    /// Clips must be played according to their mode.
+    #[test]
+    fn test_animation_frames() {
+        let aseprite = Aseprite::from_bytes(include_bytes!("../assets/dino.aseprite")).unwrap();
+        let metadata = toml::from_str::<AnimatedSpriteMetadata>(
+            r#"
+            [clips.fly]
+            mode = "ping_pong"
+            [clips.land]
+            mode = "once"
+            next = "fly"
+            "#,
+        )
+        .unwrap();
+        let sprite = AnimatedSprite::from_aseprite(&aseprite, &metadata);
+        let duration = |index: usize| aseprite.frames[index].duration;
+
+        // Looping starts at the beginning again
+        let run = aseprite.tag("run").unwrap();
+        let total = (run.from..=run.to).map(duration).sum::<f64>();
+        assert_eq!(sprite.frame_index("run", 0.0), run.from);
+        assert_eq!(
+            sprite.frame_index("run", duration(run.from) + 0.01),
+            run.from + 1
+        );
+        assert_eq!(sprite.frame_index("run", total + 0.01), run.from);
+        assert!(!sprite.is_finished("run", total * 10.0));
+
+        // One-shot clips hold the last frame
+        let land = aseprite.tag("land").unwrap();
+        assert!(sprite.is_one_shot("land"));
+        assert!(sprite.is_finished("land", 10.0));
+        assert_eq!(sprite.frame_index("land", 10.0), land.to);
+        assert_eq!(sprite.next_clip("land"), Some("fly"));
+
+        // Unknown clips loop everything
+        assert_eq!(sprite.frame_index("unknown", 0.0), 0);
+        assert_eq!(sprite.clip("fly").mode, PlayMode::PingPong);
+    }
+}
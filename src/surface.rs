@@ -0,0 +1,48 @@
+use assets_manager::{loader::TomlLoader, Asset};
+use serde::Deserialize;
+
+use crate::{graphics::Color, random::RandomRangeF64};
+
+/// Type of ground, changes how the player bounces.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Surface {
+    /// Fraction of the velocity into the ground kept when bouncing, above `1.0` gains speed.
+    pub restitution: f64,
+    /// Fraction of the velocity along the ground lost when bouncing.
+    pub friction: f64,
+    /// Color of the particles when bouncing.
+    pub particle_color: Color,
+    /// Color of the ground below the strip.
+    pub color: Color,
+    /// Sprite repeated along the top of the ground.
+    pub strip: String,
+    /// How likely the surface is picked for the next segment.
+    pub weight: f64,
+    /// Distance from the launch before the surface can appear.
+    #[serde(default)]
+    pub min_distance: f64,
+    /// Length of a single segment.
+    pub length: RandomRangeF64,
+    /// Name of the surface asset.
+    #[serde(skip)]
+    pub id: String,
+}
+
+impl Surface {
+    /// Set the name from the asset path.
+    pub fn with_id(mut self, asset_path: &str) -> Self {
+        self.id = asset_path
+            .rsplit('.')
+            .next()
+            .unwrap_or(asset_path)
+            .to_string();
+
+        self
+    }
+}
+
+impl Asset for Surface {
+    const EXTENSION: &'static str = "toml";
+
+    type Loader = TomlLoader;
+}
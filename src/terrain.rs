@@ -1,108 +1,465 @@
-use serde::Deserialize;
-use vek::Vec2;
+use std::collections::VecDeque;
+
+use fastrand::Rng;
+use serde::{Deserialize, Serialize};
+use vek::{Rect, Vec2};
 
 use crate::{
+    assets::Assets,
     camera::Camera,
-    graphics::Color,
-    physics::{
-        rigidbody::{RigidBodyBuilder, RigidBodyHandle},
-        Physics,
-    },
+    graphics::{canvas::Canvas, Color},
+    physics::collision::shape::Shape,
+    random::weighted_index,
     solid_shape::SolidShape,
-    sprite::SpriteOffset,
+    surface::Surface,
     SIZE,
 };
 
-/// Level asset path.
-pub const ASSET_PATH: &str = "level.grass-1";
+/// Amount of pixel columns in a single destructible chunk.
+const CHUNK_WIDTH: usize = 256;
+/// Empty pixels around the shape of a chunk, reserved for its outline.
+const CHUNK_OUTLINE: f64 = 2.0;
 
-/// Destructible procedurally generated terrain buffer.
+/// Procedurally generated ground, streamed endlessly along the flight path.
+///
+/// Positive heights are below the launch height.
 pub struct Terrain {
-    /// Y offset of the sprite.
-    pub y: f64,
-    /// Physics object reference.
-    pub rigidbody: RigidBodyHandle,
-    /// Array of the top collision point heights of the terrain for ecah pixel.
-    top_heights: Vec<f64>,
-    /// Solid shape and sprite.
-    shape: SolidShape,
+    /// X coordinate of the first column in `top_heights`.
+    start: i64,
+    /// Height of the ground for every pixel column.
+    top_heights: VecDeque<f64>,
+    /// Direction the heights are currently going in.
+    dir: f64,
+    /// Types of ground that can be placed.
+    surfaces: Vec<Surface>,
+    /// Start position and surface index of every segment of ground.
+    segments: VecDeque<(i64, usize)>,
+    /// X coordinate where the last segment ends.
+    segments_end: i64,
+    /// Destructible shape of the ground, split in chunks.
+    chunks: VecDeque<Chunk>,
+    /// X coordinate where the last chunk ends.
+    chunks_end: i64,
+    /// Random number generator only used for the terrain, so it's not influenced by anything else.
+    rng: Rng,
+    /// Whether the heights changed since the collider was last taken.
+    changed: bool,
 }
 
 impl Terrain {
-    /// Load a terrain from image bytes.
-    pub fn new(physics: &mut Physics) -> Self {
-        let settings = &crate::settings().terrain;
-
-        // Generate random heights
-        let mut last = 0.0;
-        let mut dir = 0.0;
-        let top_heights = (0..crate::settings().terrain.width)
-            .map(|i| {
-                // Add some noise for every step
-                last += (fastrand::f64() - 0.5) * settings.pixel_random_factor * 2.0 + dir;
-
-                // Once every set steps change the direction
-                if i % settings.direction_pixels == 0 {
-                    dir = (fastrand::f64() - 0.5) * settings.direction_random_factor * 2.0;
-                }
+    /// Pixels the surface strip is drawn above the ground height.
+    const STRIP_OFFSET: i64 = 3;
 
-                last
-            })
-            .collect::<Vec<_>>();
-
-        let shape = SolidShape::from_heights(&top_heights, 100.0, Color::LightGreen, Color::Green);
+    /// Generate the start of the terrain.
+    pub fn new(rng: &mut Rng, settings: &Settings, surfaces: Vec<Surface>) -> Self {
+        // The launch area is flat
+        let top_heights = std::iter::repeat_n(0.0, settings.flat_start as usize).collect();
+        let rng = Rng::with_seed(rng.u64(..));
 
-        let y = SIZE.h as f64 - shape.sprite().height() as f64;
+        // The launch area always has the same surface
+        let start_surface = surfaces
+            .iter()
+            .position(|surface| surface.id == settings.start_surface)
+            .unwrap_or_else(|| {
+                log::error!(
+                    "Start surface '{}' does not exist, using the first surface",
+                    settings.start_surface
+                );
 
-        // Create a heightmap for the terrain
-        let rigidbody = {
-            RigidBodyBuilder::new_static(Vec2::new(0.0, y))
-                .with_collider(shape.to_collider())
-                .spawn(physics)
-        };
+                0
+            });
+        let segments = [(0, start_surface)].into();
+        let segments_end = settings.flat_start as i64;
 
         Self {
-            rigidbody,
-            y,
+            start: 0,
             top_heights,
-            shape,
+            dir: 0.0,
+            surfaces,
+            segments,
+            segments_end,
+            chunks: VecDeque::new(),
+            chunks_end: 0,
+            rng,
+            changed: true,
+        }
+    }
+
+    /// Generate the terrain in front of a position and forget it far behind.
+    pub fn update(&mut self, x: f64, settings: &Settings) {
+        puffin::profile_scope!("Update terrain");
+
+        let end = x as i64 + SIZE.w as i64 * 2;
+        while self.end() < end {
+            let i = self.end();
+
+            // Once every set steps change the direction
+            if i % settings.direction_pixels.max(1) as i64 == 0 {
+                self.dir = (self.rng.f64() - 0.5) * settings.direction_random_factor * 2.0;
+            }
+
+            // Add some noise for every step
+            let last = self.top_heights.back().copied().unwrap_or_default();
+            let mut next =
+                last + (self.rng.f64() - 0.5) * settings.pixel_random_factor * 2.0 + self.dir;
+
+            // Turn around when going too far from the launch height
+            if next.abs() > settings.max_height {
+                next = next.clamp(-settings.max_height, settings.max_height);
+                self.dir = -self.dir;
+            }
+
+            self.top_heights.push_back(next);
+            self.changed = true;
+        }
+
+        // Turn every fully generated part into a destructible shape
+        while self.chunks_end + CHUNK_WIDTH as i64 <= self.end() {
+            let first = (self.chunks_end - self.start).max(0) as usize;
+            let heights = self
+                .top_heights
+                .range(first..(first + CHUNK_WIDTH))
+                .copied()
+                .collect::<Vec<_>>();
+            self.chunks
+                .push_back(Chunk::new(self.chunks_end, &heights, settings.depth));
+            self.chunks_end += CHUNK_WIDTH as i64;
+        }
+
+        while self.segments_end < end {
+            // Pick a random surface that's allowed at this distance
+            let weights = self
+                .surfaces
+                .iter()
+                .map(|surface| {
+                    if surface.min_distance <= self.segments_end as f64 {
+                        surface.weight
+                    } else {
+                        0.0
+                    }
+                })
+                .collect::<Vec<_>>();
+            let index = weighted_index(&weights, &mut self.rng).unwrap_or_default();
+
+            self.segments.push_back((self.segments_end, index));
+            self.segments_end += (self.surfaces[index].length.value(&mut self.rng) as i64).max(1);
+        }
+
+        let start = x as i64 - SIZE.w as i64;
+        while self.start < start && self.top_heights.len() > 1 {
+            self.top_heights.pop_front();
+            self.start += 1;
+            self.changed = true;
+        }
+        while self.segments.len() > 1 && self.segments[1].0 < start {
+            self.segments.pop_front();
+        }
+        while self
+            .chunks
+            .front()
+            .is_some_and(|chunk| chunk.start + (CHUNK_WIDTH as i64) < start)
+        {
+            self.chunks.pop_front();
         }
     }
 
-    /// Draw the terrain based on a camera offset.
-    pub fn render(&self, canvas: &mut [u32], camera: &Camera) {
+    /// Remove a circle of ground, lowering the height of the columns it hits.
+    pub fn carve(&mut self, center: Vec2<f64>, radius: f64) {
+        puffin::profile_scope!("Carve terrain");
+
+        let columns = (center.x - radius).floor() as i64..=(center.x + radius).ceil() as i64;
+        for chunk in self.chunks.iter_mut() {
+            let chunk_columns = chunk.start..(chunk.start + CHUNK_WIDTH as i64);
+            if columns.end() < &chunk_columns.start || columns.start() >= &chunk_columns.end {
+                continue;
+            }
+
+            // Pieces of ground that got disconnected fall away
+            chunk.shape.remove_circle(center - chunk.offset, radius);
+
+            for x in columns.clone().filter(|x| chunk_columns.contains(x)) {
+                let Some(height) = self.top_heights.get_mut((x - self.start) as usize) else {
+                    continue;
+                };
+
+                // Only move the ground down, so untouched columns keep their exact height
+                *height = height.max(chunk.top(x));
+                self.changed = true;
+            }
+        }
+    }
+
+    /// Height of the ground at a position, interpolated between the pixel columns.
+    pub fn height(&self, x: f64) -> f64 {
+        let index = x - self.start as f64;
+        if index <= 0.0 {
+            return self.top_heights.front().copied().unwrap_or_default();
+        }
+
+        let left = index.floor() as usize;
+        match (self.top_heights.get(left), self.top_heights.get(left + 1)) {
+            (Some(left_height), Some(right_height)) => {
+                let frac = index.fract();
+
+                left_height * (1.0 - frac) + right_height * frac
+            }
+            _ => self.top_heights.back().copied().unwrap_or_default(),
+        }
+    }
+
+    /// Direction pointing out of the ground at a position.
+    pub fn normal(&self, x: f64) -> Vec2<f64> {
+        let slope = (self.height(x + 1.0) - self.height(x - 1.0)) / 2.0;
+
+        Vec2::new(slope, -1.0).normalized()
+    }
+
+    /// Heightmap collider of all generated ground, with the position of its first column.
+    pub fn collider(&self) -> (Vec2<f64>, Shape) {
+        let shape = Shape::Heightmap {
+            heights: self.top_heights.iter().copied().collect(),
+            spacing: 1.0,
+        };
+
+        (Vec2::new(self.start as f64, 0.0), shape)
+    }
+
+    /// Heightmap collider like [`Self::collider`], but only when the ground changed since the last call.
+    pub fn changed_collider(&mut self) -> Option<(Vec2<f64>, Shape)> {
+        std::mem::take(&mut self.changed).then(|| self.collider())
+    }
+
+    /// Surface of the ground at a position.
+    pub fn surface(&self, x: f64) -> &Surface {
+        &self.surfaces[self.surface_index(x)]
+    }
+
+    /// Index of the surface of the ground at a position.
+    fn surface_index(&self, x: f64) -> usize {
+        self.segments
+            .iter()
+            .rev()
+            .find(|(start, _)| *start as f64 <= x)
+            .or(self.segments.front())
+            .map(|(_, index)| *index)
+            .unwrap_or_default()
+    }
+
+    /// Draw the terrain, every screen column samples a world column.
+    pub fn render(&self, canvas: &mut Canvas, camera: &Camera, assets: &Assets) {
         puffin::profile_scope!("Render terrain");
 
-        self.shape
-            .sprite()
-            .render(canvas, camera, Vec2::new(0.0, self.y));
+        let zoom = camera.zoom() as f64;
+
+        // Only look up the strip again when a new segment starts
+        let mut current = None;
+        for x in 0..canvas.width() {
+            let world_x = camera.screen_to_world(Vec2::new(x as f64, 0.0)).x;
+            let index = self.surface_index(world_x);
+            let surface = &self.surfaces[index];
+            let strip = match &current {
+                Some((current_index, strip)) if *current_index == index => strip,
+                _ => &current.insert((index, assets.sprite(&surface.strip))).1,
+            };
+
+            // The strip starts slightly above the ground so grass can stick out
+            let top = camera
+                .world_to_screen(Vec2::new(
+                    world_x,
+                    self.height(world_x) - Self::STRIP_OFFSET as f64,
+                ))
+                .y
+                .round() as i64;
+            let strip_x = (world_x.floor() as i64).rem_euclid(strip.width() as i64) as u32;
+            let strip_height = (strip.height() as f64 / zoom).ceil() as i64;
+            for strip_y in 0..strip_height {
+                let pixel_y = (strip_y as f64 * zoom) as u32;
+                if let Some(pixel) = strip.pixel(Vec2::new(strip_x, pixel_y)) {
+                    canvas.set(Vec2::new(x as i64, top + strip_y).as_(), pixel);
+                }
+            }
+
+            let fill_top = (top + strip_height).clamp(0, canvas.height() as i64) as i32;
+            canvas.fill_rect(
+                Rect::new(x as i32, fill_top, 1, canvas.height() as i32 - fill_top),
+                surface.color.as_u32(),
+            );
+        }
+    }
+
+    /// Position one past the last generated column.
+    fn end(&self) -> i64 {
+        self.start + self.top_heights.len() as i64
     }
+}
+
+/// Destructible part of the ground.
+struct Chunk {
+    /// X coordinate of the first column.
+    start: i64,
+    /// World position of the top left pixel of the shape.
+    offset: Vec2<f64>,
+    /// Solid pixels of the ground.
+    shape: SolidShape,
+}
+
+impl Chunk {
+    /// Create the shape of the ground from the height of every column, reaching a depth below the lowest column.
+    fn new(start: i64, heights: &[f64], depth: f64) -> Self {
+        let highest = heights.iter().copied().fold(f64::INFINITY, f64::min);
+        let lowest = heights.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+        // The shape expects heights going up from its bottom
+        let heights = heights
+            .iter()
+            .map(|height| lowest + depth - height)
+            .collect::<Vec<_>>();
+        let shape = SolidShape::from_heights(&heights, 0.0, Color::Green, Color::DarkGreen);
+
+        let offset = Vec2::new(start as f64 - CHUNK_OUTLINE, highest - CHUNK_OUTLINE);
 
-    /// Whether a point collides with the terrain.
-    ///
-    /// This doesn't use the collision shape but the actual pixels of the image.
-    pub fn point_collides(&self, point: Vec2<f64>, _physics: &Physics) -> bool {
-        // Convert the position to a coordinate that can be used as an index
-        let offset = point - (0.0, self.y);
-        self.shape.collides(offset)
+        Self {
+            start,
+            offset,
+            shape,
+        }
     }
 
-    /// Remove a circle of pixels from the terrain.
-    pub fn remove_circle(&mut self, center: Vec2<f64>, radius: f64, physics: &mut Physics) {
-        self.shape.remove_circle(center - (0.0, self.y), radius);
-        self.rigidbody.set_shape(self.shape.to_collider(), physics);
+    /// Height of the first solid pixel in a column, the bottom of the chunk when the column is empty.
+    fn top(&self, x: i64) -> f64 {
+        let local_x = (x as f64 - self.offset.x) + 0.5;
+        let bottom = self.shape.rect().h;
+
+        (0..bottom)
+            .find(|y| self.shape.collides(Vec2::new(local_x, *y as f64 + 0.5)))
+            .unwrap_or(bottom) as f64
+            + self.offset.y
     }
 }
 
-/// Level settings loaded from a file so it's easier to change them with hot-reloading.
-#[derive(Deserialize)]
+/// Terrain settings loaded from a file so it's easier to change them with hot-reloading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
-    /// Total length of the terrain.
-    pub width: u32,
+    /// Length of the flat area at the start.
+    pub flat_start: u32,
     /// Random scaling added to each pixel.
     pub pixel_random_factor: f64,
     /// Random scaling added to each direction step of the amount of pixels.
     pub direction_random_factor: f64,
     /// How many pixels before the direction changes.
     pub direction_pixels: u32,
+    /// Maximum distance of the ground from the launch height.
+    pub max_height: f64,
+    /// How far the destructible ground reaches below the lowest point of every chunk.
+    pub depth: f64,
+    /// Name of the surface of the launch area.
+    pub start_surface: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use fastrand::Rng;
+    use vek::Vec2;
+
+    use super::{Settings, Terrain};
+    use crate::{
+        graphics::Color, physics::collision::shape::Shape, random::RandomRangeF64, surface::Surface,
+    };
+
+    /// Surface with a fixed segment length.
+    fn surface(id: &str, min_distance: f64) -> Surface {
+        Surface {
+            restitution: 0.5,
+            friction: 0.5,
+            particle_color: Color::Green,
+            color: Color::Green,
+            strip: String::new(),
+            weight: 1.0,
+            min_distance,
+            length: RandomRangeF64::Static(100.0),
+            id: id.to_string(),
+        }
+    }
+
+    /// Settings with a flat start of a surface.
+    fn settings(start_surface: &str) -> Settings {
+        Settings {
+            flat_start: 200,
+            pixel_random_factor: 0.3,
+            direction_random_factor: 0.4,
+            direction_pixels: 40,
+            max_height: 80.0,
+            depth: 100.0,
+            start_surface: start_surface.to_string(),
+        }
+    }
+
+    /// Segments must start at the launch surface and only contain surfaces past their minimum distance.
+    #[test]
+    fn test_segments() {
+        let settings = settings("far");
+        let mut terrain = Terrain::new(
+            &mut Rng::with_seed(0),
+            &settings,
+            vec![surface("near", 0.0), surface("far", 2000.0)],
+        );
+        assert_eq!(terrain.surface(0.0).id, "far");
+        assert_eq!(terrain.surface(150.0).id, "far");
+
+        let mut far_segments = 0;
+        for x in (0..8000).step_by(100) {
+            terrain.update(x as f64, &settings);
+
+            for (start, index) in terrain.segments.iter().skip(1) {
+                let surface = &terrain.surfaces[*index];
+                assert!(surface.min_distance <= *start as f64);
+                assert_eq!(terrain.surface(*start as f64 + 50.0).id, surface.id);
+
+                if surface.id == "far" {
+                    far_segments += 1;
+                }
+            }
+        }
+        assert!(far_segments > 0);
+    }
+
+    /// A missing launch surface must fall back to the first surface.
+    #[test]
+    fn test_missing_start_surface() {
+        let terrain = Terrain::new(
+            &mut Rng::with_seed(0),
+            &settings("missing"),
+            vec![surface("first", 0.0), surface("second", 0.0)],
+        );
+
+        assert_eq!(terrain.surface(0.0).id, "first");
+    }
+
+    /// The collider must only be rebuilt after streaming or carving changed the heights.
+    #[test]
+    fn test_changed_collider() {
+        let settings = settings("first");
+        let mut terrain = Terrain::new(
+            &mut Rng::with_seed(0),
+            &settings,
+            vec![surface("first", 0.0)],
+        );
+
+        terrain.update(0.0, &settings);
+        assert!(terrain.changed_collider().is_some());
+        assert!(terrain.changed_collider().is_none());
+
+        terrain.update(0.0, &settings);
+        assert!(terrain.changed_collider().is_none());
+
+        terrain.update(10.0, &settings);
+        assert!(terrain.changed_collider().is_some());
+
+        terrain.carve(Vec2::new(100.0, 0.0), 10.0);
+        let (_, shape) = terrain.changed_collider().unwrap();
+        let Shape::Heightmap { heights, .. } = shape else {
+            panic!("Collider must be a heightmap");
+        };
+        assert!(heights[(100 - terrain.start) as usize] > 0.0);
+    }
 }
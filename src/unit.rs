@@ -1,40 +1,28 @@
 use assets_manager::{loader::TomlLoader, Asset, AssetGuard};
+use fastrand::Rng;
 use serde::Deserialize;
-use vek::{Extent2, Vec2};
+use vek::{Extent2, Rect, Vec2};
 
 use crate::{
-    camera::Camera,
-    object::ObjectSettings,
-    physics::{rigidbody::RigidBodyHandle, Physics},
-    projectile::Projectile,
-    random::RandomRangeF64,
-    terrain::Terrain,
-    timer::Timer,
+    assets::Assets, camera::Camera, flight::Body, graphics::canvas::Canvas, physics::Physics,
+    projectile::Projectile, random::RandomRangeF64, terrain::Terrain, timer::Timer,
 };
 
 /// All unit types.
 #[derive(Debug, Clone, Copy)]
 pub enum UnitType {
-    PlayerSpear,
     EnemySpear,
 }
 
 impl UnitType {
-    /// Settings path to load for this type.
-    pub fn settings(&self) -> AssetGuard<Settings> {
-        // Settings asset path
-        let path = match self {
-            Self::PlayerSpear => "unit.spear",
-            Self::EnemySpear => "unit.enemy-spear",
-        };
-
-        crate::asset(path)
+    /// Settings for this type.
+    pub fn settings<'a>(&self, assets: &'a Assets) -> AssetGuard<'a, Settings> {
+        assets.asset(self.asset_path())
     }
 
     /// Asset path based on what type to load.
     pub fn asset_path(&self) -> &'static str {
         match self {
-            Self::PlayerSpear => "unit.spear",
             Self::EnemySpear => "unit.enemy-spear",
         }
     }
@@ -45,7 +33,7 @@ impl UnitType {
 pub struct Unit {
     /// Type of the unit, used to find the settings.
     r#type: UnitType,
-    /// Absolute position.
+    /// Absolute position of the feet.
     pos: Vec2<f64>,
     /// Timer for throwing a spear.
     projectile_timer: Timer,
@@ -53,21 +41,18 @@ pub struct Unit {
     hide_hands_delay: f64,
     /// How much health the unit has currently.
     pub health: f64,
-    /// Collision shape.
-    pub rigidbody: RigidBodyHandle,
+    /// Whether the dino is touching the unit, so a single pass only hits once.
+    touching: bool,
 }
 
 impl Unit {
     /// Create a new unit.
-    pub fn new(pos: Vec2<f64>, r#type: UnitType, physics: &mut Physics) -> Self {
-        let projectile_timer = Timer::new(r#type.settings().projectile_spawn_interval);
+    pub fn new(pos: Vec2<f64>, r#type: UnitType, assets: &Assets) -> Self {
+        let settings = r#type.settings(assets);
+        let projectile_timer = Timer::new(settings.projectile_spawn_interval);
 
         let hide_hands_delay = 0.0;
-        let health = r#type.settings().health;
-
-        // Load the object definition for properties of the object
-        let object = crate::asset::<ObjectSettings>(r#type.asset_path());
-        let rigidbody = object.rigidbody_builder(pos).spawn(physics);
+        let health = settings.health;
 
         Self {
             r#type,
@@ -75,34 +60,37 @@ impl Unit {
             projectile_timer,
             hide_hands_delay,
             health,
-            rigidbody,
+            touching: false,
         }
     }
 
-    /// Move the unit.
+    /// Move the unit and throw projectiles at the target when it's close enough.
     ///
     /// When a projectile is returned one is spawned.
     pub fn update(
         &mut self,
         terrain: &Terrain,
+        target: Body,
         dt: f64,
+        rng: &mut Rng,
         physics: &mut Physics,
+        assets: &Assets,
     ) -> Option<Projectile> {
         puffin::profile_scope!("Unit update");
 
-        // Update rigidbody position
-        self.rigidbody.set_position(self.pos, physics);
+        let settings = self.settings(assets);
 
-        if !terrain.point_collides(self.pos, physics) {
+        let height = terrain.height(self.pos.x);
+        if self.pos.y < height - 1.0 {
             // No collision with the terrain, the unit falls down
             self.pos.y += 1.0;
-        } else if terrain.point_collides(self.pos - (0.0, 1.0), physics) {
+        } else if self.pos.y > height + 1.0 {
             // The unit has sunk into the terrain, move it up
             self.pos.y -= 1.0;
         } else {
-            // Collision with the terrain, the unit walks to the right
-            let walk_speed = self.settings().walk_speed;
-            self.pos.x += walk_speed * dt;
+            // Collision with the terrain, the unit walks
+            self.pos.x += settings.walk_speed * dt;
+            self.pos.y = terrain.height(self.pos.x);
         }
 
         // Update hands delay
@@ -110,45 +98,95 @@ impl Unit {
             self.hide_hands_delay -= dt;
         }
 
+        // Only enemies throw at the dino
+        if !matches!(settings.allegiance, Allegiance::Enemy) {
+            return None;
+        }
+        let projectile_asset_path = settings.projectile_asset_path.as_ref()?;
+
         // Spawn a projectile if timer runs out
-        if self.projectile_timer.update(dt) {
-            let hide_hands_delay = self.settings().hide_hands_delay;
-            self.hide_hands_delay = hide_hands_delay;
+        if !self.projectile_timer.update(dt) || target.pos.distance(self.pos) > settings.throw_range
+        {
+            return None;
+        }
+
+        self.hide_hands_delay = settings.hide_hands_delay;
+
+        let start = self.pos + settings.projectile_spawn_offset;
+        let speed = settings.projectile_velocity.value(rng);
+        let gravity = assets
+            .asset::<crate::projectile::Settings>(projectile_asset_path)
+            .gravity;
+
+        // Aim where the target will be when the projectile arrives, compensating for the drop
+        let flight_time = target.pos.distance(start) / speed;
+        let aim = target.pos + target.vel * flight_time;
+        let vel = (aim - start).try_normalized().unwrap_or(-Vec2::unit_y()) * speed
+            - Vec2::new(0.0, gravity * flight_time / 2.0);
+
+        Some(Projectile::new(
+            start,
+            vel,
+            projectile_asset_path,
+            settings.allegiance,
+            physics,
+            assets,
+        ))
+    }
+
+    /// Handle the dino touching the unit.
+    ///
+    /// Returns the money bonus when the unit got knocked over.
+    pub fn hit_by(&mut self, collider: Rect<f64, f64>, vel: Vec2<f64>, assets: &Assets) -> usize {
+        let was_touching = self.touching;
+        self.touching = self.collider(assets).collides_with_rect(collider);
+        if !self.touching || was_touching {
+            return 0;
+        }
 
-            let velocity = self.settings().projectile_velocity.value();
+        let impact_damage = self.settings(assets).impact_damage;
+        self.damage(vel.magnitude() * impact_damage, assets)
+    }
 
-            Some(Projectile::new(
-                self.pos + self.settings().projectile_spawn_offset,
-                Vec2::new(velocity, -velocity),
-                physics,
-            ))
+    /// Lose health.
+    ///
+    /// Returns the money bonus when the unit got knocked over by it.
+    pub fn damage(&mut self, amount: f64, assets: &Assets) -> usize {
+        if self.is_knocked_over() {
+            return 0;
+        }
+
+        self.health -= amount;
+        if self.is_knocked_over() {
+            self.settings(assets).knock_over_money
         } else {
-            None
+            0
         }
     }
 
+    /// Whether the unit has no health left.
+    pub fn is_knocked_over(&self) -> bool {
+        self.health <= 0.0
+    }
+
     /// Draw the unit.
-    pub fn render(&self, canvas: &mut [u32], camera: &Camera) {
+    pub fn render(&self, canvas: &mut Canvas, camera: &Camera, assets: &Assets) {
         puffin::profile_function!();
 
-        let settings = self.settings();
+        let settings = self.settings(assets);
 
-        crate::sprite(&settings.base_asset_path).render(
+        assets.sprite(&settings.base_asset_path).render(
             canvas,
             camera,
-            (self.pos - self.ground_collision_point())
-                .numcast()
-                .unwrap_or_default(),
+            self.pos - self.ground_collision_point(assets),
         );
 
         if let Some(hands_asset_path) = &settings.hands_asset_path {
             if self.hide_hands_delay <= 0.0 {
-                crate::sprite(hands_asset_path).render(
+                assets.sprite(hands_asset_path).render(
                     canvas,
                     camera,
-                    (self.pos - (1.0, 1.0) - self.ground_collision_point())
-                        .numcast()
-                        .unwrap_or_default(),
+                    self.pos - (1.0, 1.0) - self.ground_collision_point(assets),
                 );
             }
         }
@@ -164,17 +202,34 @@ impl Unit {
         );
     }
 
+    /// Absolute position of the feet.
+    pub fn pos(&self) -> Vec2<f64> {
+        self.pos
+    }
+
+    /// Collision box in the world, standing on the feet.
+    pub fn collider(&self, assets: &Assets) -> Rect<f64, f64> {
+        let collider = self.settings(assets).collider;
+
+        Rect::new(
+            self.pos.x - collider.w / 2.0,
+            self.pos.y - collider.h,
+            collider.w,
+            collider.h,
+        )
+    }
+
     /// Where the unit collides with the ground.
-    fn ground_collision_point(&self) -> Vec2<f64> {
-        let base_asset_path = &self.settings().base_asset_path;
-        let sprite = crate::sprite(base_asset_path);
+    fn ground_collision_point(&self, assets: &Assets) -> Vec2<f64> {
+        let base_asset_path = &self.settings(assets).base_asset_path;
+        let sprite = assets.sprite(base_asset_path);
 
         (sprite.width() as f64 / 2.0, sprite.height() as f64 - 2.0).into()
     }
 
     /// The settings for this unit.
-    fn settings(&self) -> AssetGuard<Settings> {
-        self.r#type.settings()
+    fn settings<'a>(&self, assets: &'a Assets) -> AssetGuard<'a, Settings> {
+        self.r#type.settings(assets)
     }
 }
 
@@ -201,12 +256,24 @@ pub struct Settings {
     pub projectile_spawn_offset: Vec2<f64>,
     /// How fast a projectile is thrown.
     pub projectile_velocity: RandomRangeF64,
+    /// How close the dino must be before projectiles are thrown at it.
+    pub throw_range: f64,
     /// How long the hands are hidden after launching a projectile.
     pub hide_hands_delay: f64,
     /// Size of the healthbar.
     pub healthbar_size: Extent2<f32>,
     /// Position offset of the healthbar.
     pub healthbar_offset: Vec2<f64>,
+    /// Size of the collision box standing on the feet.
+    pub collider: Extent2<f64>,
+    /// Health lost for every pixel per second of speed the dino hits the unit with.
+    pub impact_damage: f64,
+    /// Money earned when the dino knocks the unit over.
+    pub knock_over_money: usize,
+    /// Distance between units spawned ahead of the dino.
+    pub spawn_distance: RandomRangeF64,
+    /// Distance from the launch before the first unit is spawned.
+    pub min_spawn_distance: f64,
 }
 
 impl Asset for Settings {
@@ -216,7 +283,7 @@ impl Asset for Settings {
 }
 
 /// Player unit or enemy unit.
-#[derive(Debug, Clone, Copy, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Allegiance {
     /// Unit belongs to the player.
@@ -224,3 +291,105 @@ pub enum Allegiance {
     /// Unit is controlled by enemy AI.
     Enemy,
 }
+
+#[cfg(test)]
+mod tests {
+    use fastrand::Rng;
+    use vek::{Rect, Vec2};
+
+    use super::{Unit, UnitType};
+    use crate::{
+        assets::Assets, flight::Body, physics::Physics, terrain::Terrain, unit::Allegiance,
+    };
+
+    /// Flat launch area of the terrain.
+    fn terrain(assets: &Assets) -> Terrain {
+        Terrain::new(
+            &mut Rng::with_seed(0),
+            &assets.settings().terrain,
+            assets.surfaces(),
+        )
+    }
+
+    /// Units must fall onto the ground, walk on it and throw at a target in range.
+    #[test]
+    fn test_update() {
+        let assets = Assets::load();
+        let terrain = terrain(&assets);
+        let mut rng = Rng::with_seed(0);
+        let mut physics = Physics::new();
+        let settings = UnitType::EnemySpear.settings(&assets);
+        let dt = 1.0 / 60.0;
+
+        let mut unit = Unit::new(Vec2::new(200.0, -5.0), UnitType::EnemySpear, &assets);
+        let far_away = Body {
+            pos: Vec2::new(10000.0, 0.0),
+            vel: Vec2::zero(),
+        };
+        for _ in 0..4 {
+            assert!(unit
+                .update(&terrain, far_away, dt, &mut rng, &mut physics, &assets)
+                .is_none());
+        }
+        assert_eq!(unit.pos(), Vec2::new(200.0, -1.0));
+
+        unit.update(&terrain, far_away, dt, &mut rng, &mut physics, &assets);
+        assert_eq!(unit.pos(), Vec2::new(200.0 + settings.walk_speed * dt, 0.0));
+
+        let close = Body {
+            pos: Vec2::new(250.0, -50.0),
+            vel: Vec2::new(100.0, 0.0),
+        };
+        let projectile = (0..=(settings.projectile_spawn_interval / dt) as usize)
+            .find_map(|_| unit.update(&terrain, close, dt, &mut rng, &mut physics, &assets))
+            .unwrap();
+        assert_eq!(projectile.allegiance, Allegiance::Enemy);
+        assert!(projectile.body(&physics).vel.x > 0.0);
+    }
+
+    /// Hitting a unit must damage it once per pass and give money when it's knocked over.
+    #[test]
+    fn test_hit_by() {
+        let assets = Assets::load();
+        let settings = UnitType::EnemySpear.settings(&assets);
+
+        let mut unit = Unit::new(Vec2::new(100.0, 0.0), UnitType::EnemySpear, &assets);
+        let collider = unit.collider(&assets);
+        let away = Rect::new(0.0, 0.0, 1.0, 1.0);
+
+        // Too slow to knock it over
+        let slow = Vec2::new(settings.health / settings.impact_damage / 2.0, 0.0);
+        assert_eq!(unit.hit_by(collider, slow, &assets), 0);
+        let health = unit.health;
+        assert!((health - settings.health / 2.0).abs() < 0.001);
+
+        // Still touching from the same pass
+        assert_eq!(unit.hit_by(collider, slow * 10.0, &assets), 0);
+        assert_eq!(unit.health, health);
+
+        assert_eq!(unit.hit_by(away, slow, &assets), 0);
+        assert_eq!(
+            unit.hit_by(collider, slow * 2.0, &assets),
+            settings.knock_over_money
+        );
+        assert!(unit.is_knocked_over());
+    }
+
+    /// Damage must only give money once, when the unit gets knocked over.
+    #[test]
+    fn test_damage() {
+        let assets = Assets::load();
+        let settings = UnitType::EnemySpear.settings(&assets);
+
+        let mut unit = Unit::new(Vec2::zero(), UnitType::EnemySpear, &assets);
+        assert_eq!(unit.damage(settings.health / 2.0, &assets), 0);
+        assert!(!unit.is_knocked_over());
+
+        assert_eq!(
+            unit.damage(settings.health, &assets),
+            settings.knock_over_money
+        );
+        assert!(unit.is_knocked_over());
+        assert_eq!(unit.damage(settings.health, &assets), 0);
+    }
+}
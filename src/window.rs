@@ -52,6 +52,7 @@ where
         let surface_texture = SurfaceTexture::new(size.w as u32 * 2, size.h as u32 * 2, &window);
         PixelsBuilder::new(size.w as u32, size.h as u32, surface_texture)
             .clear_color(pixels::wgpu::Color::WHITE)
+            // Blending is done when drawing on the canvas, the frame is always opaque
             .blend_state(BlendState::REPLACE)
             .build_async()
             .await